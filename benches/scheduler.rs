@@ -0,0 +1,93 @@
+//! Compares the worker's schedulers, the binary heap and the timer wheel, on a large number of
+//! members expiring over an hour: `cargo bench --bench scheduler [-- members]`.
+
+#[allow(dead_code)]
+#[path = "../src/wheel.rs"]
+mod wheel;
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::{Duration, Instant};
+use wheel::TimerWheel;
+
+const SPAN_MS: u64 = 3_600_000;
+// The worker's cycle when members expire continuously.
+const STEP_MS: u64 = 100;
+
+/// Deadlines spread over the span, from a fixed xorshift sequence.
+fn deadlines(count: usize) -> Vec<u64> {
+    let mut state = 0x2545f4914f6cdd1du64;
+    (0..count).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        1 + state % SPAN_MS
+    }).collect()
+}
+
+trait Scheduler {
+    fn insert(&mut self, tick: u64, member: u64);
+    fn pop_due(&mut self, now: u64) -> Option<(u64, u64)>;
+}
+
+impl Scheduler for BinaryHeap<Reverse<(u64, u64)>> {
+    fn insert(&mut self, tick: u64, member: u64) {
+        self.push(Reverse((tick, member)));
+    }
+
+    fn pop_due(&mut self, now: u64) -> Option<(u64, u64)> {
+        match self.peek() {
+            Some(Reverse((tick, _))) if *tick <= now => self.pop().map(|Reverse(entry)| entry),
+            _ => None,
+        }
+    }
+}
+
+impl Scheduler for TimerWheel<u64> {
+    fn insert(&mut self, tick: u64, member: u64) {
+        TimerWheel::insert(self, tick, member);
+    }
+
+    fn pop_due(&mut self, now: u64) -> Option<(u64, u64)> {
+        TimerWheel::pop_due(self, now)
+    }
+}
+
+/// Schedules every member, then schedules as many again on top of them (reschedules leave the
+/// old entry behind in both), then expires everything a cycle at a time.
+fn run(name: &str, scheduler: &mut impl Scheduler, deadlines: &[u64]) {
+    let start = Instant::now();
+    for (member, tick) in deadlines.iter().enumerate() {
+        scheduler.insert(*tick, member as u64);
+    }
+    let fill = start.elapsed();
+
+    let start = Instant::now();
+    for (member, tick) in deadlines.iter().enumerate() {
+        scheduler.insert(*tick, member as u64);
+    }
+    let reschedule = start.elapsed();
+
+    let start = Instant::now();
+    let mut expired = 0;
+    for now in (0..=SPAN_MS).step_by(STEP_MS as usize) {
+        while let Some((tick, _)) = scheduler.pop_due(now) {
+            assert!(tick <= now && tick + STEP_MS > now, "{} expired a member at {} due at {}", name, now, tick);
+            expired += 1;
+        }
+    }
+    let expire = start.elapsed();
+    assert_eq!(expired, deadlines.len() * 2, "{} should expire every member", name);
+
+    let per_member = |elapsed: Duration| elapsed.as_nanos() as f64 / deadlines.len() as f64;
+    println!("{:<6} fill {:>9.1?} ({:>6.1} ns/member)  reschedule {:>9.1?} ({:>6.1} ns/member)  expire {:>9.1?} ({:>6.1} ns/member)",
+        name, fill, per_member(fill), reschedule, per_member(reschedule), expire, per_member(expire / 2));
+}
+
+fn main() {
+    let members = std::env::args().skip(1).find_map(|arg| arg.parse().ok()).unwrap_or(2_000_000);
+    let deadlines = deadlines(members);
+    println!("{} members expiring over {}s", members, SPAN_MS / 1000);
+    run("heap", &mut BinaryHeap::new(), &deadlines);
+    run("wheel", &mut TimerWheel::new(), &deadlines);
+}
@@ -0,0 +1,33 @@
+//! The `expiremember` ACL category, holding every command of the module, so access can be
+//! granted or denied as a whole with `+@expiremember` / `-@expiremember`. The built-in
+//! categories (`@write`, `@read`, `@fast`, `@admin`, ...) follow from the command flags.
+
+use redis_module::{raw, Context};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+
+use crate::commands::{self, COMMANDS};
+use crate::server;
+
+const CATEGORY: &str = "expiremember";
+
+type AddAclCategory = unsafe extern "C" fn(*mut raw::RedisModuleCtx, *const c_char) -> c_int;
+
+/// Adds the category and puts every command in it, only possible while loading. False if the
+/// server does not support module ACL categories (Redis 7.4+).
+pub fn register(ctx: &Context) -> bool {
+    let category = CString::new(CATEGORY).unwrap();
+    let add_category = unsafe { server::api::<AddAclCategory>("AddACLCategory") };
+    let (Some(add_category), Some(set_categories)) = (add_category, unsafe { raw::RedisModule_SetCommandACLCategories }) else {
+        return false;
+    };
+    if unsafe { add_category(ctx.ctx, category.as_ptr()) } != raw::REDISMODULE_OK as c_int {
+        return false;
+    }
+
+    COMMANDS.iter().all(|command| {
+        let name = CString::new(commands::name(command.name)).unwrap();
+        let command = unsafe { raw::RedisModule_GetCommand.unwrap()(ctx.ctx, name.as_ptr()) };
+        !command.is_null() && unsafe { set_categories(command, category.as_ptr()) } == raw::REDISMODULE_OK as c_int
+    })
+}
@@ -0,0 +1,49 @@
+//! Module arguments, `--loadmodule <path> name value ...`. Each name is one of the module's
+//! configurations, set before this runs, or a shorter alias of a main tuning knob. Anything else
+//! fails the load, rather than a typo going unnoticed until the setting is missed.
+//!
+//! Like the module arguments named after configurations, aliases only stand for the default: a
+//! value from the configuration file, e.g. written by `CONFIG REWRITE`, is kept.
+
+use redis_module::{Context, RedisString};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use crate::{config_get, EXPIRE_THREADS, QUEUE_DRAIN_THRESHOLD, WORKER_INTERVAL};
+
+/// The configuration `alias` stands for, with its value, default and range.
+fn alias(alias: &str) -> Option<(&'static str, &'static AtomicI64, i64, RangeInclusive<i64>)> {
+    match alias {
+        "interval-ms" => Some(("worker-interval", &WORKER_INTERVAL, 100, 1..=10_000)),
+        "queue-size" => Some(("queue-drain-threshold", &QUEUE_DRAIN_THRESHOLD, 5000, 1..=10_000_000)),
+        "workers" => Some(("expire-threads", &EXPIRE_THREADS, 1, 1..=64)),
+        _ => None,
+    }
+}
+
+/// Validates the module arguments and applies the aliases among them.
+pub fn apply(ctx: &Context, args: &[RedisString]) -> Result<(), String> {
+    if !args.len().is_multiple_of(2) {
+        return Err(format!("Module arguments should be name value pairs, '{}' has no value", args[args.len() - 1]));
+    }
+    for pair in args.chunks(2) {
+        let (name, value) = (pair[0].to_string(), pair[1].to_string());
+        if let Some((config, target, default, range)) = alias(&name) {
+            let parsed = value.parse::<i64>().ok().filter(|parsed| range.contains(parsed)).ok_or_else(|| format!(
+                "Invalid value '{}' for module argument '{}', expected an integer from {} to {}", value, name, range.start(), range.end()
+            ))?;
+            if target.load(Ordering::Relaxed) != default {
+                ctx.log_notice(&format!("Module argument '{}' ignored, expiremember.{} is set already, by name or in the configuration file", name, config));
+                continue;
+            }
+            target.store(parsed, Ordering::Relaxed);
+            ctx.log_notice(&format!("Module argument '{}' set expiremember.{} to {}", name, config, parsed));
+            continue;
+        }
+        let pattern = name.contains(['*', '?', '[']);
+        if pattern || config_get(ctx, &format!("expiremember.{}", name)).is_none() {
+            return Err(format!("Unknown module argument '{}'", name));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! EXPIREMEMBER.CHECK: verifies the tracked expirations against the keyspace and the worker's heap.
+
+use lazy_static::lazy_static;
+use redis_module::{BlockedClient, Context, ContextFlags, RedisResult, RedisValue, ThreadSafeContext};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::gc::{self, Orphan};
+use crate::schedule::Schedule;
+use crate::{rebuild_heap, ExpiringMember, EXPIRATION_TIMES, WORKER_WAKEUP};
+
+/// A check waiting for the worker to audit its heap.
+struct PendingCheck {
+    report: Vec<(&'static str, i64)>,
+    repaired: i64,
+    repair: bool,
+    client: BlockedClient,
+}
+
+lazy_static! {
+    static ref PENDING_CHECKS: Mutex<Vec<PendingCheck>> = Mutex::new(Vec::new());
+}
+
+/// Checks the tracked expirations on the main thread, then leaves the heap to the worker,
+/// which replies. Must hold the GIL.
+pub fn start(ctx: &Context, repair: bool) -> RedisResult {
+    let all: Vec<ExpiringMember> = EXPIRATION_TIMES.values();
+    let tracked = all.len();
+    let orphans = gc::find_orphans(ctx, all);
+    let missing_keys = orphans.iter().filter(|(_, orphan)| *orphan == Orphan::MissingKey).count();
+    let missing_members = orphans.len() - missing_keys;
+    let index_errors = EXPIRATION_TIMES.index_errors();
+
+    let mut repaired = 0;
+    if repair {
+        repaired += gc::forget_orphans(ctx, orphans.into_iter().map(|(orphan, _)| orphan).collect());
+        if index_errors > 0 {
+            EXPIRATION_TIMES.rebuild_index();
+            repaired += index_errors;
+        }
+    }
+
+    let report = vec![
+        ("tracked", tracked as i64),
+        ("missing_keys", missing_keys as i64),
+        ("missing_members", missing_members as i64),
+        ("index_errors", index_errors as i64),
+    ];
+    // A blocked reply is not allowed everywhere.
+    let blocking_denied = ctx.get_flags().intersects(ContextFlags::MULTI | ContextFlags::LUA | ContextFlags::DENY_BLOCKING);
+    if blocking_denied {
+        return Ok(reply(report, None, repaired as i64));
+    }
+
+    PENDING_CHECKS.lock().unwrap().push(PendingCheck { report, repaired: repaired as i64, repair, client: ctx.block_client() });
+    WORKER_WAKEUP.notify();
+    Ok(RedisValue::NoReply)
+}
+
+/// Audits the worker's schedule for the pending checks and replies to them. Called by the worker.
+pub fn audit_heap(heap: &Schedule) {
+    let checks = std::mem::take(&mut *PENDING_CHECKS.lock().unwrap());
+    if checks.is_empty() {
+        return;
+    }
+
+    let mut scheduled = HashSet::with_capacity(heap.len());
+    let mut duplicates = 0;
+    let mut stale = 0;
+    for member in heap.iter() {
+        if !scheduled.insert((member.db, &member.key, &member.member, member.generation)) {
+            duplicates += 1;
+        } else if !EXPIRATION_TIMES.is_current(member) {
+            stale += 1;
+        }
+    }
+    let unscheduled: usize = EXPIRATION_TIMES.shards()
+        .map(|shard| shard.values()
+            .filter(|tracked| !scheduled.contains(&(tracked.db, &tracked.key, &tracked.member, tracked.generation)))
+            .count())
+        .sum();
+
+    let heap_report = [
+        ("heap_size", heap.len() as i64),
+        ("heap_duplicates", duplicates),
+        ("heap_stale", stale),
+        ("unscheduled", unscheduled as i64),
+    ];
+    let heap_errors = duplicates + stale + unscheduled as i64;
+    for check in checks {
+        if check.repair && heap_errors > 0 {
+            rebuild_heap();
+        }
+        let repaired = check.repaired + if check.repair { heap_errors } else { 0 };
+        let thread_ctx = ThreadSafeContext::with_blocked_client(check.client);
+        thread_ctx.reply(Ok(reply(check.report, Some(&heap_report), repaired)));
+    }
+}
+
+/// `[name, value, ...]`, with null heap fields when the heap was not audited.
+fn reply(report: Vec<(&'static str, i64)>, heap_report: Option<&[(&'static str, i64)]>, repaired: i64) -> RedisValue {
+    let heap_report: Vec<(&'static str, RedisValue)> = match heap_report {
+        Some(heap_report) => heap_report.iter().map(|(name, value)| (*name, RedisValue::Integer(*value))).collect(),
+        None => ["heap_size", "heap_duplicates", "heap_stale", "unscheduled"].map(|name| (name, RedisValue::Null)).to_vec(),
+    };
+    RedisValue::Array(report.into_iter()
+        .map(|(name, value)| (name, RedisValue::Integer(value)))
+        .chain(heap_report)
+        .chain([("repaired", RedisValue::Integer(repaired))])
+        .flat_map(|(name, value)| [RedisValue::SimpleStringStatic(name), value])
+        .collect())
+}
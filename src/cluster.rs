@@ -0,0 +1,64 @@
+//! Slot states in cluster mode, so the worker only deletes members of keys this node serves.
+
+use redis_module::{Context, ContextFlags, RedisValue};
+
+const SLOTS: usize = 16384;
+
+/// Hash slot of `key`, honoring `{hash tags}` like Redis Cluster does.
+pub fn key_slot(key: &str) -> usize {
+    crc16(hash_tag(key).unwrap_or(key).as_bytes()) as usize % SLOTS
+}
+
+/// The non-empty content of the first `{...}` of `key`, which alone determines its slot.
+pub fn hash_tag(key: &str) -> Option<&str> {
+    let (_, rest) = key.split_once('{')?;
+    let (tag, _) = rest.split_once('}')?;
+    (!tag.is_empty()).then_some(tag)
+}
+
+/// Slots this node serves as a primary, and those of them being migrated to another node.
+pub struct SlotStates {
+    pub owned: Vec<bool>,
+    pub migrating: Vec<bool>,
+}
+
+/// The slot states of this node from `CLUSTER NODES`, or None when not running in cluster mode.
+pub fn slot_states(ctx: &Context) -> Option<SlotStates> {
+    if !ctx.get_flags().contains(ContextFlags::CLUSTER) {
+        return None;
+    }
+    let mut states = SlotStates { owned: vec![false; SLOTS], migrating: vec![false; SLOTS] };
+    let nodes = match ctx.call("CLUSTER", &["NODES"]) {
+        Ok(RedisValue::SimpleString(nodes)) | Ok(RedisValue::BulkString(nodes)) => nodes,
+        _ => return Some(states),
+    };
+    // <id> <addr> <flags> <primary> <ping> <pong> <epoch> <link> <slot>...
+    let Some(myself) = nodes.lines().find(|line| line.split(' ').nth(2).is_some_and(|flags| flags.contains("myself"))) else {
+        return Some(states);
+    };
+    if !myself.split(' ').nth(2).is_some_and(|flags| flags.contains("master")) {
+        return Some(states);
+    }
+    for slots in myself.split(' ').skip(8) {
+        if let Some(migrating) = slots.strip_prefix('[').and_then(|slots| slots.split_once("->-")) {
+            if let Ok(slot) = migrating.0.parse::<usize>() {
+                states.migrating[slot.min(SLOTS - 1)] = true;
+            }
+        } else if !slots.starts_with('[') {
+            let (start, end) = slots.split_once('-').unwrap_or((slots, slots));
+            if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                states.owned[start.min(SLOTS - 1)..=end.min(SLOTS - 1)].fill(true);
+            }
+        }
+    }
+    Some(states)
+}
+
+/// CRC16-CCITT (XMODEM), the checksum Redis Cluster derives slots from.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 }
+        })
+    })
+}
@@ -0,0 +1,85 @@
+//! The module's commands, registered when loading under their names prefixed with
+//! `expiremember.command-prefix`, so they can live next to another EXPIREMEMBER, such as a
+//! KeyDB-compatible fork's or another module's. Commands the module replicates, writes to the
+//! AOF or sends to another instance carry the prefix as well. Those of a feature compiled out
+//! are not registered at all.
+
+use redis_module::{decode_args, raw, Context, RedisResult};
+use std::ffi::CString;
+use std::os::raw::c_int;
+
+use crate::COMMAND_PREFIX;
+
+type Trampoline = extern "C" fn(*mut raw::RedisModuleCtx, *mut *mut raw::RedisModuleString, c_int) -> c_int;
+
+pub struct Command {
+    pub name: &'static str,
+    trampoline: Trampoline,
+    flags: &'static str,
+    // First key, last key and key step.
+    keys: (c_int, c_int, c_int),
+}
+
+macro_rules! commands {
+    ($($(#[$meta:meta])* [$name:expr, $handler:path, $flags:expr, $first:expr, $last:expr, $step:expr]),* $(,)?) => {
+        pub const COMMANDS: &[Command] = &[$($(#[$meta])* {
+            extern "C" fn trampoline(ctx: *mut raw::RedisModuleCtx, argv: *mut *mut raw::RedisModuleString, argc: c_int) -> c_int {
+                let context = Context::new(ctx);
+                let args = decode_args(ctx, argv, argc);
+                let response: RedisResult = $handler(&context, args);
+                context.reply(response) as c_int
+            }
+            Command { name: $name, trampoline, flags: $flags, keys: ($first, $last, $step) }
+        }),*];
+    };
+}
+
+commands! {
+    ["expiremember", crate::expiremember, "write fast deny-oom", 1, 1, 1],
+    ["expirememberat", crate::expirememberat, "write fast deny-oom", 1, 1, 1],
+    ["pexpirememberat", crate::pexpirememberat, "write fast deny-oom", 1, 1, 1],
+    #[cfg(feature = "notifications")]
+    ["expiremember.subscribe", crate::expiremember_subscribe, "readonly blocking", 0, 0, 0],
+    ["expiremember.policy", crate::expiremember_policy, "write deny-oom", 1, 1, 1],
+    ["expiremember.dump", crate::expiremember_dump, "readonly", 1, 1, 1],
+    ["expiremember.dumpslot", crate::expiremember_dumpslot, "readonly", 0, 0, 0],
+    ["expiremember.restore", crate::expiremember_restore, "write deny-oom", 1, 1, 1],
+    ["expiremember.export", crate::expiremember_export, "admin deny-script", 0, 0, 0],
+    ["expiremember.import", crate::expiremember_import, "admin write deny-oom deny-script", 0, 0, 0],
+    ["expiremember.migrate", crate::expiremember_migrate, "admin deny-script", 0, 0, 0],
+    ["expiremember.sync", crate::expiremember_sync, "write", 1, 1, 1],
+    ["expiremember.flushall", crate::expiremember_flushall, "admin write deny-script", 0, 0, 0],
+    ["expiremember.check", crate::expiremember_check, "admin blocking", 0, 0, 0],
+    ["expiremember.stats", crate::expiremember_stats, "readonly fast", 0, 0, 0],
+    #[cfg(feature = "debug")]
+    ["expiremember.info", crate::expiremember_info, "readonly", 0, 0, 0],
+    #[cfg(feature = "debug")]
+    ["expiremember.debug", crate::expiremember_debug, "admin blocking deny-script", 0, 0, 0],
+    ["expiremember.metrics", crate::expiremember_metrics, "readonly", 0, 0, 0],
+    ["expiremember.memory", crate::expiremember_memory, "readonly", 0, 0, 0],
+    #[cfg(feature = "rules")]
+    ["expiremember.reloadconf", crate::expiremember_reloadconf, "admin deny-script", 0, 0, 0],
+}
+
+/// The name `command` is registered under, with the prefix.
+pub fn name(command: &str) -> String {
+    format!("{}{}", COMMAND_PREFIX.lock().unwrap(), command)
+}
+
+/// Creates every command, only possible while loading. Fails naming the first that could not
+/// be, typically because the name is taken.
+pub fn register(ctx: &Context) -> Result<(), String> {
+    for command in COMMANDS {
+        let name = name(command.name);
+        let c_name = CString::new(name.as_str()).unwrap();
+        let flags = CString::new(command.flags).unwrap();
+        let (first, last, step) = command.keys;
+        let status = unsafe {
+            raw::RedisModule_CreateCommand.unwrap()(ctx.ctx, c_name.as_ptr(), Some(command.trampoline), flags.as_ptr(), first, last, step)
+        };
+        if status != raw::REDISMODULE_OK as c_int {
+            return Err(format!("Cannot register the {} command, the name may be taken, see expiremember.command-prefix", name));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Checks of configuration values, run when they are set so that `CONFIG SET` rejects a clearly
+//! invalid value, or combination of values, rather than the module going on with it. What was
+//! accepted is written back by `CONFIG REWRITE` like any server setting.
+//!
+//! Values from the configuration file are set one by one in its order, so combinations are only
+//! checked once they are all loaded, see `loaded`.
+
+use redis_module::configuration::{ConfigurationContext, ConfigurationValue};
+use redis_module::{RedisError, RedisString};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::notify::EventClass;
+use crate::{MAX_ENTRIES, MAX_MEMBERS_PER_KEY};
+
+static LOADED: AtomicBool = AtomicBool::new(false);
+
+/// A configuration whose new values go through `check` first, the error replied to `CONFIG SET`.
+pub struct Checked<T, V> {
+    value: T,
+    check: fn(&V) -> Result<(), String>,
+}
+
+impl<T, V> Checked<T, V> {
+    pub fn new(value: T, check: fn(&V) -> Result<(), String>) -> Self {
+        Checked { value, check }
+    }
+}
+
+impl<T, V> Deref for Checked<T, V> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: ConfigurationValue<V>, V> ConfigurationValue<V> for Checked<T, V> {
+    fn get(&self, ctx: &ConfigurationContext) -> V {
+        self.value.get(ctx)
+    }
+
+    fn set(&self, ctx: &ConfigurationContext, val: V) -> Result<(), RedisError> {
+        (self.check)(&val).map_err(RedisError::String)?;
+        self.value.set(ctx, val)
+    }
+}
+
+/// Turns on the checks of combinations, once the configuration file is loaded. Fails with the
+/// first invalid combination it holds.
+pub fn loaded() -> Result<(), String> {
+    LOADED.store(true, Ordering::Relaxed);
+    check_member_caps(MAX_ENTRIES.load(Ordering::Relaxed), MAX_MEMBERS_PER_KEY.load(Ordering::Relaxed))
+}
+
+/// Whether the configuration file is loaded.
+pub fn is_loaded() -> bool {
+    LOADED.load(Ordering::Relaxed)
+}
+
+fn check_member_caps(max_entries: i64, max_members_per_key: i64) -> Result<(), String> {
+    if max_entries > 0 && max_members_per_key > max_entries {
+        return Err(format!("max-members-per-key ({}) cannot exceed max-entries ({})", max_members_per_key, max_entries));
+    }
+    Ok(())
+}
+
+pub fn check_max_entries(max_entries: &i64) -> Result<(), String> {
+    match LOADED.load(Ordering::Relaxed) {
+        true => check_member_caps(*max_entries, MAX_MEMBERS_PER_KEY.load(Ordering::Relaxed)),
+        false => Ok(()),
+    }
+}
+
+pub fn check_max_members_per_key(max_members_per_key: &i64) -> Result<(), String> {
+    match LOADED.load(Ordering::Relaxed) {
+        true => check_member_caps(MAX_ENTRIES.load(Ordering::Relaxed), *max_members_per_key),
+        false => Ok(()),
+    }
+}
+
+/// A SHA1 as returned by `SCRIPT LOAD`, or empty.
+pub fn check_script_sha(sha: &RedisString) -> Result<(), String> {
+    let sha = sha.try_as_str().map_err(|_| "expire-script-sha must be a SHA1 in hex".to_string())?;
+    if !sha.is_empty() && (sha.len() != 40 || !sha.bytes().all(|byte| byte.is_ascii_hexdigit())) {
+        return Err(format!("expire-script-sha must be a SHA1 in hex, as returned by SCRIPT LOAD, not '{}'", sha));
+    }
+    Ok(())
+}
+
+/// Letters, digits and punctuation, so the names stay single arguments.
+pub fn check_command_prefix(prefix: &RedisString) -> Result<(), String> {
+    match prefix.try_as_str() {
+        Ok(prefix) if prefix.bytes().all(|byte| byte.is_ascii_graphic()) => Ok(()),
+        _ => Err("command-prefix can only hold letters, digits and punctuation".to_string()),
+    }
+}
+
+/// A `host:port`, or empty. The host is only resolved by the worker, it may not be yet.
+pub fn check_statsd_address(address: &RedisString) -> Result<(), String> {
+    let address = address.try_as_str().map_err(|_| "statsd-address must be a host:port".to_string())?;
+    if address.is_empty() {
+        return Ok(());
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok_and(|port| port > 0) => Ok(()),
+        _ => Err(format!("statsd-address must be a host:port, not '{}'", address)),
+    }
+}
+
+/// Settings of a feature compiled out are refused, other than their default, see Cargo.toml.
+fn requires(name: &str, feature: &str, compiled_in: bool, is_default: bool) -> Result<(), String> {
+    match compiled_in || is_default {
+        true => Ok(()),
+        false => Err(format!("{} needs the module built with the {} feature", name, feature)),
+    }
+}
+
+pub fn check_events_channel(channel: &RedisString) -> Result<(), String> {
+    requires("events-channel", "notifications", cfg!(feature = "notifications"), channel.is_empty())
+}
+
+pub fn check_events_stream(stream: &RedisString) -> Result<(), String> {
+    requires("events-stream", "notifications", cfg!(feature = "notifications"), stream.is_empty())
+}
+
+pub fn check_event_log_size(size: &i64) -> Result<(), String> {
+    requires("event-log-size", "notifications", cfg!(feature = "notifications"), *size == 0)
+}
+
+pub fn check_keyspace_event_class(class: &EventClass) -> Result<(), String> {
+    requires("keyspace-event-class", "notifications", cfg!(feature = "notifications"), *class == EventClass::none)
+}
+
+/// A rules file that parses, or empty.
+pub fn check_config_file(path: &RedisString) -> Result<(), String> {
+    requires("config-file", "rules", cfg!(feature = "rules"), path.is_empty())?;
+    #[cfg(feature = "rules")]
+    crate::rules::check_config_file(path)?;
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use redis_module::{error::Error, key::RedisKey, native_types::RedisType, raw, Context, RedisString};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem::{size_of, ManuallyDrop};
+use std::os::raw::{c_char, c_int, c_longlong, c_void};
+use std::time::Duration;
+
+use crate::shadow::{shadow_key_name, tracked_key_name};
+use crate::deadline::Deadline;
+use crate::{commands, rebuild_heap, ExpiringMember, EXPIRATION_TIMES};
+
+const ENCODING_VERSION: i32 = 1;
+
+/// Value of a shadow key when `backend` is `datatype`: the expirations of one tracked key.
+pub static MEMBER_TTL_TYPE: RedisType = RedisType::new(
+    "memberttl",
+    ENCODING_VERSION,
+    raw::RedisModuleTypeMethods {
+        version: raw::REDISMODULE_TYPE_METHOD_VERSION as u64,
+        rdb_load: Some(rdb_load),
+        rdb_save: Some(rdb_save),
+        aof_rewrite: Some(aof_rewrite),
+        free: Some(free),
+
+        mem_usage: Some(mem_usage),
+        digest: None,
+
+        aux_load: None,
+        aux_save: None,
+        aux_save2: None,
+        aux_save_triggers: 0,
+
+        free_effort: None,
+        unlink: None,
+        copy: None,
+        defrag: Some(defrag),
+
+        copy2: None,
+        free_effort2: None,
+        mem_usage2: None,
+        unlink2: None,
+    },
+);
+
+/// Member → unix ms deadline.
+#[derive(Default)]
+pub struct MemberTtls {
+    members: HashMap<String, u64>,
+}
+
+/// Records the expiration in the shadow key of `member.key`, creating it if needed.
+pub fn store(ctx: &Context, member: &ExpiringMember) {
+    let shadow = ctx.create_string(shadow_key_name(&member.key).as_bytes());
+    let key = ctx.open_key_writable(&shadow);
+    let deadline = member.expire_at.unix_ms();
+    match key.get_value::<MemberTtls>(&MEMBER_TTL_TYPE) {
+        Ok(Some(ttls)) => {
+            ttls.members.insert(member.member.to_string(), deadline);
+        }
+        Ok(None) => {
+            let mut ttls = MemberTtls::default();
+            ttls.members.insert(member.member.to_string(), deadline);
+            let _ = key.set_value(&MEMBER_TTL_TYPE, ttls);
+        }
+        // The shadow name is taken by a key of another type, leave it alone.
+        Err(_) => {}
+    }
+}
+
+/// Drops `member` from the shadow key of `key`, deleting the shadow once it is empty.
+pub fn forget(ctx: &Context, key: &str, member: &str) {
+    let shadow = ctx.create_string(shadow_key_name(key).as_bytes());
+    let key = ctx.open_key_writable(&shadow);
+    if let Ok(Some(ttls)) = key.get_value::<MemberTtls>(&MEMBER_TTL_TYPE) {
+        ttls.members.remove(member);
+        if ttls.members.is_empty() {
+            let _ = key.delete();
+        }
+    }
+}
+
+/// The (member, unix ms deadline) pairs of a shadow key, None if it is not a `memberttl` key.
+pub fn members(key: &RedisKey) -> Option<Vec<(String, u64)>> {
+    let ttls = key.get_value::<MemberTtls>(&MEMBER_TTL_TYPE).ok()??;
+    Some(ttls.members.iter().map(|(member, deadline)| (member.clone(), *deadline)).collect())
+}
+
+unsafe extern "C" fn rdb_save(rdb: *mut raw::RedisModuleIO, value: *mut c_void) {
+    let ttls = &*value.cast::<MemberTtls>();
+    raw::save_unsigned(rdb, ttls.members.len() as u64);
+    for (member, deadline) in &ttls.members {
+        raw::save_string(rdb, member);
+        raw::save_unsigned(rdb, *deadline);
+    }
+}
+
+/// Loads a shadow key and re-tracks its expirations, so they survive DEBUG RELOAD,
+/// restarts and full syncs without relying on the aux field.
+unsafe extern "C" fn rdb_load(rdb: *mut raw::RedisModuleIO, encver: c_int) -> *mut c_void {
+    if encver > ENCODING_VERSION {
+        return std::ptr::null_mut();
+    }
+    let ttls = match load_ttls(rdb) {
+        Ok(ttls) => ttls,
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    let shadow = RedisString::from_ptr(raw::RedisModule_GetKeyNameFromIO.unwrap()(rdb)).unwrap_or_default();
+    if let Some(key) = tracked_key_name(shadow) {
+        let db = raw::RedisModule_GetDbIdFromIO.unwrap()(rdb);
+        for (member, deadline) in &ttls.members {
+            let expire_at = Deadline::from_unix(Duration::from_millis(*deadline));
+            EXPIRATION_TIMES.insert(ExpiringMember::new(db, key.to_string(), member.clone(), expire_at));
+        }
+
+        rebuild_heap();
+    }
+
+    Box::into_raw(Box::new(ttls)).cast::<c_void>()
+}
+
+fn load_ttls(rdb: *mut raw::RedisModuleIO) -> Result<MemberTtls, Error> {
+    let count = raw::load_unsigned(rdb)?;
+    let mut members = HashMap::with_capacity(count as usize);
+    for _ in 0..count {
+        let member = raw::load_string(rdb)?.to_string();
+        members.insert(member, raw::load_unsigned(rdb)?);
+    }
+    Ok(MemberTtls { members })
+}
+
+/// Rewrites a shadow key as one `EXPIREMEMBERAT` per member, which recreates it on replay.
+unsafe extern "C" fn aof_rewrite(aof: *mut raw::RedisModuleIO, key: *mut raw::RedisModuleString, value: *mut c_void) {
+    let ttls = &*value.cast::<MemberTtls>();
+    let shadow = RedisString::from_ptr(key).unwrap_or_default();
+    let Some(key) = tracked_key_name(shadow) else {
+        return;
+    };
+
+    let command = CString::new(commands::name("EXPIREMEMBERAT")).unwrap();
+    let format = CString::new("bblc").unwrap();
+    let unit = CString::new("ms").unwrap();
+    for (member, deadline) in &ttls.members {
+        raw::RedisModule_EmitAOF.unwrap()(
+            aof,
+            command.as_ptr(),
+            format.as_ptr(),
+            key.as_ptr().cast::<c_char>(),
+            key.len(),
+            member.as_ptr().cast::<c_char>(),
+            member.len(),
+            *deadline as c_longlong,
+            unit.as_ptr(),
+        );
+    }
+}
+
+unsafe extern "C" fn free(value: *mut c_void) {
+    drop(Box::from_raw(value.cast::<MemberTtls>()));
+}
+
+/// Bytes taken by a shadow key's value, for `MEMORY USAGE`: the value, its table counted at
+/// capacity with a control byte per slot, and the member names.
+unsafe extern "C" fn mem_usage(value: *const c_void) -> usize {
+    let ttls = &*value.cast::<MemberTtls>();
+    size_of::<MemberTtls>()
+        + ttls.members.capacity() * (size_of::<(String, u64)>() + 1)
+        + ttls.members.keys().map(String::capacity).sum::<usize>()
+}
+
+/// Moves the allocations of a shadow key where active defragmentation asks to: the value, the
+/// member names, and the table, rebuilt around them. Module allocations all go through the
+/// server's allocator, so it can move any of them. Done in one go, a shadow key holding the
+/// expirations of a single key.
+unsafe extern "C" fn defrag(ctx: *mut raw::RedisModuleDefragCtx, _key: *mut raw::RedisModuleString, value: *mut *mut c_void) -> c_int {
+    let Some(defrag_alloc) = raw::RedisModule_DefragAlloc else {
+        return 0;
+    };
+    let moved = defrag_alloc(ctx, *value);
+    if !moved.is_null() {
+        *value = moved;
+    }
+    let ttls = &mut *(*value).cast::<MemberTtls>();
+    ttls.members = std::mem::take(&mut ttls.members).into_iter()
+        .map(|(member, deadline)| {
+            if member.capacity() == 0 {
+                return (member, deadline);
+            }
+            let mut member = ManuallyDrop::new(member);
+            let moved = defrag_alloc(ctx, member.as_mut_ptr().cast::<c_void>());
+            let member = match moved.is_null() {
+                true => ManuallyDrop::into_inner(member),
+                // The old buffer was freed by the move.
+                false => String::from_raw_parts(moved.cast::<u8>(), member.len(), member.capacity()),
+            };
+            (member, deadline)
+        })
+        .collect();
+    0
+}
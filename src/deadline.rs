@@ -0,0 +1,121 @@
+//! Deadlines on the monotonic clock, so stepping the system clock neither delays nor hastens
+//! expirations. Unix times are only used at the edges: AT-style commands, persistence,
+//! replication and events.
+//!
+//! Both clocks can be moved ahead with EXPIREMEMBER.DEBUG JUMPTIME, for tests that would
+//! otherwise wait for members to fall due.
+
+use redis_module::raw;
+use std::ops::Add;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How far ahead a deadline may fall, about a century, so it never overflows the clock.
+pub const MAX_AHEAD: Duration = Duration::from_secs(100 * 365 * 86_400);
+
+/// How far the module's clocks are ahead of the real ones, in ms.
+static OFFSET_MS: AtomicU64 = AtomicU64::new(0);
+
+fn offset() -> Duration {
+    Duration::from_millis(OFFSET_MS.load(Ordering::SeqCst))
+}
+
+fn instant_now() -> Instant {
+    Instant::now() + offset()
+}
+
+fn system_now() -> SystemTime {
+    SystemTime::now() + offset()
+}
+
+/// Moves the module's clocks `by` ahead, returning how far ahead they are now, None if that
+/// would be more than `MAX_AHEAD`. Members whose deadlines are jumped over are due at once.
+#[cfg(feature = "debug")]
+pub fn jump(by: Duration) -> Option<Duration> {
+    let by = by.as_millis() as u64;
+    OFFSET_MS.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |offset| {
+        offset.checked_add(by).filter(|ahead| Duration::from_millis(*ahead) <= MAX_AHEAD)
+    }).ok().map(|offset| Duration::from_millis(offset + by))
+}
+
+/// How far the module's clocks are ahead of the real ones.
+#[cfg(feature = "debug")]
+pub fn jumped() -> Duration {
+    offset()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn now() -> Self {
+        Deadline(instant_now())
+    }
+
+    /// When the running command started, or its transaction or script, which see the same time
+    /// throughout as they do for key expirations. Now on servers without the cached time.
+    pub fn command_start() -> Self {
+        match unsafe { raw::RedisModule_CachedMicroseconds } {
+            Some(cached_microseconds) => Deadline::from_unix(Duration::from_micros(unsafe { cached_microseconds() } as u64) + offset()),
+            None => Deadline::now(),
+        }
+    }
+
+    /// `ttl` from now, at most `MAX_AHEAD`.
+    pub fn after(ttl: Duration) -> Self {
+        Deadline(instant_now() + ttl.min(MAX_AHEAD))
+    }
+
+    /// `ttl` after this deadline, None if more than `MAX_AHEAD` from now.
+    pub fn checked_add(self, ttl: Duration) -> Option<Self> {
+        let at = self.0.checked_add(ttl)?;
+        (at.saturating_duration_since(instant_now()) <= MAX_AHEAD).then_some(Deadline(at))
+    }
+
+    /// The Unix time `since_epoch`, as far from now as the system clock says it is. Deadlines
+    /// further in the past than the monotonic clock goes back are clamped to now, those more
+    /// than `MAX_AHEAD` ahead to that.
+    pub fn from_unix(since_epoch: Duration) -> Self {
+        let (now, system_now) = (instant_now(), system_now());
+        match UNIX_EPOCH.checked_add(since_epoch).map(|at| at.duration_since(system_now)) {
+            Some(Ok(ahead)) => Deadline(now + ahead.min(MAX_AHEAD)),
+            Some(Err(behind)) => Deadline(now.checked_sub(behind.duration()).unwrap_or(now)),
+            None => Deadline(now + MAX_AHEAD),
+        }
+    }
+
+    /// Like `from_unix`, but None for a time more than `MAX_AHEAD` ahead.
+    pub fn checked_from_unix(since_epoch: Duration) -> Option<Self> {
+        let ahead = UNIX_EPOCH.checked_add(since_epoch)?.duration_since(system_now()).unwrap_or_default();
+        (ahead <= MAX_AHEAD).then(|| Deadline::from_unix(since_epoch))
+    }
+
+    /// How long after `origin` the deadline falls, zero if before it.
+    pub fn since(self, origin: Deadline) -> Duration {
+        self.0.saturating_duration_since(origin.0)
+    }
+
+    /// How long until the deadline, zero once passed.
+    pub fn remaining(self) -> Duration {
+        self.0.saturating_duration_since(instant_now())
+    }
+
+    /// The Unix time in ms the deadline falls on by the system clock now.
+    pub fn unix_ms(self) -> u64 {
+        let (now, system_now) = (instant_now(), system_now());
+        let at = match self.0.checked_duration_since(now) {
+            Some(ahead) => system_now + ahead,
+            None => system_now - now.duration_since(self.0),
+        };
+        at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+    }
+}
+
+impl Add<Duration> for Deadline {
+    type Output = Deadline;
+
+    /// Clamped to `MAX_AHEAD`, see `checked_add` for a deadline that may not be.
+    fn add(self, ttl: Duration) -> Deadline {
+        Deadline(self.0 + ttl.min(MAX_AHEAD))
+    }
+}
@@ -0,0 +1,66 @@
+//! EXPIREMEMBER.DEBUG: switches for test suites and incident response, with the `debug` feature.
+//! Like the server's own DEBUG, they act on this instance only and are neither replicated nor
+//! persisted.
+
+use lazy_static::lazy_static;
+use redis_module::{BlockedClient, Context, ContextFlags, RedisError, RedisResult, RedisString, RedisValue, ThreadSafeContext};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{deadline, EXPIRY_PAUSED, WORKER_WAKEUP};
+
+lazy_static! {
+    // Calls of CYCLE waiting for the worker's next cycle.
+    static ref PENDING_CYCLES: Mutex<Vec<BlockedClient>> = Mutex::new(Vec::new());
+}
+
+/// Runs the subcommand in `args`, the command name left out.
+pub fn run(ctx: &Context, args: &[RedisString]) -> RedisResult {
+    let subcommand = args[0].to_string().to_ascii_lowercase();
+    match (subcommand.as_str(), args.len()) {
+        ("pause", 1) => {
+            EXPIRY_PAUSED.store(true, Ordering::SeqCst);
+            ctx.log_notice("Active expiration paused by EXPIREMEMBER.DEBUG PAUSE");
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        }
+        ("resume", 1) => {
+            EXPIRY_PAUSED.store(false, Ordering::SeqCst);
+            WORKER_WAKEUP.notify();
+            ctx.log_notice("Active expiration resumed by EXPIREMEMBER.DEBUG RESUME");
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        }
+        ("jumptime", 2) => {
+            let by = args[1].parse_integer().ok().filter(|by| *by > 0)
+                .ok_or(RedisError::Str("ERR milliseconds should be a positive integer"))?;
+            let ahead = deadline::jump(Duration::from_millis(by as u64))
+                .ok_or(RedisError::Str("ERR the module clock cannot be moved further ahead"))?;
+            WORKER_WAKEUP.notify();
+            ctx.log_notice(&format!("Module clock moved {} ms ahead by EXPIREMEMBER.DEBUG JUMPTIME, {} ms in all", by, ahead.as_millis()));
+            Ok(RedisValue::Integer(ahead.as_millis() as i64))
+        }
+        ("cycle", 1) => {
+            // The worker replies once it ran the cycle, which a transaction cannot wait for.
+            if ctx.get_flags().intersects(ContextFlags::MULTI | ContextFlags::LUA | ContextFlags::DENY_BLOCKING) {
+                return Err(RedisError::Str("ERR EXPIREMEMBER.DEBUG CYCLE cannot run in a transaction"));
+            }
+            PENDING_CYCLES.lock().unwrap().push(ctx.block_client());
+            WORKER_WAKEUP.notify();
+            Ok(RedisValue::NoReply)
+        }
+        ("pause" | "resume" | "jumptime" | "cycle", _) => Err(RedisError::String(format!("ERR wrong number of arguments for 'expiremember.debug|{}' command", subcommand))),
+        _ => Err(RedisError::String(format!("ERR unknown subcommand '{}', try PAUSE, RESUME, JUMPTIME or CYCLE", args[0]))),
+    }
+}
+
+/// Takes the calls of CYCLE the worker's cycle is run for. Called by the worker.
+pub fn take_cycles() -> Vec<BlockedClient> {
+    std::mem::take(&mut *PENDING_CYCLES.lock().unwrap())
+}
+
+/// Replies to the calls of CYCLE with the number of members the cycle expired.
+pub fn cycle_ran(clients: Vec<BlockedClient>, expired: u64) {
+    for client in clients {
+        ThreadSafeContext::with_blocked_client(client).reply(Ok(RedisValue::Integer(expired as i64)));
+    }
+}
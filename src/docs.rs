@@ -0,0 +1,362 @@
+//! Command metadata for COMMAND INFO and COMMAND DOCS: summaries, arity, key specs and
+//! arguments, which clients use for routing and auto-completion.
+
+use redis_module::{raw, Context};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_int;
+use std::ptr;
+
+use crate::commands;
+
+type ArgType = raw::RedisModuleCommandArgType;
+
+struct Arg {
+    name: &'static CStr,
+    kind: ArgType,
+    token: Option<&'static CStr>,
+    optional: bool,
+    subargs: &'static [Arg],
+}
+
+const fn arg(name: &'static CStr, kind: ArgType) -> Arg {
+    Arg { name, kind, token: None, optional: false, subargs: &[] }
+}
+
+const fn token(name: &'static CStr, token: &'static CStr) -> Arg {
+    arg(name, raw::RedisModuleCommandArgType_REDISMODULE_ARG_TYPE_PURE_TOKEN).token(token)
+}
+
+impl Arg {
+    const fn token(mut self, token: &'static CStr) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    const fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    const fn of(mut self, subargs: &'static [Arg]) -> Self {
+        self.subargs = subargs;
+        self
+    }
+}
+
+struct Command {
+    name: &'static CStr,
+    summary: &'static CStr,
+    complexity: &'static CStr,
+    since: &'static CStr,
+    arity: c_int,
+    // Flags of the key at argument 1, for the commands that take one.
+    key: Option<u32>,
+    args: &'static [Arg],
+}
+
+const KEY: ArgType = raw::RedisModuleCommandArgType_REDISMODULE_ARG_TYPE_KEY;
+const STRING: ArgType = raw::RedisModuleCommandArgType_REDISMODULE_ARG_TYPE_STRING;
+const INTEGER: ArgType = raw::RedisModuleCommandArgType_REDISMODULE_ARG_TYPE_INTEGER;
+const UNIX_TIME: ArgType = raw::RedisModuleCommandArgType_REDISMODULE_ARG_TYPE_UNIX_TIME;
+const PATTERN: ArgType = raw::RedisModuleCommandArgType_REDISMODULE_ARG_TYPE_PATTERN;
+const ONEOF: ArgType = raw::RedisModuleCommandArgType_REDISMODULE_ARG_TYPE_ONEOF;
+const BLOCK: ArgType = raw::RedisModuleCommandArgType_REDISMODULE_ARG_TYPE_BLOCK;
+
+const WRITE_KEY: u32 = raw::REDISMODULE_CMD_KEY_RW | raw::REDISMODULE_CMD_KEY_UPDATE;
+const READ_KEY: u32 = raw::REDISMODULE_CMD_KEY_RO | raw::REDISMODULE_CMD_KEY_ACCESS;
+
+const UNIT: Arg = arg(c"unit", ONEOF).optional().of(&[token(c"s", c"s"), token(c"ms", c"ms")]);
+const PRIORITY: Arg = arg(c"priority", ONEOF).token(c"PRIORITY").optional()
+    .of(&[token(c"high", c"high"), token(c"normal", c"normal"), token(c"low", c"low")]);
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: c"expiremember",
+        summary: c"Sets the time to live of a member of a hash, set, sorted set or list, or a JSON path, 0 removes it.",
+        complexity: c"O(1)",
+        since: c"1.0.0",
+        arity: -4,
+        key: Some(WRITE_KEY),
+        args: &[arg(c"key", KEY), arg(c"member", STRING), arg(c"ttl", INTEGER), UNIT, PRIORITY],
+    },
+    Command {
+        name: c"expirememberat",
+        summary: c"Sets the Unix time at which a member of a hash, set, sorted set or list, or a JSON path, expires.",
+        complexity: c"O(1)",
+        since: c"1.1.0",
+        arity: -4,
+        key: Some(WRITE_KEY),
+        args: &[
+            arg(c"key", KEY),
+            arg(c"member", STRING),
+            arg(c"timestamp", UNIX_TIME),
+            UNIT,
+            arg(c"clock", INTEGER).token(c"CLOCK").optional(),
+            PRIORITY,
+        ],
+    },
+    Command {
+        name: c"pexpirememberat",
+        summary: c"Sets the Unix time in milliseconds at which a member of a hash, set, sorted set or list, or a JSON path, expires.",
+        complexity: c"O(1)",
+        since: c"1.1.0",
+        arity: 4,
+        key: Some(WRITE_KEY),
+        args: &[arg(c"key", KEY), arg(c"member", STRING), arg(c"unix-time-milliseconds", UNIX_TIME)],
+    },
+    #[cfg(feature = "notifications")]
+    Command {
+        name: c"expiremember.subscribe",
+        summary: c"Reads expired members from the event log after a cursor.",
+        complexity: c"O(N) where N is the number of events returned",
+        since: c"1.1.0",
+        arity: -2,
+        key: None,
+        args: &[
+            arg(c"cursor", STRING),
+            arg(c"count", INTEGER).token(c"COUNT").optional(),
+            arg(c"milliseconds", INTEGER).token(c"BLOCK").optional(),
+        ],
+    },
+    Command {
+        name: c"expiremember.policy",
+        summary: c"Sets or returns what happens to the expiration of an overwritten member of a key.",
+        complexity: c"O(1)",
+        since: c"1.1.0",
+        arity: -2,
+        key: Some(WRITE_KEY),
+        args: &[
+            arg(c"key", KEY),
+            arg(c"policy", ONEOF).optional().of(&[
+                token(c"keep", c"KEEP"),
+                token(c"clear", c"CLEAR"),
+                arg(c"reset", BLOCK).of(&[token(c"reset", c"RESET"), arg(c"ttl", INTEGER), UNIT]),
+            ]),
+        ],
+    },
+    Command {
+        name: c"expiremember.dump",
+        summary: c"Serializes the expirations of a key.",
+        complexity: c"O(N) where N is the number of expirations of the key",
+        since: c"1.1.0",
+        arity: 2,
+        key: Some(READ_KEY),
+        args: &[arg(c"key", KEY)],
+    },
+    Command {
+        name: c"expiremember.dumpslot",
+        summary: c"Serializes the expirations of every key of a cluster slot.",
+        complexity: c"O(N) where N is the number of tracked expirations",
+        since: c"1.1.0",
+        arity: 2,
+        key: None,
+        args: &[arg(c"slot", INTEGER)],
+    },
+    Command {
+        name: c"expiremember.restore",
+        summary: c"Schedules the expirations of an EXPIREMEMBER.DUMP payload on a key.",
+        complexity: c"O(N) where N is the number of expirations in the payload",
+        since: c"1.1.0",
+        arity: -3,
+        key: Some(WRITE_KEY),
+        args: &[arg(c"key", KEY), arg(c"payload", STRING), token(c"replace", c"REPLACE").optional()],
+    },
+    Command {
+        name: c"expiremember.export",
+        summary: c"Writes every tracked expiration to a file in the background.",
+        complexity: c"O(N) where N is the number of tracked expirations",
+        since: c"1.1.0",
+        arity: 2,
+        key: None,
+        args: &[arg(c"path", STRING)],
+    },
+    Command {
+        name: c"expiremember.import",
+        summary: c"Schedules the expirations of an exported file in the background.",
+        complexity: c"O(N) where N is the number of expirations in the file",
+        since: c"1.1.0",
+        arity: 2,
+        key: None,
+        args: &[arg(c"path", STRING)],
+    },
+    Command {
+        name: c"expiremember.migrate",
+        summary: c"Replays the tracked expirations on another instance in the background.",
+        complexity: c"O(N) where N is the number of tracked expirations",
+        since: c"1.1.0",
+        arity: -3,
+        key: None,
+        args: &[
+            arg(c"host", STRING),
+            arg(c"port", INTEGER),
+            arg(c"pattern", PATTERN).token(c"MATCH").optional(),
+            arg(c"count", INTEGER).token(c"BATCH").optional(),
+            arg(c"password", STRING).token(c"AUTH").optional(),
+        ],
+    },
+    Command {
+        name: c"expiremember.sync",
+        summary: c"Expires the overdue members of a key, or of every key, before replying.",
+        complexity: c"O(N) where N is the number of overdue members",
+        since: c"1.1.0",
+        arity: -1,
+        // Without the key, the server finds none.
+        key: Some(WRITE_KEY),
+        args: &[arg(c"key", KEY).optional()],
+    },
+    Command {
+        name: c"expiremember.flushall",
+        summary: c"Forgets the tracked expirations, of every key or of a database or key pattern, leaving the members in place.",
+        complexity: c"O(N) where N is the number of tracked expirations",
+        since: c"1.1.0",
+        arity: -1,
+        key: None,
+        args: &[
+            arg(c"db", INTEGER).token(c"DB").optional(),
+            arg(c"pattern", PATTERN).token(c"MATCH").optional(),
+        ],
+    },
+    Command {
+        name: c"expiremember.check",
+        summary: c"Verifies the tracked expirations against the keyspace and the schedule.",
+        complexity: c"O(N) where N is the number of tracked expirations",
+        since: c"1.1.0",
+        arity: -1,
+        key: None,
+        args: &[token(c"repair", c"REPAIR").optional()],
+    },
+    Command {
+        name: c"expiremember.stats",
+        summary: c"Returns counters of schedules and deletions, in total and over the last minute, or per key.",
+        complexity: c"O(1), O(N log N) with KEYS where N is the number of keys counted",
+        since: c"1.1.0",
+        arity: -1,
+        key: None,
+        args: &[arg(c"keys", BLOCK).optional().of(&[token(c"keys", c"KEYS"), arg(c"count", INTEGER).optional()])],
+    },
+    #[cfg(feature = "debug")]
+    Command {
+        name: c"expiremember.info",
+        summary: c"Returns the effective configuration, the compiled-in constants and the state of the background thread.",
+        complexity: c"O(1)",
+        since: c"1.1.0",
+        arity: 1,
+        key: None,
+        args: &[],
+    },
+    #[cfg(feature = "debug")]
+    Command {
+        name: c"expiremember.debug",
+        summary: c"Pauses or resumes the background deletions, moves the module's clock ahead, or runs an expiry cycle.",
+        complexity: c"O(1), O(N) with CYCLE where N is the number of members expired",
+        since: c"1.1.0",
+        arity: -2,
+        key: None,
+        args: &[arg(c"subcommand", ONEOF).of(&[
+            token(c"pause", c"PAUSE"),
+            token(c"resume", c"RESUME"),
+            arg(c"jumptime", BLOCK).of(&[token(c"jumptime", c"JUMPTIME"), arg(c"milliseconds", INTEGER)]),
+            token(c"cycle", c"CYCLE"),
+        ])],
+    },
+    Command {
+        name: c"expiremember.metrics",
+        summary: c"Returns the module's metrics in the Prometheus text exposition format.",
+        complexity: c"O(1)",
+        since: c"1.1.0",
+        arity: 1,
+        key: None,
+        args: &[],
+    },
+    Command {
+        name: c"expiremember.memory",
+        summary: c"Estimates the memory taken by the module, in total or per key.",
+        complexity: c"O(N) where N is the number of tracked expirations",
+        since: c"1.1.0",
+        arity: -1,
+        key: None,
+        args: &[arg(c"keys", BLOCK).optional().of(&[token(c"keys", c"KEYS"), arg(c"count", INTEGER).optional()])],
+    },
+    #[cfg(feature = "rules")]
+    Command {
+        name: c"expiremember.reloadconf",
+        summary: c"Reloads the rules file set by expiremember.config-file.",
+        complexity: c"O(N) where N is the number of lines of the file",
+        since: c"1.1.0",
+        arity: 1,
+        key: None,
+        args: &[],
+    },
+];
+
+static VERSION: raw::RedisModuleCommandInfoVersion = raw::RedisModuleCommandInfoVersion {
+    version: 1,
+    sizeof_historyentry: size_of::<raw::RedisModuleCommandHistoryEntry>(),
+    sizeof_keyspec: size_of::<raw::RedisModuleCommandKeySpec>(),
+    sizeof_arg: size_of::<raw::RedisModuleCommandArg>(),
+};
+
+/// Sets the metadata of every command, only possible while loading. False if the server does
+/// not support command metadata (Redis 7.0+).
+pub fn register(ctx: &Context) -> bool {
+    let Some(set_info) = (unsafe { raw::RedisModule_SetCommandInfo }) else {
+        return false;
+    };
+
+    COMMANDS.iter().all(|spec| {
+        let name = CString::new(commands::name(spec.name.to_str().unwrap())).unwrap();
+        let command = unsafe { raw::RedisModule_GetCommand.unwrap()(ctx.ctx, name.as_ptr()) };
+        if command.is_null() {
+            return false;
+        }
+        // The server copies the metadata, the arrays only have to outlive the call.
+        let mut arrays = Vec::new();
+        let mut key_specs = key_specs(spec.key);
+        let info = raw::RedisModuleCommandInfo {
+            version: &VERSION,
+            summary: spec.summary.as_ptr(),
+            complexity: spec.complexity.as_ptr(),
+            since: spec.since.as_ptr(),
+            history: ptr::null_mut(),
+            tips: ptr::null(),
+            arity: spec.arity,
+            key_specs: if spec.key.is_some() { key_specs.as_mut_ptr() } else { ptr::null_mut() },
+            args: c_args(spec.args, &mut arrays),
+        };
+        unsafe { set_info(command, &info) == raw::REDISMODULE_OK as c_int }
+    })
+}
+
+/// The key spec of a key at argument 1, followed by the terminating empty spec.
+fn key_specs(flags: Option<u32>) -> [raw::RedisModuleCommandKeySpec; 2] {
+    let mut specs: [raw::RedisModuleCommandKeySpec; 2] = unsafe { std::mem::zeroed() };
+    if let Some(flags) = flags {
+        specs[0].flags = flags as u64;
+        specs[0].begin_search_type = raw::RedisModuleKeySpecBeginSearchType_REDISMODULE_KSPEC_BS_INDEX;
+        specs[0].bs.index.pos = 1;
+        specs[0].find_keys_type = raw::RedisModuleKeySpecFindKeysType_REDISMODULE_KSPEC_FK_RANGE;
+        specs[0].fk.range = raw::RedisModuleCommandKeySpec__bindgen_ty_2__bindgen_ty_1 { lastkey: 0, keystep: 1, limit: 0 };
+    }
+    specs
+}
+
+/// `args` as a C array ending with an empty argument, kept alive in `arrays` along with the
+/// arrays of their subarguments.
+fn c_args(args: &[Arg], arrays: &mut Vec<Vec<raw::RedisModuleCommandArg>>) -> *mut raw::RedisModuleCommandArg {
+    let mut array: Vec<raw::RedisModuleCommandArg> = Vec::with_capacity(args.len() + 1);
+    for arg in args {
+        let mut c_arg: raw::RedisModuleCommandArg = unsafe { std::mem::zeroed() };
+        c_arg.name = arg.name.as_ptr();
+        c_arg.type_ = arg.kind;
+        c_arg.key_spec_index = if arg.kind == KEY { 0 } else { -1 };
+        c_arg.token = arg.token.map_or(ptr::null(), CStr::as_ptr);
+        c_arg.flags = if arg.optional { raw::REDISMODULE_CMD_ARG_OPTIONAL as c_int } else { 0 };
+        c_arg.subargs = if arg.subargs.is_empty() { ptr::null_mut() } else { c_args(arg.subargs, arrays) };
+        array.push(c_arg);
+    }
+    array.push(unsafe { std::mem::zeroed() });
+    // Moving `array` into `arrays` leaves its buffer in place.
+    let array_ptr = array.as_mut_ptr();
+    arrays.push(array);
+    array_ptr
+}
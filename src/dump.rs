@@ -0,0 +1,127 @@
+//! Binary formats of EXPIREMEMBER.DUMP / EXPIREMEMBER.RESTORE payloads and of
+//! EXPIREMEMBER.EXPORT / EXPIREMEMBER.IMPORT snapshot files.
+//!
+//! Both are `version:u8 count:u64 record* checksum:u64`, integers little endian, strings
+//! prefixed by their u32 length, the checksum being FNV-1a over everything before it.
+//! A payload record is `member deadline_ms:u64 priority:u8`, a snapshot record
+//! `db:u32 key member deadline_ms:u64 priority:u8`.
+
+use crate::schedule::Priority;
+
+const PAYLOAD_VERSION: u8 = 2;
+// Payload records without the priority, all normal.
+const PAYLOAD_VERSION_NORMAL: u8 = 1;
+const SNAPSHOT_VERSION: u8 = 0x83;
+// Snapshot records without the priority, all normal.
+const SNAPSHOT_VERSION_NORMAL: u8 = 0x82;
+// Snapshot records without the database either, all in database 0.
+const SNAPSHOT_VERSION_DB0: u8 = 0x81;
+
+/// A snapshot record: db, key, member, unix ms deadline and priority.
+pub type SnapshotEntry = (i32, String, String, u64, Priority);
+
+pub fn encode(members: &[(String, u64, Priority)]) -> Vec<u8> {
+    let mut out = vec![PAYLOAD_VERSION];
+    out.extend_from_slice(&(members.len() as u64).to_le_bytes());
+    for (member, deadline, priority) in members {
+        put_str(&mut out, member);
+        out.extend_from_slice(&deadline.to_le_bytes());
+        out.push(priority.as_u64() as u8);
+    }
+    seal(out)
+}
+
+/// Returns the (member, unix ms deadline, priority) entries, or None if the payload is corrupt or
+/// unsupported.
+pub fn decode(payload: &[u8]) -> Option<Vec<(String, u64, Priority)>> {
+    let (version, mut rest) = open(payload)?;
+    if version != PAYLOAD_VERSION && version != PAYLOAD_VERSION_NORMAL {
+        return None;
+    }
+    let count = take_u64(&mut rest)?;
+    let mut members = Vec::new();
+    for _ in 0..count {
+        let (member, deadline) = (take_str(&mut rest)?, take_u64(&mut rest)?);
+        let priority = if version == PAYLOAD_VERSION { take_priority(&mut rest)? } else { Priority::Normal };
+        members.push((member, deadline, priority));
+    }
+    rest.is_empty().then_some(members)
+}
+
+pub fn encode_snapshot(entries: &[SnapshotEntry]) -> Vec<u8> {
+    let mut out = vec![SNAPSHOT_VERSION];
+    out.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    for (db, key, member, deadline, priority) in entries {
+        out.extend_from_slice(&(*db as u32).to_le_bytes());
+        put_str(&mut out, key);
+        put_str(&mut out, member);
+        out.extend_from_slice(&deadline.to_le_bytes());
+        out.push(priority.as_u64() as u8);
+    }
+    seal(out)
+}
+
+/// Returns the entries of a snapshot, or None if the file is corrupt or unsupported.
+pub fn decode_snapshot(snapshot: &[u8]) -> Option<Vec<SnapshotEntry>> {
+    let (version, mut rest) = open(snapshot)?;
+    if ![SNAPSHOT_VERSION, SNAPSHOT_VERSION_NORMAL, SNAPSHOT_VERSION_DB0].contains(&version) {
+        return None;
+    }
+    let count = take_u64(&mut rest)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let db = if version != SNAPSHOT_VERSION_DB0 { take_u32(&mut rest)? as i32 } else { 0 };
+        let (key, member, deadline) = (take_str(&mut rest)?, take_str(&mut rest)?, take_u64(&mut rest)?);
+        let priority = if version == SNAPSHOT_VERSION { take_priority(&mut rest)? } else { Priority::Normal };
+        entries.push((db, key, member, deadline, priority));
+    }
+    rest.is_empty().then_some(entries)
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn seal(mut out: Vec<u8>) -> Vec<u8> {
+    let checksum = fnv1a(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+/// Verifies the checksum, returning the version and the records.
+fn open(data: &[u8]) -> Option<(u8, &[u8])> {
+    let (body, checksum) = data.split_at_checked(data.len().checked_sub(8)?)?;
+    if fnv1a(body) != u64::from_le_bytes(checksum.try_into().ok()?) {
+        return None;
+    }
+    let (&version, rest) = body.split_first()?;
+    Some((version, rest))
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let (head, tail) = rest.split_at_checked(len)?;
+    *rest = tail;
+    Some(head)
+}
+
+fn take_u64(rest: &mut &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(take(rest, 8)?.try_into().ok()?))
+}
+
+fn take_u32(rest: &mut &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(take(rest, 4)?.try_into().ok()?))
+}
+
+fn take_priority(rest: &mut &[u8]) -> Option<Priority> {
+    Some(Priority::from_u64(take(rest, 1)?[0] as u64))
+}
+
+fn take_str(rest: &mut &[u8]) -> Option<String> {
+    let len = take_u32(rest)? as usize;
+    String::from_utf8(take(rest, len)?.to_vec()).ok()
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
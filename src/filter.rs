@@ -0,0 +1,120 @@
+//! Command filter noting the arguments of commands whose keyspace notifications do not carry
+//! them: the source of COPY, and the members written by HSET, ZADD and the like.
+
+use lazy_static::lazy_static;
+use redis_module::{raw, Context, RedisString};
+use std::collections::VecDeque;
+use std::os::raw::c_int;
+use std::sync::Mutex;
+
+use crate::overwrite;
+
+/// Commands remembered until their notification, older ones are dropped.
+const MAX_PENDING: usize = 1024;
+
+lazy_static! {
+    // (source, destination) of COPY commands seen by the filter, in order. Commands queued in a
+    // MULTI are filtered when queued, so several can be pending until EXEC runs them.
+    static ref PENDING_COPIES: Mutex<VecDeque<(String, String)>> = Mutex::new(VecDeque::new());
+    // (event, key, members) of member writes to keys with an overwrite policy, likewise.
+    static ref PENDING_WRITES: Mutex<VecDeque<(&'static str, String, Vec<String>)>> = Mutex::new(VecDeque::new());
+}
+
+/// Registers the filter, false if the server refused it.
+pub fn register(ctx: &Context) -> bool {
+    let filter = unsafe {
+        raw::RedisModule_RegisterCommandFilter.unwrap()(ctx.ctx, Some(command_filter), raw::REDISMODULE_CMDFILTER_NOSELF as c_int)
+    };
+    !filter.is_null()
+}
+
+/// The source of the COPY to `destination` being notified, None if the filter did not see it.
+pub fn copy_source(destination: &str) -> Option<String> {
+    let mut pending = PENDING_COPIES.lock().unwrap();
+    let position = pending.iter().position(|(_, to)| to == destination)?;
+    pending.remove(position).map(|(from, _)| from)
+}
+
+/// The members written by the command sending `event` for `key`, None if the filter did not see it.
+pub fn written_members(event: &str, key: &str) -> Option<Vec<String>> {
+    let mut pending = PENDING_WRITES.lock().unwrap();
+    let position = pending.iter().position(|(pending_event, pending_key, _)| *pending_event == event && pending_key == key)?;
+    pending.remove(position).map(|(_, _, members)| members)
+}
+
+extern "C" fn command_filter(fctx: *mut raw::RedisModuleCommandFilterCtx) {
+    let argc = unsafe { raw::RedisModule_CommandFilterArgsCount.unwrap()(fctx) };
+    if argc < 3 {
+        return;
+    }
+    let args: Vec<&[u8]> = (0..argc)
+        .map(|pos| RedisString::string_as_slice(unsafe { raw::RedisModule_CommandFilterArgGet.unwrap()(fctx, pos) }))
+        .collect();
+    match args[0].to_ascii_lowercase().as_slice() {
+        b"copy" => copy(&args),
+        b"hset" | b"hmset" => write("hset", &args, args.get(2..).unwrap_or_default().iter().step_by(2)),
+        b"hincrby" => write("hincrby", &args, args.get(2..3).unwrap_or_default().iter()),
+        b"hincrbyfloat" => write("hincrbyfloat", &args, args.get(2..3).unwrap_or_default().iter()),
+        b"zincrby" => write("zincr", &args, args.get(3..4).unwrap_or_default().iter()),
+        b"zadd" => zadd(&args),
+        _ => {}
+    }
+}
+
+fn copy(args: &[&[u8]]) {
+    // The filter cannot tell the database of the source, so copies to another database are
+    // not followed.
+    if args[3..].iter().any(|arg| arg.eq_ignore_ascii_case(b"db")) {
+        return;
+    }
+
+    let destination = lossy(args[2]);
+    let mut pending = PENDING_COPIES.lock().unwrap();
+    // A COPY that failed (destination exists, no REPLACE) is never notified.
+    pending.retain(|(_, to)| *to != destination);
+    if pending.len() == MAX_PENDING {
+        pending.pop_front();
+    }
+    pending.push_back((lossy(args[1]), destination));
+}
+
+/// ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]
+fn zadd(args: &[&[u8]]) {
+    let mut event = "zadd";
+    let mut position = 2;
+    while let Some(option) = args.get(position).map(|arg| arg.to_ascii_lowercase()) {
+        match option.as_slice() {
+            // Never overwrites a member.
+            b"nx" => return,
+            b"incr" => event = "zincr",
+            b"xx" | b"gt" | b"lt" | b"ch" => {}
+            _ => break,
+        }
+        position += 1;
+    }
+    write(event, args, args.get(position + 1..).unwrap_or_default().iter().step_by(2));
+}
+
+fn write<'a>(event: &'static str, args: &[&[u8]], members: impl Iterator<Item = &'a &'a [u8]>) {
+    let key = lossy(args[1]);
+    if !overwrite::has_policy(&key) {
+        return;
+    }
+
+    let mut members: Vec<String> = members.map(|member| lossy(member)).collect();
+    let mut pending = PENDING_WRITES.lock().unwrap();
+    // A failed write (e.g. WRONGTYPE) is never notified, so its members are merged into the next
+    // write to the key rather than left to be matched with it.
+    if let Some(position) = pending.iter().position(|(pending_event, pending_key, _)| *pending_event == event && *pending_key == key) {
+        let (_, _, earlier) = pending.remove(position).unwrap();
+        members.extend(earlier);
+    }
+    if pending.len() == MAX_PENDING {
+        pending.pop_front();
+    }
+    pending.push_back((event, key, members));
+}
+
+fn lossy(arg: &[u8]) -> String {
+    String::from_utf8_lossy(arg).into_owned()
+}
@@ -0,0 +1,107 @@
+//! Background pass forgetting orphaned expirations, whose key or member no longer exists, for
+//! when keyspace notifications did not catch the removal (e.g. a key overwritten by SET).
+
+use redis_module::Context;
+use std::collections::HashMap;
+
+use crate::{cluster, commands, shadow, with_db, Container, ExpiringMember, MembersByKey, BACKEND, EXPIRATION_TIMES, SHARDS};
+
+/// Walks the tracked expirations `count` at a time across calls, a shard after the other,
+/// wrapping around at the end.
+pub struct Sampler {
+    shard: usize,
+    cursor: usize,
+}
+
+impl Sampler {
+    pub fn new() -> Self {
+        Sampler { shard: 0, cursor: 0 }
+    }
+
+    /// The next `count` tracked expirations. The order shifts as expirations come and go, which
+    /// at worst makes a pass skip or repeat some of them.
+    pub fn next(&mut self, count: usize) -> Vec<ExpiringMember> {
+        let mut sample = Vec::new();
+        // Every shard once at most, the first one possibly twice if the walk started mid-way.
+        for _ in 0..=SHARDS {
+            if sample.len() >= count {
+                break;
+            }
+            let shard = EXPIRATION_TIMES.read_shard(self.shard);
+            let start = sample.len();
+            sample.extend(shard.values().skip(self.cursor).take(count - start).cloned());
+            self.cursor += sample.len() - start;
+            if self.cursor >= shard.len() {
+                self.shard = (self.shard + 1) % SHARDS;
+                self.cursor = 0;
+            }
+        }
+        sample
+    }
+}
+
+/// Why an expiration is orphaned.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Orphan {
+    // The key is gone or of a type whose members cannot expire.
+    MissingKey,
+    MissingMember,
+}
+
+/// Forgets the expirations of `sample` whose key is gone, of an unsupported type, or no longer
+/// has the member. Must hold the GIL.
+///
+/// Returns the number of expirations forgotten.
+pub fn collect_orphans(ctx: &Context, sample: Vec<ExpiringMember>) -> usize {
+    let orphans = find_orphans(ctx, sample);
+    forget_orphans(ctx, orphans.into_iter().map(|(orphan, _)| orphan).collect())
+}
+
+/// The orphaned expirations of `sample`. Must hold the GIL.
+pub fn find_orphans(ctx: &Context, sample: Vec<ExpiringMember>) -> Vec<(ExpiringMember, Orphan)> {
+    let mut by_key: MembersByKey = HashMap::new();
+    for member in sample {
+        by_key.entry((member.db, member.key.clone())).or_default().push(member);
+    }
+
+    // Only keys served here are checked, keys of other or migrating slots are not missing.
+    let slot_states = cluster::slot_states(ctx);
+    let mut orphans = Vec::new();
+    for ((db, key), members) in by_key {
+        let slot = cluster::key_slot(&key);
+        if slot_states.as_ref().is_some_and(|states| !states.owned[slot] || states.migrating[slot]) {
+            continue;
+        }
+        match with_db(ctx, db, || Container::type_of(ctx, &key)) {
+            // Kept for when the type is tracked again.
+            Ok(Some(container)) if !container.is_tracked() => {}
+            Ok(Some(container)) => orphans.extend(members.into_iter()
+                .filter(|member| !with_db(ctx, db, || container.contains(ctx, &key, &member.member)))
+                .map(|member| (member, Orphan::MissingMember))),
+            Ok(None) | Err(_) => orphans.extend(members.into_iter().map(|member| (member, Orphan::MissingKey))),
+        }
+    }
+    orphans
+}
+
+/// Forgets `orphans` unless rescheduled since found, and propagates that. Must hold the GIL.
+///
+/// Returns the number of expirations forgotten.
+pub fn forget_orphans(ctx: &Context, mut orphans: Vec<ExpiringMember>) -> usize {
+    let backend = *BACKEND.lock().unwrap();
+    orphans.retain(|orphan| {
+        let current = EXPIRATION_TIMES.is_current(orphan);
+        if current {
+            EXPIRATION_TIMES.remove(orphan.db, &orphan.key, &orphan.member);
+        }
+        current
+    });
+
+    for orphan in &orphans {
+        with_db(ctx, orphan.db, || {
+            shadow::forget(ctx, backend, &orphan.key, &orphan.member);
+            ctx.replicate(&commands::name("EXPIREMEMBER"), &[&*orphan.key, &*orphan.member, "-1"]);
+        });
+    }
+    orphans.len()
+}
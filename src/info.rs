@@ -0,0 +1,246 @@
+//! The module's INFO sections, `INFO expiremember`: how many expirations are tracked, scheduled
+//! and queued, how fast members expire, how far behind the worker is, how long it waits for and
+//! holds the GIL, and when it last ran.
+//!
+//! EXPIREMEMBER.INFO: the effective configuration, the compiled-in constants and the worker's
+//! state, for support sessions, with the `debug` feature.
+
+use lazy_static::lazy_static;
+use linkme::distributed_slice;
+use redis_module::{server_events::INFO_COMMAND_HANDLER_LIST, InfoContext, RedisResult};
+#[cfg(feature = "debug")]
+use redis_module::{Context, RedisValue};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::deadline::Deadline;
+use crate::timer::Driver;
+use crate::{lag, limits, pool, watchdog, DRIVER, EXPIRATION_QUEUE, EXPIRATION_TIMES, THREAD_STARTED};
+#[cfg(feature = "debug")]
+use crate::{deadline, server, throttle, COMPACTION_MIN_ENTRIES, EXPIRY_PAUSED, IS_REPLICA, LOGICAL_CLOCK, SHARDS};
+
+/// The expiry rate is averaged over at least this long.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Members expired so far, as of `at`.
+#[derive(Clone, Copy)]
+struct Sample {
+    at: Instant,
+    expired: u64,
+}
+
+lazy_static! {
+    // Entries in the worker's schedule, stale ones included, as of its last cycle.
+    static ref SCHEDULED: AtomicUsize = AtomicUsize::new(0);
+    static ref PARKED: AtomicBool = AtomicBool::new(false);
+    static ref LAST_CYCLE: Mutex<Option<Instant>> = Mutex::new(None);
+    static ref LAST_CYCLE_USEC: AtomicU64 = AtomicU64::new(0);
+    static ref CYCLES: AtomicU64 = AtomicU64::new(0);
+    // The earliest deadline in the worker's schedule, as of its last cycle.
+    static ref NEXT_DEADLINE: Mutex<Option<Deadline>> = Mutex::new(None);
+    static ref GIL_WAIT_USEC: AtomicU64 = AtomicU64::new(0);
+    static ref GIL_HELD_USEC: AtomicU64 = AtomicU64::new(0);
+    // The sample the rate is measured from, and the one replacing it once a window old.
+    static ref SAMPLES: Mutex<(Sample, Sample)> = {
+        let sample = Sample { at: Instant::now(), expired: 0 };
+        Mutex::new((sample, sample))
+    };
+}
+
+/// Records the end of a worker cycle that took `duration`, leaving `scheduled` entries due from
+/// `next_deadline` on, and parking the worker unless it has a deadline.
+pub fn cycle_ended(scheduled: usize, next_deadline: Option<Deadline>, parked: bool, duration: Duration) {
+    SCHEDULED.store(scheduled, Ordering::Relaxed);
+    LAST_CYCLE_USEC.store(duration.as_micros() as u64, Ordering::Relaxed);
+    *NEXT_DEADLINE.lock().unwrap() = next_deadline;
+    PARKED.store(parked, Ordering::Relaxed);
+    *LAST_CYCLE.lock().unwrap() = Some(Instant::now());
+    CYCLES.fetch_add(1, Ordering::Relaxed);
+    expired_per_sec();
+}
+
+/// Entries in the worker's schedule, stale ones included, as of its last cycle.
+pub fn scheduled() -> usize {
+    SCHEDULED.load(Ordering::Relaxed)
+}
+
+/// (time since the last cycle ended, None before the first one, whether the worker is parked).
+pub fn liveness() -> (Option<Duration>, bool) {
+    (LAST_CYCLE.lock().unwrap().map(|at| at.elapsed()), PARKED.load(Ordering::Relaxed))
+}
+
+/// Accounts for the worker, or one of the deletion threads, having waited `wait` for the GIL
+/// and then held it for `held`.
+pub fn gil_used(wait: Duration, held: Duration) {
+    GIL_WAIT_USEC.fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+    GIL_HELD_USEC.fetch_add(held.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Members expired per second over the last one to two windows, sampled by the worker and by
+/// INFO alike so the rate goes back to 0 while the worker is parked.
+fn expired_per_sec() -> f64 {
+    let expired = pool::stats().iter().map(|(members, _)| members).sum();
+    let now = Sample { at: Instant::now(), expired };
+    let mut samples = SAMPLES.lock().unwrap();
+    if now.at.duration_since(samples.1.at) >= RATE_WINDOW {
+        *samples = (samples.1, now);
+    }
+    let elapsed = now.at.duration_since(samples.0.at).as_secs_f64();
+    if elapsed <= 0.0 {
+        return 0.0;
+    }
+    (now.expired - samples.0.expired) as f64 / elapsed
+}
+
+/// The module's metrics at one point in time.
+pub struct Snapshot {
+    pub tracked: usize,
+    pub scheduled: usize,
+    pub queued: usize,
+    pub expired: u64,
+    pub expired_per_sec: f64,
+    pub overdue: usize,
+    pub backlog_rejections: u64,
+    pub oom_rejections: u64,
+    pub gil_wait: Duration,
+    pub gil_held: Duration,
+    pub started: bool,
+    pub parked: bool,
+    // Since the last cycle ended, None before the first one.
+    pub last_cycle: Option<Duration>,
+    pub last_cycle_duration: Duration,
+    pub cycles: u64,
+    // `ok`, `stalled` or `dead`, see `watchdog`.
+    pub health: &'static str,
+    pub stalls: u64,
+    // (members, batches) expired by each deletion thread, the worker first.
+    pub threads: Vec<(u64, u64)>,
+    // How long after their deadlines members were deleted.
+    pub lag: lag::Summary,
+}
+
+pub fn snapshot() -> Snapshot {
+    let (overdue, backlog_rejections, oom_rejections) = limits::stats();
+    let threads = pool::stats();
+    Snapshot {
+        tracked: EXPIRATION_TIMES.len(),
+        scheduled: SCHEDULED.load(Ordering::Relaxed),
+        queued: EXPIRATION_QUEUE.len(),
+        expired: threads.iter().map(|(members, _)| members).sum(),
+        expired_per_sec: expired_per_sec(),
+        overdue,
+        backlog_rejections,
+        oom_rejections,
+        gil_wait: Duration::from_micros(GIL_WAIT_USEC.load(Ordering::Relaxed)),
+        gil_held: Duration::from_micros(GIL_HELD_USEC.load(Ordering::Relaxed)),
+        started: THREAD_STARTED.load(Ordering::SeqCst),
+        parked: PARKED.load(Ordering::Relaxed),
+        last_cycle: LAST_CYCLE.lock().unwrap().map(|at| at.elapsed()),
+        last_cycle_duration: Duration::from_micros(LAST_CYCLE_USEC.load(Ordering::Relaxed)),
+        cycles: CYCLES.load(Ordering::Relaxed),
+        health: watchdog::health(),
+        stalls: watchdog::stalls(),
+        threads,
+        lag: lag::summary(),
+    }
+}
+
+#[distributed_slice(INFO_COMMAND_HANDLER_LIST)]
+fn add_info(ctx: &InfoContext, _for_crash_report: bool) -> RedisResult<()> {
+    let snapshot = snapshot();
+    let builder = ctx.builder()
+        .add_section("stats")
+        .field("tracked", snapshot.tracked as u64)?
+        .field("scheduled", snapshot.scheduled as u64)?
+        .field("queued", snapshot.queued as u64)?
+        .field("expired", snapshot.expired)?
+        .field("expired_per_sec", format!("{:.2}", snapshot.expired_per_sec))?
+        .field("overdue", snapshot.overdue as u64)?
+        .field("backlog_rejections", snapshot.backlog_rejections)?
+        .field("oom_rejections", snapshot.oom_rejections)?
+        .field("gil_wait_usec", snapshot.gil_wait.as_micros() as u64)?
+        .field("gil_held_usec", snapshot.gil_held.as_micros() as u64)?
+        .field("lag_p50_usec", snapshot.lag.p50.as_micros() as u64)?
+        .field("lag_p95_usec", snapshot.lag.p95.as_micros() as u64)?
+        .field("lag_p99_usec", snapshot.lag.p99.as_micros() as u64)?
+        .field("lag_max_usec", snapshot.lag.max.as_micros() as u64)?
+        .build_section()?;
+
+    let mut worker = builder
+        .add_section("worker")
+        .field("started", snapshot.started as u64)?
+        .field("driver", driver_name())?
+        .field("parked", snapshot.parked as u64)?
+        .field("last_cycle_ms_ago", snapshot.last_cycle.map_or(-1, |ago| ago.as_millis() as i64))?
+        .field("last_cycle_time", snapshot.last_cycle.map_or(0, |ago| Deadline::now().unix_ms() - ago.as_millis() as u64))?
+        .field("last_cycle_usec", snapshot.last_cycle_duration.as_micros() as u64)?
+        .field("cycles", snapshot.cycles)?
+        .field("health", snapshot.health)?
+        .field("stalls", snapshot.stalls)?;
+    for (thread, (members, batches)) in snapshot.threads.into_iter().enumerate() {
+        worker = worker
+            .add_dictionary(&format!("thread{}", thread))
+            .field("expired", members)?
+            .field("batches", batches)?
+            .build_dictionary()?;
+    }
+    worker.build_section()?.build_info()?;
+    Ok(())
+}
+
+fn driver_name() -> &'static str {
+    match *DRIVER.lock().unwrap() {
+        Driver::thread => "thread",
+        Driver::timer => "timer",
+    }
+}
+
+#[cfg(feature = "debug")]
+fn fields(fields: Vec<(&'static str, RedisValue)>) -> RedisValue {
+    RedisValue::Array(fields.into_iter().flat_map(|(name, value)| [RedisValue::SimpleStringStatic(name), value]).collect())
+}
+
+/// `config`, `constants` and `runtime`, each a flat list of names and values. Must hold the GIL.
+#[cfg(feature = "debug")]
+pub fn report(ctx: &Context) -> RedisResult {
+    let config = ctx.call("CONFIG", &["GET", "expiremember.*"])?;
+    let constants = fields(vec![
+        ("compaction_min_entries", RedisValue::Integer(COMPACTION_MIN_ENTRIES as i64)),
+        ("shards", RedisValue::Integer(SHARDS as i64)),
+        ("rate_window_ms", RedisValue::Integer(RATE_WINDOW.as_millis() as i64)),
+    ]);
+
+    let shard_sizes: Vec<usize> = EXPIRATION_TIMES.shards().map(|shard| shard.len()).collect();
+    let next_deadline = *NEXT_DEADLINE.lock().unwrap();
+    let runtime = fields(vec![
+        ("server", RedisValue::SimpleStringStatic(server::name())),
+        ("server_version", RedisValue::BulkString(server::version())),
+        ("role", RedisValue::SimpleStringStatic(if IS_REPLICA.load(Ordering::SeqCst) { "replica" } else { "primary" })),
+        ("worker_started", RedisValue::Integer(THREAD_STARTED.load(Ordering::SeqCst) as i64)),
+        ("driver", RedisValue::SimpleStringStatic(driver_name())),
+        ("deletion_threads", RedisValue::Integer(pool::threads() as i64)),
+        ("parked", RedisValue::Integer(PARKED.load(Ordering::Relaxed) as i64)),
+        ("paused", RedisValue::Integer(EXPIRY_PAUSED.load(Ordering::SeqCst) as i64)),
+        ("clock_jumped_ms", RedisValue::Integer(deadline::jumped().as_millis() as i64)),
+        ("last_cycle_ms_ago", RedisValue::Integer(LAST_CYCLE.lock().unwrap().map_or(-1, |at| at.elapsed().as_millis() as i64))),
+        ("cycles", RedisValue::Integer(CYCLES.load(Ordering::Relaxed) as i64)),
+        ("health", RedisValue::SimpleStringStatic(watchdog::health())),
+        ("last_panic", watchdog::last_panic().map_or(RedisValue::Null, RedisValue::BulkString)),
+        ("next_deadline", next_deadline.map_or(RedisValue::Null, |deadline| RedisValue::Integer(deadline.unix_ms() as i64))),
+        ("next_deadline_in_ms", next_deadline.map_or(RedisValue::Null, |deadline| RedisValue::Integer(deadline.remaining().as_millis() as i64))),
+        ("lock_free_in_ms", RedisValue::Integer(throttle::lock_free_at().map_or(0, |at| at.remaining().as_millis() as i64))),
+        ("tracked", RedisValue::Integer(EXPIRATION_TIMES.len() as i64)),
+        ("scheduled", RedisValue::Integer(SCHEDULED.load(Ordering::Relaxed) as i64)),
+        ("queued", RedisValue::Integer(EXPIRATION_QUEUE.len() as i64)),
+        ("smallest_shard", RedisValue::Integer(shard_sizes.iter().min().copied().unwrap_or(0) as i64)),
+        ("largest_shard", RedisValue::Integer(shard_sizes.iter().max().copied().unwrap_or(0) as i64)),
+        ("logical_clock", RedisValue::Integer(LOGICAL_CLOCK.load(Ordering::SeqCst) as i64)),
+    ]);
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("config"), config,
+        RedisValue::SimpleStringStatic("constants"), constants,
+        RedisValue::SimpleStringStatic("runtime"), runtime,
+    ]))
+}
@@ -0,0 +1,64 @@
+//! KeyDB compatibility mode, enabled with `expiremember.keydb-compat`: EXPIREMEMBER,
+//! EXPIREMEMBERAT and PEXPIREMEMBERAT reply and fail like KeyDB's, for clients written against it.
+//! The extended EXPIREMEMBERAT forms keep working, replicas and the AOF receive them.
+
+use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use std::time::Duration;
+
+use crate::deadline::Deadline;
+use crate::{delete_member, schedule_member, selected_db, Container, ExpiringMember};
+
+const NOT_AN_INTEGER: RedisError = RedisError::Str("ERR value is not an integer or out of range");
+const INVALID_EXPIRE_TIME: RedisError = RedisError::Str("ERR invalid expire time");
+
+/// EXPIREMEMBER key member ttl [unit]
+pub fn expiremember(ctx: &Context, args: &[RedisString]) -> RedisResult {
+    let ttl = args[3].parse_integer().map_err(|_| NOT_AN_INTEGER)?;
+    if args.len() > 5 {
+        return Err(RedisError::Str("ERR Invalid number of arguments"));
+    }
+    // Only the first letter of the unit counts.
+    let unit_ms = match args.get(4).map(|unit| unit.as_slice().first().map(u8::to_ascii_lowercase)) {
+        None | Some(Some(b's')) => 1000,
+        Some(Some(b'm')) => 1,
+        _ => return Err(RedisError::Str("ERR Invalid unit arg")),
+    };
+
+    let ttl_ms = ttl.saturating_mul(unit_ms);
+    let expire_at = match ttl_ms > 0 {
+        true => Some(Deadline::command_start().checked_add(Duration::from_millis(ttl_ms as u64)).ok_or(INVALID_EXPIRE_TIME)?),
+        false => None,
+    };
+    expire_member(ctx, &args[1], &args[2], expire_at)
+}
+
+/// EXPIREMEMBERAT key member timestamp, or PEXPIREMEMBERAT with `unit_ms` 1.
+pub fn expirememberat(ctx: &Context, args: &[RedisString], unit_ms: i64) -> RedisResult {
+    let timestamp_ms = args[3].parse_integer().map_err(|_| NOT_AN_INTEGER)?.saturating_mul(unit_ms);
+    let expire_at = match timestamp_ms > 0 {
+        true => Some(Deadline::checked_from_unix(Duration::from_millis(timestamp_ms as u64)).ok_or(INVALID_EXPIRE_TIME)?),
+        false => None,
+    };
+    expire_member(ctx, &args[1], &args[2], expire_at)
+}
+
+/// Schedules the member, or deletes it right away when `expire_at` has passed (None: long ago).
+/// Replies 0 without doing anything if the key or the member does not exist.
+fn expire_member(ctx: &Context, key: &RedisString, member: &RedisString, expire_at: Option<Deadline>) -> RedisResult {
+    let (key, member) = (key.to_string(), member.to_string());
+    let container = match Container::of(ctx, &key) {
+        Ok(Some(container)) => container,
+        Ok(None) => return Ok(RedisValue::Integer(0)),
+        Err(_) => return Err(RedisError::Str("ERR object type is unsupported")),
+    };
+    if !container.contains(ctx, &key, &member) {
+        return Ok(RedisValue::Integer(0));
+    }
+
+    match expire_at {
+        Some(expire_at) if expire_at > Deadline::command_start() => {
+            schedule_member(ctx, ExpiringMember::new(selected_db(ctx), key, member, expire_at))
+        }
+        _ => delete_member(ctx, key, member),
+    }
+}
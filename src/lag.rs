@@ -0,0 +1,79 @@
+//! Expiry lag: how long after its deadline each member was deleted, kept as a histogram so its
+//! percentiles can be checked against an SLA. Buckets are a quarter of a power of two wide, so
+//! percentiles are within 25% of the exact ones, and lags are counted in microseconds.
+
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Lags under this many microseconds have a bucket each.
+const LINEAR: u64 = 8;
+const BUCKETS: usize = LINEAR as usize + 4 * 61;
+
+lazy_static! {
+    static ref COUNTS: Vec<AtomicU64> = (0..BUCKETS).map(|_| AtomicU64::new(0)).collect();
+    static ref SUM_USEC: AtomicU64 = AtomicU64::new(0);
+    static ref MAX_USEC: AtomicU64 = AtomicU64::new(0);
+}
+
+fn bucket(usec: u64) -> usize {
+    if usec < LINEAR {
+        return usec as usize;
+    }
+    let power = 63 - usec.leading_zeros();
+    LINEAR as usize + 4 * (power as usize - 3) + ((usec >> (power - 2)) & 3) as usize
+}
+
+/// The largest lag counted in `bucket`, in microseconds.
+fn upper_bound(bucket: usize) -> u64 {
+    if bucket < LINEAR as usize {
+        return bucket as u64;
+    }
+    let power = (bucket - LINEAR as usize) / 4 + 3;
+    let quarter = ((bucket - LINEAR as usize) % 4) as u64;
+    ((4 + quarter + 1) << (power - 2)).wrapping_sub(1)
+}
+
+/// Counts a member deleted `lag` after its deadline.
+pub fn record(lag: Duration) {
+    let usec = lag.as_micros().min(u64::MAX as u128) as u64;
+    COUNTS[bucket(usec)].fetch_add(1, Ordering::Relaxed);
+    SUM_USEC.fetch_add(usec, Ordering::Relaxed);
+    MAX_USEC.fetch_max(usec, Ordering::Relaxed);
+}
+
+/// The lag distribution of the members deleted so far.
+pub struct Summary {
+    pub count: u64,
+    pub sum: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+pub fn summary() -> Summary {
+    let counts: Vec<u64> = COUNTS.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+    let count = counts.iter().sum::<u64>();
+    let max = MAX_USEC.load(Ordering::Relaxed);
+    let quantile = |q: f64| {
+        let rank = ((q * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, bucket_count) in counts.iter().enumerate() {
+            seen += bucket_count;
+            if seen >= rank {
+                return Duration::from_micros(upper_bound(bucket).min(max));
+            }
+        }
+        Duration::from_micros(max)
+    };
+    Summary {
+        count,
+        sum: Duration::from_micros(SUM_USEC.load(Ordering::Relaxed)),
+        p50: quantile(0.50),
+        p95: quantile(0.95),
+        p99: quantile(0.99),
+        max: Duration::from_micros(max),
+    }
+}
+
@@ -1,22 +1,114 @@
-use crossbeam::queue::ArrayQueue;
+#![cfg_attr(test, allow(dead_code, unused_imports))]
+
+use crossbeam::queue::SegQueue;
 use lazy_static::lazy_static;
+use linkme::distributed_slice;
 use redis_module::{
-    redis_module, raw as rawmod, Context, RedisError, RedisResult, RedisString, RedisValue,
-    ThreadSafeContext, KeyType, Status, RedisModuleIO,
+    redis_module, configuration::ConfigurationFlags, enum_configuration, BlockedClient, Context, ContextFlags,
+    ModuleOptions, RedisError, server_events::{LoadingSubevent, ServerRole, LOADING_SERVER_EVENTS_LIST, ROLE_CHANGED_SERVER_EVENTS_LIST}, RedisResult, RedisString, RedisValue, Status, ThreadSafeContext,
+    KeyType, NotifyEvent, raw, CallOptionsBuilder, CallReply, CallResult, DetachedFromClient,
 };
-use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard, atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering}};
+use std::panic::{self, AssertUnwindSafe};
 use std::thread;
-use std::time::{Duration, Instant, SystemTime};
-use std::collections::{BinaryHeap, HashMap};
-use std::cmp::Reverse;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, RandomState};
+use std::os::raw::{c_int, c_void};
+use std::ffi::CStr;
+
+mod acl;
+mod arguments;
+mod check;
+mod cluster;
+mod commands;
+mod config;
+mod datatype;
+mod deadline;
+#[cfg(feature = "debug")]
+mod debug;
+mod docs;
+mod dump;
+mod filter;
+mod gc;
+mod info;
+mod keydb;
+mod lag;
+mod limits;
+mod memory;
+mod metrics;
+mod notify;
+mod overwrite;
+mod persistence;
+mod pool;
+mod profile;
+#[cfg(feature = "rules")]
+mod rules;
+mod schedule;
+mod server;
+mod shadow;
+mod snapshot;
+mod statsd;
+mod stats;
+mod throttle;
+mod timer;
+mod trace;
+mod watchdog;
+mod wheel;
+
+use config::Checked;
+use deadline::Deadline;
+use datatype::MEMBER_TTL_TYPE;
+use limits::MaxEntriesPolicy;
+use notify::EventClass;
+use persistence::EXPIREMEMBER_TYPE;
+use profile::Profile;
+use overwrite::OverwritePolicy;
+use schedule::{Priority, Schedule, Scheduler};
+use stats::Counter;
+use shadow::Backend;
+use timer::Driver;
+
+enum_configuration! {
+    /// How conflicting schedules of the same member are resolved.
+    #[allow(non_camel_case_types)]
+    #[derive(Copy, PartialEq, Eq)]
+    enum MergePolicy {
+        // The schedule applied last wins.
+        arrival = 1,
+        // The schedule with the highest logical clock wins, then the one with the latest deadline.
+        clock = 2,
+    }
+}
+
 
 #[derive(Clone, Eq, PartialEq)]
 struct ExpiringMember {
-    expire_at: SystemTime,
-    key: String,
-    member: String,
+    expire_at: Deadline,
+    // Database of the key.
+    db: i32,
+    // Shared with the tracked expirations, the queue and the schedule, and between the members
+    // of a key.
+    key: Arc<str>,
+    member: Arc<str>,
+    // Logical clock of the schedule under the `clock` merge policy, 0 otherwise.
+    clock: u64,
+    priority: Priority,
+    // Unique to each schedule, so an overridden one is told apart from the tracked one even with
+    // the same deadline.
+    generation: u64,
+}
+
+impl ExpiringMember {
+    fn new(db: i32, key: impl Into<Arc<str>>, member: impl Into<Arc<str>>, expire_at: Deadline) -> Self {
+        let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+        ExpiringMember { expire_at, db, key: key.into(), member: member.into(), clock: 0, priority: Priority::Normal, generation }
+    }
 }
 
+/// Members grouped by (database, key).
+type MembersByKey = HashMap<(i32, Arc<str>), Vec<ExpiringMember>>;
+
 impl Ord for ExpiringMember {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.expire_at.cmp(&other.expire_at)
@@ -29,151 +121,2239 @@ impl PartialOrd for ExpiringMember {
     }
 }
 
+/// Schedules on their way to the worker. Unbounded, so a pipeline of any size is queued whole,
+/// the worker is woken to drain it once `queue-drain-threshold` schedules are waiting.
 struct ExpirationQueue {
-    queue: ArrayQueue<ExpiringMember>,
+    queue: SegQueue<ExpiringMember>,
 }
 
 impl ExpirationQueue {
-    fn new(capacity: usize) -> Self {
+    fn new() -> Self {
         ExpirationQueue {
-            queue: ArrayQueue::new(capacity),
+            queue: SegQueue::new(),
         }
     }
 
-    fn add_member(&self, member: ExpiringMember) -> Result<(), ExpiringMember> {
+    fn add_member(&self, member: ExpiringMember) {
         self.queue.push(member)
     }
 
     fn try_pop(&self) -> Option<ExpiringMember> {
         self.queue.pop()
     }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the worker should drain the queue now rather than when it wakes up by itself.
+    fn filling_up(&self) -> bool {
+        self.len() as i64 >= QUEUE_DRAIN_THRESHOLD.load(Ordering::Relaxed)
+    }
+}
+
+/// How long members still due after a cycle (paused or held back) wait for the next one, and the
+/// period the orphan check's effort is given for, `worker-interval`.
+fn worker_interval() -> Duration {
+    Duration::from_millis(WORKER_INTERVAL.load(Ordering::Relaxed).max(1) as u64)
+}
+
+/// Size below which the worker's schedule is not compacted, however many stale entries it holds.
+const COMPACTION_MIN_ENTRIES: usize = 1024;
+
+#[derive(Default)]
+struct WakeupState {
+    pending: bool,
+    // When the sleeping worker wakes up by itself, None while it is awake or parked.
+    until: Option<Deadline>,
+}
+
+/// Lets the worker sleep until its next deadline, and schedules with an earlier one wake it up.
+#[derive(Default)]
+struct Wakeup {
+    state: Mutex<WakeupState>,
+    condvar: Condvar,
+}
+
+impl Wakeup {
+    fn notify(&self) {
+        if timer::is_started() {
+            timer::arm(Deadline::now());
+            return;
+        }
+        self.state.lock().unwrap().pending = true;
+        self.condvar.notify_one();
+    }
+
+    /// Wakes the worker if it would sleep past `deadline`. An awake worker runs another cycle,
+    /// it may have drained the queue before the member got there.
+    fn notify_before(&self, deadline: Deadline) {
+        if timer::is_started() {
+            timer::arm(deadline);
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.until.is_none_or(|until| deadline < until) {
+            state.pending = true;
+            self.condvar.notify_one();
+        }
+    }
+
+    /// Sleeps until `until`, or for as long as it takes when None, or until notified since the
+    /// last call.
+    fn wait(&self, until: Option<Deadline>) {
+        let mut state = self.state.lock().unwrap();
+        state.until = until;
+        let mut state = match until {
+            Some(until) => self.condvar.wait_timeout_while(state, until.remaining(), |state| !state.pending).unwrap().0,
+            None => self.condvar.wait_while(state, |state| !state.pending).unwrap(),
+        };
+        state.pending = false;
+        state.until = None;
+    }
+}
+
+/// A shard of the tracked expirations by (database, key, member), indexed by key for the per-key
+/// lookups of keyspace notifications.
+#[derive(Default)]
+struct Shard {
+    entries: HashMap<(i32, Arc<str>, Arc<str>), ExpiringMember>,
+    // (database, key) → its tracked members. Holds the strings shared by the entries of a key.
+    keys: HashMap<(i32, Arc<str>), HashSet<Arc<str>>>,
+}
+
+impl Shard {
+    fn get(&self, db: i32, key: &str, member: &str) -> Option<&ExpiringMember> {
+        self.entries.get(&(db, Arc::from(key), Arc::from(member)))
+    }
+
+    /// Whether `member` is still the tracked schedule, i.e. was neither overridden nor cancelled
+    /// since.
+    fn is_current(&self, member: &ExpiringMember) -> bool {
+        self.entries.get(&(member.db, member.key.clone(), member.member.clone()))
+            .is_some_and(|tracked| tracked.generation == member.generation)
+    }
+
+    /// Tracks `member`, its key and member names replaced by those already tracked if any.
+    /// Returns the tracked copy.
+    fn insert(&mut self, mut member: ExpiringMember) -> ExpiringMember {
+        if let Some(((_, key), members)) = self.keys.get_key_value(&(member.db, member.key.clone())) {
+            member.key = key.clone();
+            if let Some(name) = members.get(&member.member) {
+                member.member = name.clone();
+            }
+        }
+        self.keys.entry((member.db, member.key.clone())).or_default().insert(member.member.clone());
+        self.entries.insert((member.db, member.key.clone(), member.member.clone()), member.clone());
+        member
+    }
+
+    fn remove(&mut self, db: i32, key: &str, member: &str) -> Option<ExpiringMember> {
+        let removed = self.entries.remove(&(db, Arc::from(key), Arc::from(member)))?;
+        unlink(&mut self.keys, db, &removed.key, &removed.member);
+        Some(removed)
+    }
+
+    /// The expirations tracked for `key` of database `db`.
+    fn of_key<'a>(&'a self, db: i32, key: &str) -> impl Iterator<Item = &'a ExpiringMember> {
+        self.keys.get_key_value(&(db, Arc::from(key))).into_iter()
+            .flat_map(move |((_, key), members)| members.iter().map(move |member| (key, member)))
+            .filter_map(move |(key, member)| self.entries.get(&(db, key.clone(), member.clone())))
+    }
+
+    /// Number of expirations tracked for `key` of database `db`.
+    fn key_len(&self, db: i32, key: &str) -> usize {
+        self.keys.get(&(db, Arc::from(key))).map_or(0, HashSet::len)
+    }
+
+    /// Forgets and returns the expirations tracked for `key` of database `db`.
+    fn remove_key(&mut self, db: i32, key: &str) -> Vec<ExpiringMember> {
+        let Some(((_, key), members)) = self.keys.remove_entry(&(db, Arc::from(key))) else {
+            return Vec::new();
+        };
+        members.into_iter()
+            .filter_map(|member| self.entries.remove(&(db, key.clone(), member)))
+            .collect()
+    }
+
+    /// Forgets the expirations of database `db`, returning how many there were.
+    fn remove_db(&mut self, db: i32) -> usize {
+        let count = self.entries.len();
+        self.entries.retain(|(entry_db, _, _), _| *entry_db != db);
+        self.keys.retain(|(entry_db, _), _| *entry_db != db);
+        count - self.entries.len()
+    }
+
+    /// Exchanges the expirations of databases `first` and `second`, as SWAPDB does with their keys.
+    fn swap_dbs(&mut self, first: i32, second: i32) {
+        let swap = |db: i32| if db == first { second } else if db == second { first } else { db };
+        self.entries = std::mem::take(&mut self.entries).into_iter()
+            .map(|((db, key, member), mut tracked)| {
+                tracked.db = swap(db);
+                ((tracked.db, key, member), tracked)
+            })
+            .collect();
+        self.keys = std::mem::take(&mut self.keys).into_iter()
+            .map(|((db, key), members)| ((swap(db), key), members))
+            .collect();
+    }
+
+    fn values(&self) -> impl Iterator<Item = &ExpiringMember> {
+        self.entries.values()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.keys.clear();
+    }
+
+    /// Number of entries missing from the key index plus index references to no entry.
+    fn index_errors(&self) -> usize {
+        let unindexed = self.entries.keys()
+            .filter(|(db, key, member)| !self.keys.get(&(*db, key.clone())).is_some_and(|members| members.contains(member)))
+            .count();
+        let dangling = self.keys.iter()
+            .flat_map(|((db, key), members)| members.iter().map(move |member| (*db, key.clone(), member.clone())))
+            .filter(|id| !self.entries.contains_key(id))
+            .count();
+        unindexed + dangling
+    }
+
+    fn rebuild_index(&mut self) {
+        self.keys.clear();
+        for (db, key, member) in self.entries.keys() {
+            self.keys.entry((*db, key.clone())).or_default().insert(member.clone());
+        }
+    }
+}
+
+/// Number of shards of the tracked expirations.
+const SHARDS: usize = 64;
+
+/// Tracked expirations, split into shards by key name with a lock each, so reads from the worker
+/// and background jobs only wait for writes to the same shard. A key has the same shard in every
+/// database, so MOVE and SWAPDB stay within shards.
+///
+/// Shards are locked one at a time, whole-state reads are therefore not a consistent snapshot
+/// when writes happen meanwhile. Writes only happen while holding the GIL.
+struct Expirations {
+    shards: Vec<RwLock<Shard>>,
+    hasher: RandomState,
+}
+
+impl Expirations {
+    fn new() -> Self {
+        Expirations {
+            shards: (0..SHARDS).map(|_| RwLock::new(Shard::default())).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn shard_index(&self, key: &str) -> usize {
+        self.hasher.hash_one(key) as usize % self.shards.len()
+    }
+
+    fn shard(&self, key: &str) -> &RwLock<Shard> {
+        &self.shards[self.shard_index(key)]
+    }
+
+    fn get(&self, db: i32, key: &str, member: &str) -> Option<ExpiringMember> {
+        self.shard(key).read().unwrap().get(db, key, member).cloned()
+    }
+
+    fn is_current(&self, member: &ExpiringMember) -> bool {
+        self.shard(&member.key).read().unwrap().is_current(member)
+    }
+
+    /// The current ones of `members`, checked with a single read lock per shard.
+    fn retain_current(&self, members: Vec<ExpiringMember>) -> Vec<ExpiringMember> {
+        let mut by_shard: Vec<Vec<ExpiringMember>> = self.shards.iter().map(|_| Vec::new()).collect();
+        for member in members {
+            by_shard[self.shard_index(&member.key)].push(member);
+        }
+        let mut current = Vec::new();
+        for (shard, members) in self.shards.iter().zip(by_shard).filter(|(_, members)| !members.is_empty()) {
+            let shard = shard.read().unwrap();
+            current.extend(members.into_iter().filter(|member| shard.is_current(member)));
+        }
+        current
+    }
+
+    /// Tracks `member`, returning the tracked copy which shares the names of the key's other
+    /// members.
+    fn insert(&self, member: ExpiringMember) -> ExpiringMember {
+        self.shard(&member.key).write().unwrap().insert(member)
+    }
+
+    fn remove(&self, db: i32, key: &str, member: &str) -> Option<ExpiringMember> {
+        self.shard(key).write().unwrap().remove(db, key, member)
+    }
+
+    /// The expirations tracked for `key` of database `db`.
+    fn of_key(&self, db: i32, key: &str) -> Vec<ExpiringMember> {
+        self.shard(key).read().unwrap().of_key(db, key).cloned().collect()
+    }
+
+    fn key_len(&self, db: i32, key: &str) -> usize {
+        self.shard(key).read().unwrap().key_len(db, key)
+    }
+
+    /// Forgets and returns the expirations tracked for `key` of database `db`.
+    fn remove_key(&self, db: i32, key: &str) -> Vec<ExpiringMember> {
+        self.shard(key).write().unwrap().remove_key(db, key)
+    }
+
+    /// Forgets the expirations of database `db`, returning how many there were.
+    fn remove_db(&self, db: i32) -> usize {
+        self.shards.iter().map(|shard| shard.write().unwrap().remove_db(db)).sum()
+    }
+
+    fn swap_dbs(&self, first: i32, second: i32) {
+        for shard in &self.shards {
+            shard.write().unwrap().swap_dbs(first, second);
+        }
+    }
+
+    /// Each shard read-locked in turn.
+    fn shards(&self) -> impl Iterator<Item = RwLockReadGuard<'_, Shard>> {
+        self.shards.iter().map(|shard| shard.read().unwrap())
+    }
+
+    /// The `index`-th shard, read-locked.
+    fn read_shard(&self, index: usize) -> RwLockReadGuard<'_, Shard> {
+        self.shards[index % self.shards.len()].read().unwrap()
+    }
+
+    /// `f` of every tracked expiration, those it returns None for left out.
+    fn collect<R>(&self, mut f: impl FnMut(&ExpiringMember) -> Option<R>) -> Vec<R> {
+        let mut collected = Vec::new();
+        for shard in self.shards() {
+            collected.extend(shard.values().filter_map(&mut f));
+        }
+        collected
+    }
+
+    /// A copy of every tracked expiration.
+    fn values(&self) -> Vec<ExpiringMember> {
+        self.collect(|tracked| Some(tracked.clone()))
+    }
+
+    fn len(&self) -> usize {
+        self.shards().map(|shard| shard.len()).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.shards().all(|shard| shard.is_empty())
+    }
+
+    fn clear(&self) {
+        for shard in &self.shards {
+            shard.write().unwrap().clear();
+        }
+    }
+
+    fn index_errors(&self) -> usize {
+        self.shards().map(|shard| shard.index_errors()).sum()
+    }
+
+    fn rebuild_index(&self) {
+        for shard in &self.shards {
+            shard.write().unwrap().rebuild_index();
+        }
+    }
+}
+
+fn unlink(keys: &mut HashMap<(i32, Arc<str>), HashSet<Arc<str>>>, db: i32, key: &Arc<str>, member: &str) {
+    let id = (db, key.clone());
+    if let Some(members) = keys.get_mut(&id) {
+        members.remove(member);
+        if members.is_empty() {
+            keys.remove(&id);
+        }
+    }
 }
 
 lazy_static! {
-    static ref EXPIRATION_QUEUE: Arc<ExpirationQueue> = Arc::new(ExpirationQueue::new(10000));
-    static ref EXPIRATION_TIMES: Mutex<HashMap<String, SystemTime>> = Mutex::new(HashMap::new());
+    static ref EXPIRATION_QUEUE: Arc<ExpirationQueue> = Arc::new(ExpirationQueue::new());
+    static ref WORKER_WAKEUP: Wakeup = Wakeup::default();
+    // Shards are read-locked by the worker (and the BGSAVE child), write-locked only while
+    // holding the GIL.
+    static ref EXPIRATION_TIMES: Expirations = Expirations::new();
     static ref THREAD_STARTED: AtomicBool = AtomicBool::new(false);
+    static ref NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+    // Set when EXPIRATION_TIMES was replaced wholesale, the worker then rebuilds its heap from it.
+    static ref HEAP_REBUILD: AtomicBool = AtomicBool::new(false);
+    // Longest the worker sleeps, in ms, when nothing is due sooner.
+    static ref MAX_SLEEP: AtomicI64 = AtomicI64::new(1000);
+    // See `worker_interval`, in ms.
+    static ref WORKER_INTERVAL: AtomicI64 = AtomicI64::new(100);
+    static ref SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::heap);
+    // Preset of the knobs below, see profile.rs.
+    static ref PROFILE: Mutex<Profile> = Mutex::new(Profile::custom);
+    // Width in ms of the buckets deadlines are rounded up to in the schedule, 0 for none.
+    static ref TTL_GRANULARITY: AtomicI64 = AtomicI64::new(0);
+    // Queued schedules at which the worker is woken to drain the queue right away.
+    static ref QUEUE_DRAIN_THRESHOLD: AtomicI64 = AtomicI64::new(5000);
+    // Most members the worker expires per GIL acquisition.
+    static ref EXPIRE_BATCH: AtomicI64 = AtomicI64::new(1000);
+    // Largest share of the time, in percent, the worker may hold the GIL.
+    static ref MAX_LOCK_PERCENT: AtomicI64 = AtomicI64::new(100);
+    // Most members the worker deletes per second, 0 for no limit.
+    static ref MAX_DELETIONS_PER_SECOND: AtomicI64 = AtomicI64::new(0);
+    // Threads deleting due members, the worker included.
+    static ref EXPIRE_THREADS: AtomicI64 = AtomicI64::new(1);
+    static ref DRIVER: Mutex<Driver> = Mutex::new(Driver::thread);
+    // Most expirations tracked at once, 0 for no limit.
+    static ref MAX_ENTRIES: Checked<AtomicI64, i64> = Checked::new(AtomicI64::new(0), config::check_max_entries);
+    static ref MAX_ENTRIES_POLICY: Mutex<MaxEntriesPolicy> = Mutex::new(MaxEntriesPolicy::reject);
+    // Most expirations tracked at once for a single key, 0 for no limit.
+    static ref MAX_MEMBERS_PER_KEY: Checked<AtomicI64, i64> = Checked::new(AtomicI64::new(0), config::check_max_members_per_key);
+    // Overdue members left scheduled at which new schedules are rejected, 0 to never reject.
+    static ref BACKLOG_THRESHOLD: AtomicI64 = AtomicI64::new(0);
+    // Keys whose expired members are counted for EXPIREMEMBER.STATS KEYS, 0 disables the counts.
+    static ref STATS_KEYS: AtomicI64 = AtomicI64::new(1000);
+    // Time in ms without an expiry cycle after which the worker is reported stalled, 0 disables the check.
+    static ref WATCHDOG_TIMEOUT: AtomicI64 = AtomicI64::new(30_000);
+    // Whether every expiration is logged, see `trace`.
+    static ref TRACE: AtomicBool = AtomicBool::new(false);
+    // File expirations are traced to, appended to, empty for the server log.
+    static ref TRACE_FILE: Mutex<String> = Mutex::new(String::new());
+    // Most expirations traced per second.
+    static ref TRACE_RATE: AtomicI64 = AtomicI64::new(1000);
+    // host:port the worker sends StatsD metrics to, empty disables them.
+    static ref STATSD_ADDRESS: Checked<Mutex<String>, RedisString> = Checked::new(Mutex::new(String::new()), config::check_statsd_address);
+    // Prepended to the StatsD metric names, with a dot.
+    static ref STATSD_PREFIX: Mutex<String> = Mutex::new("expiremember".to_string());
+    // Prepended to the name of every command, see commands.rs.
+    static ref COMMAND_PREFIX: Checked<Mutex<String>, RedisString> = Checked::new(Mutex::new(String::new()), config::check_command_prefix);
+    // How often, in ms, StatsD metrics are sent.
+    static ref STATSD_INTERVAL: AtomicI64 = AtomicI64::new(10_000);
+
+    // Pub/Sub channel expiry events are published to, empty disables events.
+    static ref EVENTS_CHANNEL: Checked<Mutex<String>, RedisString> = Checked::new(Mutex::new(String::new()), config::check_events_channel);
+    // Whether members of hashes, sets, sorted sets, lists and JSON documents can expire, see
+    // `Container::of`.
+    static ref TRACK_HASHES: AtomicBool = AtomicBool::new(true);
+    static ref TRACK_SETS: AtomicBool = AtomicBool::new(true);
+    static ref TRACK_ZSETS: AtomicBool = AtomicBool::new(true);
+    static ref TRACK_LISTS: AtomicBool = AtomicBool::new(true);
+    static ref TRACK_JSON: AtomicBool = AtomicBool::new(true);
+    // Stream expiry events are appended to, empty disables them, and about how many it keeps.
+    static ref EVENTS_STREAM: Checked<Mutex<String>, RedisString> = Checked::new(Mutex::new(String::new()), config::check_events_stream);
+    static ref EVENTS_STREAM_MAXLEN: AtomicI64 = AtomicI64::new(10_000);
+    // Class of the keyspace event sent for keys whose members expired, none by default.
+    static ref KEYSPACE_EVENT_CLASS: Checked<Mutex<EventClass>, EventClass> = Checked::new(Mutex::new(EventClass::none), config::check_keyspace_event_class);
+    // Rules file watched for changes, empty for none, see rules.rs.
+    static ref CONFIG_FILE: Checked<Mutex<String>, RedisString> = Checked::new(Mutex::new(String::new()), config::check_config_file);
+    // Whether expiry events carry the member's value (hash field value, zset score).
+    static ref EVENTS_INCLUDE_VALUE: AtomicBool = AtomicBool::new(false);
+    // Redis Function called as `FCALL <name> 1 <key> <member>` for every expired member.
+    static ref EXPIRE_FUNCTION: Mutex<String> = Mutex::new(String::new());
+    // Loaded Lua script called as `EVALSHA <sha> 1 <key> <member>` for every expired member.
+    static ref EXPIRE_SCRIPT_SHA: Checked<Mutex<String>, RedisString> = Checked::new(Mutex::new(String::new()), config::check_script_sha);
+    // Number of recent expirations retained for EXPIREMEMBER.SUBSCRIBE, 0 disables the log.
+    static ref EVENT_LOG_SIZE: Checked<AtomicI64, i64> = Checked::new(AtomicI64::new(0), config::check_event_log_size);
+    // Replicas leave deletions to their primary, kept current by role change events.
+    static ref IS_REPLICA: AtomicBool = AtomicBool::new(false);
+    // Whether background deletions wait while a fork child (BGSAVE, AOF rewrite, full sync) runs.
+    static ref PAUSE_DURING_FORK: AtomicBool = AtomicBool::new(false);
+    // Set by EXPIREMEMBER.DEBUG PAUSE, background deletions then wait for RESUME.
+    static ref EXPIRY_PAUSED: AtomicBool = AtomicBool::new(false);
+    // Resolution of conflicting schedules, for active-active setups.
+    static ref MERGE_POLICY: Mutex<MergePolicy> = Mutex::new(MergePolicy::arrival);
+    // Highest logical clock seen, see `advance_clock`.
+    static ref LOGICAL_CLOCK: AtomicU64 = AtomicU64::new(0);
+    // Tracked expirations checked for orphans per worker cycle, 0 disables the check.
+    static ref GC_EFFORT: AtomicI64 = AtomicI64::new(100);
+    // Whether COPY also copies the member expirations of the source key.
+    static ref COPY_EXPIRATIONS: AtomicBool = AtomicBool::new(false);
+    // Whether EXPIREMEMBER and EXPIREMEMBERAT behave like KeyDB's, see `keydb`.
+    static ref KEYDB_COMPAT: AtomicBool = AtomicBool::new(false);
+    // Set while the module removes a member itself, whose notifications are then ignored.
+    static ref REMOVING_MEMBER: AtomicBool = AtomicBool::new(false);
+    // Source key of the RENAME being notified, between its `rename_from` and `rename_to` events.
+    static ref RENAME_SOURCE: Mutex<Option<String>> = Mutex::new(None);
+    // Source database and key of the MOVE being notified, between its `move_from` and `move_to` events.
+    static ref MOVE_SOURCE: Mutex<Option<(i32, String)>> = Mutex::new(None);
+    // Storage backend, fixed at load time.
+    static ref BACKEND: Mutex<Backend> = Mutex::new(Backend::memory);
+
+    static ref EVENT_LOG: Mutex<EventLog> = Mutex::new(EventLog::new());
+}
+
+struct ExpiryEvent {
+    id: u64,
+    key: Arc<str>,
+    member: Arc<str>,
+    expired_at: u64,
+}
+
+impl ExpiryEvent {
+    fn to_redis_value(&self) -> RedisValue {
+        RedisValue::Array(vec![
+            RedisValue::Integer(self.id as i64),
+            RedisValue::BulkString(self.key.to_string()),
+            RedisValue::BulkString(self.member.to_string()),
+            RedisValue::Integer(self.expired_at as i64),
+        ])
+    }
+}
+
+struct Subscriber {
+    cursor: u64,
+    count: usize,
+    deadline: Option<Deadline>,
+    client: BlockedClient,
+}
+
+/// Bounded log of recent expirations, read by EXPIREMEMBER.SUBSCRIBE with a cursor.
+struct EventLog {
+    events: VecDeque<ExpiryEvent>,
+    last_id: u64,
+    subscribers: Vec<Subscriber>,
+}
+
+impl EventLog {
+    fn new() -> Self {
+        EventLog {
+            events: VecDeque::new(),
+            last_id: 0,
+            subscribers: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, member: &ExpiringMember, capacity: usize) {
+        self.last_id += 1;
+        self.events.push_back(ExpiryEvent {
+            id: self.last_id,
+            key: member.key.clone(),
+            member: member.member.clone(),
+            expired_at: member.expire_at.unix_ms(),
+        });
+        while self.events.len() > capacity {
+            self.events.pop_front();
+        }
+    }
+
+    /// Reply with the events following `cursor`: `[next_cursor, [[id, key, member, expired_at], ...]]`.
+    fn read(&self, cursor: u64, count: usize) -> RedisValue {
+        let events: Vec<RedisValue> = self.events.iter()
+            .filter(|event| event.id > cursor)
+            .take(count)
+            .map(ExpiryEvent::to_redis_value)
+            .collect();
+        let next_cursor = match events.last() {
+            Some(RedisValue::Array(fields)) => fields[0].clone(),
+            _ => RedisValue::Integer(cursor.max(self.last_id) as i64),
+        };
+        RedisValue::Array(vec![next_cursor, RedisValue::Array(events)])
+    }
+
+    /// Unblocks the subscribers that have new events or whose timeout has passed.
+    fn wake_subscribers(&mut self) {
+        let now = Deadline::now();
+        let (ready, waiting): (Vec<Subscriber>, Vec<Subscriber>) = self.subscribers.drain(..)
+            .partition(|sub| sub.cursor < self.last_id || sub.deadline.is_some_and(|deadline| deadline <= now));
+        self.subscribers = waiting;
+        for sub in ready {
+            let reply = if sub.cursor < self.last_id { self.read(sub.cursor, sub.count) } else { RedisValue::Null };
+            let thread_ctx = ThreadSafeContext::with_blocked_client(sub.client);
+            thread_ctx.reply(Ok(reply));
+        }
+    }
+
+    /// When the first blocked subscriber times out.
+    fn next_timeout(&self) -> Option<Deadline> {
+        self.subscribers.iter().filter_map(|sub| sub.deadline).min()
+    }
 }
 
 fn expiremember(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    if args.len() != 4 && args.len() != 5 {
+    if args.len() >= 4 && KEYDB_COMPAT.load(Ordering::Relaxed) {
+        return keydb::expiremember(ctx, &args);
+    }
+    if args.len() < 4 || args.len() > 7 {
         return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember' command"));
     }
 
     let key = args[1].to_string();
     let member = args[2].to_string();
     let expire_value = args[3].parse_integer()?;
-    
-    let unit = if args.len() == 5 { args[4].to_string().to_lowercase() } else { "s".to_string() };
-
-    let expire_at = match unit.as_str() {
-        "s" => SystemTime::now() + Duration::from_secs(expire_value as u64),
-        "ms" => SystemTime::now() + Duration::from_millis(expire_value as u64),
-        _ => return Err(RedisError::Str("ERR invalid time unit for 'expiremember' command")),
+    if expire_value < -1 {
+        return Err(RedisError::Str("ERR invalid expire time in 'expiremember' command"));
+    }
+    let (unit, options) = split_unit(&args[4..]);
+    let ttl = parse_duration(expire_value.max(0), unit, "expiremember")?;
+    let priority = match options {
+        [] => Priority::Normal,
+        [option, priority] if option.to_string().eq_ignore_ascii_case("priority") => parse_priority(priority)?,
+        _ => return Err(RedisError::Str("ERR syntax error")),
     };
 
-    let mut expiration_times = EXPIRATION_TIMES.lock().unwrap();
     match expire_value {
         -1 => {
-            expiration_times.remove(&(key.clone() + &member));
-            return Ok(RedisValue::Integer(0));
-        }
-        0 => {
-            let redis_string_key = ctx.create_string(key.as_bytes());
-            let opened_key = ctx.open_key_writable(&redis_string_key);
-            match opened_key.key_type() {
-                KeyType::Hash => { let _ = opened_key.hash_del(&member); },
-                KeyType::ZSet => { 
-                    let redis_string_member = ctx.create_string(member.as_bytes());
-                    let _ = ctx.call("ZREM", &[&redis_string_key, &redis_string_member]);
-                },
-                KeyType::Set => { 
-                    let redis_string_member = ctx.create_string(member.as_bytes());
-                    let _ = ctx.call("SREM", &[&redis_string_key, &redis_string_member]);
-                },
-                KeyType::Empty => {
-                }
-                _ => return Err(RedisError::Str("ERR key type not supported for 'expiremember' command")),
+            if EXPIRATION_TIMES.remove(selected_db(ctx), &key, &member).is_some() {
+                stats::add(Counter::Cancelled, 1);
             }
-            expiration_times.remove(&(key.clone() + &member));
-            return Ok(RedisValue::Integer(1));
+            shadow::forget(ctx, *BACKEND.lock().unwrap(), &key, &member);
+            ctx.replicate_verbatim();
+            Ok(RedisValue::Integer(0))
         }
+        0 => delete_member(ctx, key, member),
+        // Relative to the start of the transaction or script, like EXPIRE.
         _ => {
-            expiration_times.insert(key.clone() + &member, expire_at);
+            let expire_at = Deadline::command_start().checked_add(ttl)
+                .ok_or(RedisError::Str("ERR invalid expire time in 'expiremember' command"))?;
+            schedule_member(ctx, ExpiringMember { priority, ..ExpiringMember::new(selected_db(ctx), key, member, expire_at) })
+        }
+    }
+}
+
+/// EXPIREMEMBERAT key member timestamp [unit] [CLOCK clock] [PRIORITY high|normal|low]
+///
+/// Like EXPIREMEMBER but with an absolute Unix time, a timestamp in the past deletes the member right away.
+/// CLOCK carries the logical clock of a schedule made elsewhere, see the `clock` merge policy.
+fn expirememberat(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() == 4 && KEYDB_COMPAT.load(Ordering::Relaxed) {
+        return keydb::expirememberat(ctx, &args, 1000);
+    }
+    if args.len() < 4 || args.len() > 9 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expirememberat' command"));
+    }
+
+    let key = args[1].to_string();
+    let member = args[2].to_string();
+    let timestamp = args[3].parse_integer()?;
+    if timestamp < 0 {
+        return Err(RedisError::Str("ERR invalid expire time in 'expirememberat' command"));
+    }
+
+    let (unit, options) = split_unit(&args[4..]);
+    let (mut clock, mut priority) = (0, Priority::Normal);
+    for option in options.chunks(2) {
+        match option {
+            [option, value] if option.to_string().eq_ignore_ascii_case("clock") => clock = value.parse_integer()
+                .ok()
+                .filter(|clock| *clock > 0)
+                .ok_or(RedisError::Str("ERR invalid clock in 'expirememberat' command"))? as u64,
+            [option, value] if option.to_string().eq_ignore_ascii_case("priority") => priority = parse_priority(value)?,
+            _ => return Err(RedisError::Str("ERR syntax error")),
         }
     }
-    drop(expiration_times);
+    let expire_at = Deadline::checked_from_unix(parse_duration(timestamp, unit, "expirememberat")?)
+        .ok_or(RedisError::Str("ERR invalid expire time in 'expirememberat' command"))?;
+
+    if expire_at <= Deadline::command_start() {
+        delete_member(ctx, key, member)
+    } else {
+        schedule_member(ctx, ExpiringMember { clock, priority, ..ExpiringMember::new(selected_db(ctx), key, member, expire_at) })
+    }
+}
+
+/// PEXPIREMEMBERAT key member timestamp
+///
+/// EXPIREMEMBERAT with the timestamp in milliseconds, as in KeyDB.
+fn pexpirememberat(ctx: &Context, mut args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 4 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'pexpirememberat' command"));
+    }
+    if KEYDB_COMPAT.load(Ordering::Relaxed) {
+        return keydb::expirememberat(ctx, &args, 1);
+    }
+    args.push(ctx.create_string("ms"));
+    expirememberat(ctx, args)
+}
+
+/// The time unit leading `options`, if any, and the options after it.
+fn split_unit(options: &[RedisString]) -> (Option<&RedisString>, &[RedisString]) {
+    match options.split_first() {
+        Some((unit, rest)) if !["clock", "priority"].iter().any(|option| unit.to_string().eq_ignore_ascii_case(option)) => (Some(unit), rest),
+        _ => (None, options),
+    }
+}
 
-    let expiring_member = ExpiringMember { expire_at, key, member };
-    let _ = EXPIRATION_QUEUE.add_member(expiring_member);
+fn parse_priority(priority: &RedisString) -> Result<Priority, RedisError> {
+    Priority::parse(&priority.to_string()).ok_or(RedisError::Str("ERR invalid priority, expected high, normal or low"))
+}
 
-    if !THREAD_STARTED.load(Ordering::SeqCst) {
-        start_expiration_thread();
-        THREAD_STARTED.store(true, Ordering::SeqCst);
+/// `value` in `unit`, seconds by default. Fails for negative values.
+fn parse_duration(value: i64, unit: Option<&RedisString>, command: &str) -> Result<Duration, RedisError> {
+    if value < 0 {
+        return Err(RedisError::String(format!("ERR invalid expire time in '{}' command", command)));
     }
+    let unit = unit.map_or_else(|| "s".to_string(), |unit| unit.to_string().to_lowercase());
+    match unit.as_str() {
+        "s" => Ok(Duration::from_secs(value as u64)),
+        "ms" => Ok(Duration::from_millis(value as u64)),
+        _ => Err(RedisError::String(format!("ERR invalid time unit for '{}' command", command))),
+    }
+}
 
+/// Deletes the member of `key` in the selected database right away and forgets its expiration.
+fn delete_member(ctx: &Context, key: String, member: String) -> RedisResult {
+    let container = Container::of(ctx, &key)?;
+    // Not held while removing: deleting the last member fires a `del` notification, see `key_event`.
+    EXPIRATION_TIMES.remove(selected_db(ctx), &key, &member);
+    if let Some(container) = container {
+        container.remove(ctx, &key, &member);
+    }
+    shadow::forget(ctx, *BACKEND.lock().unwrap(), &key, &member);
     Ok(RedisValue::Integer(1))
 }
 
-fn start_expiration_thread() {
-    thread::spawn(move || {
-        let thread_ctx = ThreadSafeContext::new();
-        let mut heap = BinaryHeap::new();
-        loop {
-            let now = SystemTime::now();
-            let mut members_to_expire = HashMap::new();
+/// Key types whose members can expire. The members of a list are its elements, by value: an
+/// expiring element is the first occurrence of its value when it expires. Those of a RedisJSON
+/// document are paths, deleted with JSON.DEL.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Hash,
+    Set,
+    ZSet,
+    List,
+    Json,
+}
+
+/// Type name of RedisJSON documents, as TYPE reports it.
+const JSON_TYPE: &str = "ReJSON-RL";
+
+impl Container {
+    /// The type of `key`, None if it does not exist. The key is not kept open, so it never
+    /// outlives the commands later run against it. Fails for types disabled with `track-hashes`,
+    /// `track-sets`, `track-zsets`, `track-lists` or `track-json`, whose members are then never
+    /// scheduled nor removed.
+    fn of(ctx: &Context, key: &str) -> Result<Option<Container>, RedisError> {
+        Container::type_of(ctx, key)?.map(Container::tracked).transpose()
+    }
+
+    /// Like `of`, but whether or not the type is disabled, see `is_tracked`.
+    fn type_of(ctx: &Context, key: &str) -> Result<Option<Container>, RedisError> {
+        let container = match ctx.open_key(&ctx.create_string(key.as_bytes())).key_type() {
+            KeyType::Hash => Container::Hash,
+            KeyType::Set => Container::Set,
+            KeyType::ZSet => Container::ZSet,
+            KeyType::List => Container::List,
+            KeyType::Module if matches!(ctx.call("TYPE", &[key]), Ok(RedisValue::SimpleString(name)) if name == JSON_TYPE) => Container::Json,
+            KeyType::Empty => return Ok(None),
+            _ => return Err(RedisError::WrongType),
+        };
+        Ok(Some(container))
+    }
+
+    /// Whether members of this type can expire, i.e. it is not disabled by its `track-*`.
+    fn is_tracked(self) -> bool {
+        self.tracked().is_ok()
+    }
 
-            while let Some(member) = EXPIRATION_QUEUE.try_pop() {
-                heap.push(Reverse(member));
+    /// This type, failing if disabled by its `track-*`.
+    fn tracked(self) -> Result<Container, RedisError> {
+        let (tracked, config, members) = match self {
+            Container::Hash => (&*TRACK_HASHES, "track-hashes", "hash fields"),
+            Container::Set => (&*TRACK_SETS, "track-sets", "set members"),
+            Container::ZSet => (&*TRACK_ZSETS, "track-zsets", "sorted set members"),
+            Container::List => (&*TRACK_LISTS, "track-lists", "list elements"),
+            Container::Json => (&*TRACK_JSON, "track-json", "JSON paths"),
+        };
+        if !tracked.load(Ordering::Relaxed) {
+            return Err(RedisError::String(format!("ERR expirations of {} are disabled by expiremember.{}", members, config)));
+        }
+        Ok(self)
+    }
+
+    /// Removes `member` with a regular HDEL, SREM, ZREM, `LREM key 1` or JSON.DEL, propagated
+    /// verbatim to replicas and the AOF. It advances the replication offset like any write and shows up in
+    /// MONITOR.
+    ///
+    /// Returns whether the member existed.
+    fn remove(self, ctx: &Context, key: &str, member: &str) -> bool {
+        let (command, args) = match self {
+            Container::Hash => ("HDEL", vec![key, member]),
+            Container::Set => ("SREM", vec![key, member]),
+            Container::ZSet => ("ZREM", vec![key, member]),
+            Container::List => ("LREM", vec![key, "1", member]),
+            Container::Json => ("JSON.DEL", vec![key, member]),
+        };
+        let options = CallOptionsBuilder::new().replicate().build();
+        REMOVING_MEMBER.store(true, Ordering::Relaxed);
+        let reply: CallResult = ctx.call_ext(command, &options, args.as_slice());
+        REMOVING_MEMBER.store(false, Ordering::Relaxed);
+        matches!(reply, Ok(CallReply::I64(removed)) if removed.to_i64() >= 1)
+    }
+
+    /// Removes `members` with a single variadic HDEL, SREM or ZREM, propagated like `remove`.
+    /// Sorted sets go through the zset API instead when the server has it, list elements and JSON
+    /// paths are removed one `LREM` or JSON.DEL at a time. Fails with the server's error if it
+    /// refused the removal.
+    fn remove_all(self, ctx: &Context, key: &str, members: &[&str]) -> Result<(), String> {
+        if self == Container::ZSet && zset_remove(ctx, key, members) {
+            return Ok(());
+        }
+        let command = match self {
+            Container::Hash => "HDEL",
+            Container::Set => "SREM",
+            Container::ZSet => "ZREM",
+            Container::List | Container::Json => return self.remove_each(ctx, key, members),
+        };
+        let args: Vec<&str> = std::iter::once(key).chain(members.iter().copied()).collect();
+        let options = CallOptionsBuilder::new().replicate().build();
+        REMOVING_MEMBER.store(true, Ordering::Relaxed);
+        let result: CallResult = ctx.call_ext(command, &options, args.as_slice());
+        REMOVING_MEMBER.store(false, Ordering::Relaxed);
+        result.map(|_| ()).map_err(|err| err.to_string())
+    }
+
+    /// Removes `members` of a list or JSON document one command each, as `remove` does, for want
+    /// of a variadic one. Stops at the first the server refuses.
+    fn remove_each(self, ctx: &Context, key: &str, members: &[&str]) -> Result<(), String> {
+        let options = CallOptionsBuilder::new().replicate().build();
+        REMOVING_MEMBER.store(true, Ordering::Relaxed);
+        let result = members.iter().try_for_each(|member| {
+            let result: CallResult = match self {
+                Container::Json => ctx.call_ext("JSON.DEL", &options, &[key, member]),
+                _ => ctx.call_ext("LREM", &options, &[key, "1", member]),
+            };
+            result.map(|_| ()).map_err(|err| err.to_string())
+        });
+        REMOVING_MEMBER.store(false, Ordering::Relaxed);
+        result
+    }
+
+    /// Which of `members` exist, with their value (as for `value`) if so, looked up with a single
+    /// HMGET, SMISMEMBER or ZMSCORE. Member by member on servers before 6.2, and for list
+    /// elements and JSON paths, which have no such command.
+    fn lookup(self, ctx: &Context, key: &str, members: &[&str]) -> Vec<Option<Option<String>>> {
+        let command = match self {
+            Container::Hash => "HMGET",
+            Container::Set => "SMISMEMBER",
+            Container::ZSet => "ZMSCORE",
+            Container::List | Container::Json => return self.lookup_each(ctx, key, members),
+        };
+        let args: Vec<&str> = std::iter::once(key).chain(members.iter().copied()).collect();
+        let options = CallOptionsBuilder::new().build();
+        let reply: CallResult = ctx.call_ext(command, &options, args.as_slice());
+        match reply {
+            Ok(CallReply::Array(replies)) if replies.len() == members.len() => replies.iter()
+                .map(|reply| match reply {
+                    Ok(CallReply::Null(_)) => None,
+                    Ok(CallReply::I64(found)) => (found.to_i64() == 1).then_some(None),
+                    Ok(CallReply::String(value)) => Some(value.to_string()),
+                    _ => Some(None),
+                })
+                .collect(),
+            _ => self.lookup_each(ctx, key, members),
+        }
+    }
+
+    fn lookup_each(self, ctx: &Context, key: &str, members: &[&str]) -> Vec<Option<Option<String>>> {
+        members.iter()
+            .map(|member| self.contains(ctx, key, member).then(|| self.value(ctx, key, member)))
+            .collect()
+    }
+
+    fn contains(self, ctx: &Context, key: &str, member: &str) -> bool {
+        let reply = match self {
+            Container::Hash => ctx.call("HEXISTS", &[key, member]),
+            Container::Set => ctx.call("SISMEMBER", &[key, member]),
+            Container::ZSet => return !matches!(ctx.call("ZSCORE", &[key, member]), Ok(RedisValue::Null)),
+            Container::List => {
+                let reply: CallResult = ctx.call_ext("LPOS", &CallOptionsBuilder::new().build(), &[key, member]);
+                return matches!(reply, Ok(CallReply::I64(_)));
             }
+            // An array of the types of the matches for `$` paths, a type or nil for legacy ones.
+            Container::Json => return match ctx.call("JSON.TYPE", &[key, member]) {
+                Ok(RedisValue::Array(types)) => !types.is_empty(),
+                Ok(RedisValue::SimpleString(_) | RedisValue::BulkString(_)) => true,
+                _ => false,
+            },
+        };
+        matches!(reply, Ok(RedisValue::Integer(1)))
+    }
 
-            while let Some(Reverse(member)) = heap.peek() {
-                if member.expire_at > now {
-                    break;
-                }
+    /// Current value of a member: the field value for hashes, the score for zsets, the JSON
+    /// serialization for JSON paths.
+    fn value(self, ctx: &Context, key: &str, member: &str) -> Option<String> {
+        let command = match self {
+            Container::Hash => "HGET",
+            Container::ZSet => "ZSCORE",
+            Container::Json => "JSON.GET",
+            Container::Set | Container::List => return None,
+        };
+        match ctx.call(command, &[key, member]) {
+            Ok(RedisValue::SimpleString(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
 
-                if let Some(&expiration_time) = EXPIRATION_TIMES.lock().unwrap().get(&(member.key.clone() + &member.member)) {
-                    if expiration_time == member.expire_at {
-                        members_to_expire.entry(member.key.clone())
-                                         .or_insert_with(Vec::new)
-                                         .push(member.clone());
-                    }
-                }
-                heap.pop();
-            }
-
-            if !members_to_expire.is_empty() {
-                let ctx: redis_module::ContextGuard = thread_ctx.lock();
-                for (key, members) in &members_to_expire {
-                    let redis_string_key = ctx.create_string(key.as_bytes());
-                    let key = ctx.open_key_writable(&redis_string_key);
-                    match key.key_type() {
-                        KeyType::Hash => {
-                            for member in members {
-                                key.hash_del(&member.member);
-                            }
-                        },
-                        KeyType::ZSet => {
-                            for member in members {
-                                let redis_string_member = ctx.create_string(member.member.as_bytes());
-                                let _ = ctx.call("ZREM", &[&redis_string_key, &redis_string_member]);
-                            }
-                        },
-                        KeyType::Set => {
-                            for member in members {
-                                let redis_string_member = ctx.create_string(member.member.as_bytes());
-                                let _ = ctx.call("SREM", &[&redis_string_key, &redis_string_member]);
-                            }
-                        },
-                        _ => continue,
-                    }
-                }
-                drop(ctx);
+/// Removes `members` of the sorted set `key` through the zset API, sparing the command call and
+/// its reply. Replicated and notified like a ZREM, an emptied key like its deletion. False if
+/// the server lacks the API.
+fn zset_remove(ctx: &Context, key: &str, members: &[&str]) -> bool {
+    let Some(zset_rem) = (unsafe { raw::RedisModule_ZsetRem }) else {
+        return false;
+    };
+    let name = ctx.create_string(key.as_bytes());
+    let mut removed = 0;
+    let opened = raw::open_key(ctx.ctx, name.inner, raw::KeyMode::READ | raw::KeyMode::WRITE);
+    for member in members {
+        let member = ctx.create_string(member.as_bytes());
+        let mut deleted: c_int = 0;
+        if unsafe { zset_rem(opened, member.inner, &mut deleted) } == raw::REDISMODULE_OK as c_int && deleted == 1 {
+            removed += 1;
+        }
+    }
+    // An emptied key is deleted on close.
+    raw::close_key(opened);
+    if removed == 0 {
+        return true;
+    }
+
+    let args: Vec<&str> = std::iter::once(key).chain(members.iter().copied()).collect();
+    ctx.replicate("ZREM", args.as_slice());
+    REMOVING_MEMBER.store(true, Ordering::Relaxed);
+    ctx.notify_keyspace_event(NotifyEvent::ZSET, "zrem", &name);
+    if matches!(Container::of(ctx, key), Ok(None)) {
+        ctx.notify_keyspace_event(NotifyEvent::GENERIC, "del", &name);
+    }
+    REMOVING_MEMBER.store(false, Ordering::Relaxed);
+    true
+}
+
+/// Tracks the expiration and propagates it as an absolute `EXPIREMEMBERAT`, so replicas
+/// and AOF replays compute the same deadline regardless of when they apply it. `ctx` must have
+/// the database of the member selected. Fails with WRONGTYPE for keys of a type whose members
+/// cannot expire.
+///
+/// Under the `clock` merge policy, a schedule older than the tracked one (by clock, then by
+/// deadline) is ignored and 0 returned, so instances applying each other's schedules in any
+/// order agree on the outcome.
+fn schedule_member(ctx: &Context, mut expiring_member: ExpiringMember) -> RedisResult {
+    Container::of(ctx, &expiring_member.key)?;
+    let clock_policy = *MERGE_POLICY.lock().unwrap() == MergePolicy::clock;
+    if clock_policy {
+        expiring_member.clock = advance_clock(expiring_member.clock);
+        if let Some(tracked) = EXPIRATION_TIMES.get(expiring_member.db, &expiring_member.key, &expiring_member.member) {
+            if (tracked.clock, tracked.expire_at) >= (expiring_member.clock, expiring_member.expire_at) {
+                return Ok(RedisValue::Integer(0));
             }
+        }
+    }
+    // Replicas and the AOF being loaded track whatever the primary did.
+    if !IS_REPLICA.load(Ordering::SeqCst) && !ctx.get_flags().intersects(ContextFlags::REPLICATED | ContextFlags::LOADING) {
+        #[cfg(feature = "rules")]
+        rules::check_excluded(&expiring_member.key)?;
+        limits::check_memory(ctx, &expiring_member)?;
+        limits::check_backlog(ctx)?;
+        limits::make_room(ctx, &expiring_member)?;
+    }
+
+    replicate_schedule(ctx, &expiring_member, clock_policy);
+
+    shadow::store(ctx, *BACKEND.lock().unwrap(), &expiring_member);
+    let overridden = EXPIRATION_TIMES.get(expiring_member.db, &expiring_member.key, &expiring_member.member).is_some();
+    stats::add(if overridden { Counter::Overridden } else { Counter::Scheduled }, 1);
+    let expiring_member = EXPIRATION_TIMES.insert(expiring_member);
+
+    let expire_at = expiring_member.expire_at;
+    EXPIRATION_QUEUE.add_member(expiring_member);
+
+    if EXPIRATION_QUEUE.filling_up() {
+        stats::add(Counter::QueueOverflows, 1);
+        WORKER_WAKEUP.notify();
+    } else {
+        WORKER_WAKEUP.notify_before(expire_at);
+    }
+
+    Ok(RedisValue::Integer(1))
+}
+
+/// Propagates the schedule of `member` as `EXPIREMEMBERAT key member <unix-ms> ms`, with its clock
+/// under the `clock` merge policy and its priority unless normal. Must hold the GIL, with the
+/// database of `member` selected.
+fn replicate_schedule(ctx: &Context, member: &ExpiringMember, clock_policy: bool) {
+    let expire_at_ms = member.expire_at.unix_ms().to_string();
+    let clock = member.clock.to_string();
+    let mut replicated = vec![&*member.key, &*member.member, expire_at_ms.as_str(), "ms"];
+    if clock_policy {
+        replicated.extend(["CLOCK", clock.as_str()]);
+    }
+    if member.priority != Priority::Normal {
+        replicated.extend(["PRIORITY", member.priority.name()]);
+    }
+    ctx.replicate(&commands::name("EXPIREMEMBERAT"), replicated.as_slice());
+}
+
+/// Hybrid logical clock: a local schedule (`received` 0) gets a clock past every clock seen so
+/// far and at least the current Unix time in ms, a received clock is returned as is and merged in.
+fn advance_clock(received: u64) -> u64 {
+    if received > 0 {
+        LOGICAL_CLOCK.fetch_max(received, Ordering::SeqCst);
+        return received;
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+    let previous = LOGICAL_CLOCK.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |clock| Some((clock + 1).max(now))).unwrap();
+    (previous + 1).max(now)
+}
+
+/// EXPIREMEMBER.POLICY key [KEEP | CLEAR | RESET ttl [unit]]
+///
+/// Sets what happens to the expiration of a tracked member of `key` when a command writes the
+/// member again, or without a policy returns the current one.
+fn expiremember_policy(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() < 2 || args.len() > 5 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.policy' command"));
+    }
+    let db = selected_db(ctx);
+    let key = args[1].to_string();
+    let Some(policy) = args.get(2) else {
+        return Ok(match overwrite::get(db, &key) {
+            OverwritePolicy::Keep => RedisValue::Array(vec![RedisValue::SimpleStringStatic("keep")]),
+            OverwritePolicy::Clear => RedisValue::Array(vec![RedisValue::SimpleStringStatic("clear")]),
+            OverwritePolicy::Reset(ttl) => RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("reset"),
+                RedisValue::Integer(ttl.as_millis() as i64),
+            ]),
+        });
+    };
 
-            thread::sleep(Duration::from_millis(100));
+    let policy = match (policy.to_string().to_lowercase().as_str(), args.len()) {
+        ("keep", 3) => OverwritePolicy::Keep,
+        ("clear", 3) => OverwritePolicy::Clear,
+        ("reset", 4 | 5) => {
+            let ttl = args[3].parse_integer()?;
+            if ttl <= 0 {
+                return Err(RedisError::Str("ERR invalid expire time in 'expiremember.policy' command"));
+            }
+            let ttl = parse_duration(ttl, args.get(4), "expiremember.policy")?;
+            if ttl > deadline::MAX_AHEAD {
+                return Err(RedisError::Str("ERR invalid expire time in 'expiremember.policy' command"));
+            }
+            OverwritePolicy::Reset(ttl)
         }
-    });
+        _ => return Err(RedisError::Str("ERR syntax error")),
+    };
+    overwrite::set(db, key, policy);
+    ctx.replicate_verbatim();
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
+/// EXPIREMEMBER.DUMP key
+///
+/// Serializes the expirations tracked for `key` into an opaque payload for EXPIREMEMBER.RESTORE.
+fn expiremember_dump(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 2 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.dump' command"));
+    }
+
+    let key = args[1].to_string();
+    let members: Vec<(String, u64, Priority)> = EXPIRATION_TIMES.of_key(selected_db(ctx), &key).into_iter()
+        .map(|tracked| (tracked.member.to_string(), tracked.expire_at.unix_ms(), tracked.priority))
+        .collect();
+    Ok(RedisValue::StringBuffer(dump::encode(&members)))
+}
+
+/// EXPIREMEMBER.DUMPSLOT slot
+///
+/// Returns `[key, payload, ...]` with an EXPIREMEMBER.DUMP payload for every tracked key of the
+/// cluster slot, to be restored on the node importing it.
+fn expiremember_dumpslot(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 2 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.dumpslot' command"));
+    }
+    let slot = args[1].parse_integer()
+        .ok()
+        .filter(|slot| (0..16384).contains(slot))
+        .ok_or(RedisError::Str("ERR Invalid or out of range slot"))? as usize;
+
+    let db = selected_db(ctx);
+    let mut keys: HashMap<String, Vec<(String, u64, Priority)>> = HashMap::new();
+    for shard in EXPIRATION_TIMES.shards() {
+        for tracked in shard.values() {
+            if tracked.db == db && cluster::key_slot(&tracked.key) == slot {
+                keys.entry(tracked.key.to_string()).or_default().push((
+                    tracked.member.to_string(),
+                    tracked.expire_at.unix_ms(),
+                    tracked.priority,
+                ));
+            }
+        }
+    }
+    Ok(RedisValue::Array(keys.into_iter()
+        .flat_map(|(key, members)| [RedisValue::BulkString(key), RedisValue::StringBuffer(dump::encode(&members))])
+        .collect()))
+}
+
+/// EXPIREMEMBER.RESTORE key payload [REPLACE]
+///
+/// Schedules the expirations of an EXPIREMEMBER.DUMP payload on `key`, keeping their absolute
+/// deadlines. REPLACE first forgets the expirations already tracked for `key`.
+fn expiremember_restore(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 3 && args.len() != 4 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.restore' command"));
+    }
+    let replace = match args.get(3) {
+        Some(option) if option.to_string().eq_ignore_ascii_case("replace") => true,
+        Some(_) => return Err(RedisError::Str("ERR syntax error")),
+        None => false,
+    };
+    let members = dump::decode(args[2].as_slice())
+        .ok_or(RedisError::Str("ERR payload version or checksum are wrong"))?;
+
+    let db = selected_db(ctx);
+    let key = args[1].to_string();
+    if replace {
+        let backend = *BACKEND.lock().unwrap();
+        let forgotten = EXPIRATION_TIMES.remove_key(db, &key);
+        for tracked in forgotten {
+            shadow::forget(ctx, backend, &key, &tracked.member);
+            ctx.replicate(&commands::name("EXPIREMEMBER"), &[key.as_str(), &*tracked.member, "-1"]);
+        }
+    }
+
+    let now = Deadline::now();
+    for (member, deadline, priority) in &members {
+        let expire_at = Deadline::from_unix(Duration::from_millis(*deadline));
+        if expire_at <= now {
+            delete_member(ctx, key.clone(), member.clone())?;
+        } else {
+            let expiring_member = ExpiringMember::new(db, key.clone(), member.clone(), expire_at);
+            schedule_member(ctx, ExpiringMember { priority: *priority, ..expiring_member })?;
+        }
+    }
+    Ok(RedisValue::Integer(members.len() as i64))
+}
+
+/// EXPIREMEMBER.EXPORT path
+///
+/// Writes every tracked expiration to `path` from a background thread.
+fn expiremember_export(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 2 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.export' command"));
+    }
+    if !snapshot::try_start_job() {
+        return Err(RedisError::Str("ERR an export, import or migration is already in progress"));
+    }
+    snapshot::export(args[1].to_string());
+    Ok(RedisValue::SimpleStringStatic("Background export started"))
+}
+
+/// EXPIREMEMBER.IMPORT path
+///
+/// Schedules the expirations of an EXPIREMEMBER.EXPORT file from a background thread.
+fn expiremember_import(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 2 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.import' command"));
+    }
+    if !snapshot::try_start_job() {
+        return Err(RedisError::Str("ERR an export, import or migration is already in progress"));
+    }
+    snapshot::import(args[1].to_string());
+    Ok(RedisValue::SimpleStringStatic("Background import started"))
+}
+
+/// EXPIREMEMBER.MIGRATE host port [MATCH pattern] [BATCH count] [AUTH password]
+///
+/// Replays the tracked expirations of the matching keys on another instance from a background thread.
+fn expiremember_migrate(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() < 3 || !args.len().is_multiple_of(2) {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.migrate' command"));
+    }
+    let port = args[2].parse_integer()
+        .ok()
+        .and_then(|port| u16::try_from(port).ok())
+        .ok_or(RedisError::Str("ERR invalid port"))?;
+
+    let mut migration = snapshot::Migration { host: args[1].to_string(), port, pattern: None, batch: 1000, auth: None };
+    for option in args[3..].chunks(2) {
+        match option[0].to_string().to_lowercase().as_str() {
+            "match" => migration.pattern = Some(option[1].to_string()),
+            "batch" => {
+                migration.batch = option[1].parse_integer()
+                    .ok()
+                    .filter(|batch| *batch > 0)
+                    .ok_or(RedisError::Str("ERR batch must be positive"))? as usize;
+            }
+            "auth" => migration.auth = Some(option[1].to_string()),
+            _ => return Err(RedisError::Str("ERR syntax error")),
+        }
+    }
+
+    if !snapshot::try_start_job() {
+        return Err(RedisError::Str("ERR an export, import or migration is already in progress"));
+    }
+    snapshot::migrate(migration);
+    Ok(RedisValue::SimpleStringStatic("Background migration started"))
+}
+
+/// EXPIREMEMBER.SYNC [key]
+///
+/// Expires every member whose deadline has passed, of `key` in the selected database or of all
+/// keys of every database, before replying with the number of members handled. Members of
+/// migrating cluster slots are still held back.
+fn expiremember_sync(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() > 2 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.sync' command"));
+    }
+    let key = args.get(1).map(|key| (selected_db(ctx), key.to_string()));
+
+    let now = Deadline::now();
+    let mut members_to_expire: MembersByKey = HashMap::new();
+    let due = EXPIRATION_TIMES.collect(|tracked| {
+        (tracked.expire_at <= now && key.as_ref().is_none_or(|(db, key)| *db == tracked.db && *key == *tracked.key)).then(|| tracked.clone())
+    });
+    for tracked in due {
+        members_to_expire.entry((tracked.db, tracked.key.clone())).or_default().push(tracked);
+    }
+    let due = members_to_expire.values().map(Vec::len).sum::<usize>();
+
+    let deferred = expire_members(ctx, &ExpiryHooks::load(), members_to_expire);
+    EVENT_LOG.lock().unwrap().wake_subscribers();
+    Ok(RedisValue::Integer((due - deferred.len()) as i64))
+}
+
+/// EXPIREMEMBER.FLUSHALL [DB db] [MATCH pattern]
+///
+/// Forgets every tracked expiration, or those of database `db` or of the keys matching
+/// `pattern`, leaving the members themselves in place, and returns how many were forgotten.
+fn expiremember_flushall(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if !args.len().is_multiple_of(2) {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.flushall' command"));
+    }
+    let (mut db, mut pattern) = (None, None);
+    for option in args[1..].chunks(2) {
+        match option[0].to_string().to_lowercase().as_str() {
+            "db" => {
+                db = Some(option[1].parse_integer()
+                    .ok()
+                    .and_then(|db| i32::try_from(db).ok())
+                    .filter(|db| *db >= 0)
+                    .ok_or(RedisError::Str("ERR invalid database"))?);
+            }
+            "match" => pattern = Some(option[1].to_string()),
+            _ => return Err(RedisError::Str("ERR syntax error")),
+        }
+    }
+    // An import would schedule again what is being flushed.
+    if snapshot::is_running() {
+        return Err(RedisError::Str("ERR an export, import or migration is in progress"));
+    }
+
+    let keys: HashSet<(i32, Arc<str>)> = EXPIRATION_TIMES.collect(|tracked| {
+        let matched = db.is_none_or(|db| db == tracked.db)
+            && pattern.as_ref().is_none_or(|pattern| snapshot::glob_match(pattern.as_bytes(), tracked.key.as_bytes()));
+        matched.then(|| (tracked.db, tracked.key.clone()))
+    }).into_iter().collect();
+
+    let backend = *BACKEND.lock().unwrap();
+    let mut forgotten = 0;
+    for (db, key) in keys {
+        forgotten += EXPIRATION_TIMES.remove_key(db, &key).len();
+        if backend != Backend::memory {
+            with_db(ctx, db, || ctx.call("DEL", &[shadow::shadow_key_name(&key).as_str()]).map(|_| ()))?;
+        }
+    }
+    stats::add(Counter::Cancelled, forgotten as u64);
+    rebuild_heap();
+    ctx.replicate_verbatim();
+    ctx.log_notice(&format!("EXPIREMEMBER.FLUSHALL forgot {} member expirations", forgotten));
+    Ok(RedisValue::Integer(forgotten as i64))
+}
+
+/// EXPIREMEMBER.STATS [KEYS [count]]
+///
+/// Reports counters of schedules and deletions, since the module was loaded and over the last
+/// minute, or with KEYS the `count` keys with the most expired members, 10 by default.
+fn expiremember_stats(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() > 3 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.stats' command"));
+    }
+    match args.get(1) {
+        None => stats::report(),
+        Some(option) if option.to_string().eq_ignore_ascii_case("keys") => stats::report_keys(parse_keys_count(args.get(2))?),
+        Some(_) => Err(RedisError::Str("ERR syntax error")),
+    }
+}
+
+/// EXPIREMEMBER.MEMORY [KEYS [count]]
+///
+/// Estimates the memory taken by the module's structures, or with KEYS by the `count` keys
+/// taking the most, 10 by default.
+fn expiremember_memory(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() > 3 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.memory' command"));
+    }
+    match args.get(1) {
+        None => memory::report(),
+        Some(option) if option.to_string().eq_ignore_ascii_case("keys") => memory::report_keys(parse_keys_count(args.get(2))?),
+        Some(_) => Err(RedisError::Str("ERR syntax error")),
+    }
+}
+
+/// The count of a KEYS option, 10 when not given.
+fn parse_keys_count(count: Option<&RedisString>) -> RedisResult<usize> {
+    match count {
+        Some(count) => count.parse_integer().ok().filter(|count| *count > 0)
+            .map(|count| count as usize)
+            .ok_or(RedisError::Str("ERR count should be a positive integer")),
+        None => Ok(10),
+    }
+}
+
+/// EXPIREMEMBER.METRICS
+///
+/// Returns the module's metrics in the Prometheus text exposition format.
+fn expiremember_metrics(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.metrics' command"));
+    }
+    Ok(RedisValue::BulkString(metrics::render()))
+}
+
+/// EXPIREMEMBER.INFO
+///
+/// Reports the effective configuration, the compiled-in constants and the runtime state of the
+/// worker, for debugging.
+#[cfg(feature = "debug")]
+fn expiremember_info(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.info' command"));
+    }
+    info::report(ctx)
+}
+
+/// EXPIREMEMBER.DEBUG PAUSE | RESUME | JUMPTIME milliseconds | CYCLE
+///
+/// Pauses and resumes the background deletions, like `DEBUG SET-ACTIVE-EXPIRE`, EXPIREMEMBER.SYNC
+/// still expiring due members while paused. JUMPTIME moves the module's clock ahead, returning
+/// how far ahead it is in ms. CYCLE runs a cycle of the worker now, even if paused, and returns
+/// the number of members it expired.
+#[cfg(feature = "debug")]
+fn expiremember_debug(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() < 2 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.debug' command"));
+    }
+    debug::run(ctx, &args[1..])
+}
+
+/// EXPIREMEMBER.RELOADCONF
+///
+/// Reloads `expiremember.config-file` now rather than when it is seen changed, and returns the
+/// number of rules loaded.
+#[cfg(feature = "rules")]
+fn expiremember_reloadconf(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.reloadconf' command"));
+    }
+    rules::reload(ctx).map_err(|err| RedisError::String(format!("ERR {}", err)))?;
+    Ok(rules::counts())
+}
+
+/// EXPIREMEMBER.CHECK [REPAIR]
+///
+/// Reports expirations whose key or member is gone and inconsistencies between the tracking
+/// structures, and with REPAIR fixes them.
+fn expiremember_check(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() > 2 {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.check' command"));
+    }
+    let repair = match args.get(1) {
+        Some(option) if option.to_string().eq_ignore_ascii_case("repair") => true,
+        Some(_) => return Err(RedisError::Str("ERR syntax error")),
+        None => false,
+    };
+    if repair && IS_REPLICA.load(Ordering::SeqCst) {
+        return Err(RedisError::Str("ERR REPAIR is not allowed on a replica, repair its primary instead"));
+    }
+    check::start(ctx, repair)
+}
+
+/// EXPIREMEMBER.SUBSCRIBE cursor [COUNT count] [BLOCK milliseconds]
+///
+/// Returns the expirations that happened after `cursor` (`$` for only new ones),
+/// optionally blocking until at least one is available.
+#[cfg(feature = "notifications")]
+fn expiremember_subscribe(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() < 2 || !args.len().is_multiple_of(2) {
+        return Err(RedisError::Str("ERR wrong number of arguments for 'expiremember.subscribe' command"));
+    }
+    if EVENT_LOG_SIZE.load(Ordering::Relaxed) <= 0 {
+        return Err(RedisError::Str("ERR the expiry event log is disabled, set expiremember.event-log-size"));
+    }
+
+    let mut count = usize::MAX;
+    let mut block = None;
+    for option in args[2..].chunks(2) {
+        let value = option[1].parse_integer()?;
+        match option[0].to_string().to_lowercase().as_str() {
+            "count" if value > 0 => count = value as usize,
+            "block" if value >= 0 => block = Some(value as u64),
+            _ => return Err(RedisError::Str("ERR syntax error")),
+        }
+    }
+
+    let mut log = EVENT_LOG.lock().unwrap();
+    let cursor = if args[1].to_string() == "$" {
+        log.last_id
+    } else {
+        args[1].parse_integer()
+            .ok()
+            .filter(|cursor| *cursor >= 0)
+            .ok_or(RedisError::Str("ERR invalid cursor"))? as u64
+    };
+
+    let blocking_denied = ctx.get_flags().intersects(ContextFlags::MULTI | ContextFlags::LUA | ContextFlags::DENY_BLOCKING);
+    match block {
+        Some(timeout) if cursor >= log.last_id && !blocking_denied => {
+            let deadline = (timeout > 0).then(|| Deadline::after(Duration::from_millis(timeout)));
+            log.subscribers.push(Subscriber { cursor, count, deadline, client: ctx.block_client() });
+            WORKER_WAKEUP.notify();
+            Ok(RedisValue::NoReply)
+        }
+        Some(_) if cursor >= log.last_id => Ok(RedisValue::Null),
+        _ => Ok(log.read(cursor, count)),
+    }
+}
+
+/// Starts the worker, under the thread driver in a thread of its own, along with the watchdog.
+/// Called on load.
+fn start_expiration_thread() {
+    THREAD_STARTED.store(true, Ordering::SeqCst);
+    if *DRIVER.lock().unwrap() == Driver::timer {
+        timer::start(Worker::new(1));
+        watchdog::start(None);
+        return;
+    }
+    let worker = thread::spawn(move || {
+        let thread_ctx = ThreadSafeContext::new();
+        loop {
+            let run = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut worker = Worker::new(EXPIRE_THREADS.load(Ordering::Relaxed).max(1) as usize);
+                loop {
+                    let wake_at = worker.cycle(&Gil::Thread(&thread_ctx));
+                    WORKER_WAKEUP.wait(wake_at);
+                }
+            }));
+            if let Err(panic) = run {
+                watchdog::panicked(panic);
+                thread::sleep(watchdog::RESTART_DELAY);
+            }
+        }
+    });
+    watchdog::start(Some(worker));
+}
+
+/// How the worker gets hold of the GIL: locking it from its thread, or already holding it on
+/// the main thread under the timer driver.
+enum Gil<'a> {
+    Thread(&'a ThreadSafeContext<DetachedFromClient>),
+    Held(&'a Context),
+}
+
+impl Gil<'_> {
+    /// Runs `f` holding the GIL, the time it takes counting against `max-lock-percent`.
+    fn with<R>(&self, f: impl FnOnce(&Context) -> R) -> R {
+        match self {
+            Gil::Thread(thread_ctx) => {
+                let waiting = Instant::now();
+                let ctx = thread_ctx.lock();
+                let locked = Instant::now();
+                let result = f(&ctx);
+                let held = locked.elapsed();
+                add_latency_sample(held);
+                drop(ctx);
+                throttle::charge_lock(held);
+                info::gil_used(locked.duration_since(waiting), held);
+                result
+            }
+            Gil::Held(ctx) => {
+                let started = Instant::now();
+                let result = f(ctx);
+                let held = started.elapsed();
+                add_latency_sample(held);
+                throttle::charge_lock(held);
+                info::gil_used(Duration::ZERO, held);
+                result
+            }
+        }
+    }
+}
+
+/// Reports the worker having held the GIL for `held` to the latency monitor as the
+/// `expiremember-cycle` event, kept there when over `latency-monitor-threshold`. Must hold the GIL.
+fn add_latency_sample(held: Duration) {
+    if let Some(add_sample) = unsafe { raw::RedisModule_LatencyAddSample } {
+        unsafe { add_sample(c"expiremember-cycle".as_ptr(), held.as_millis() as raw::mstime_t) };
+    }
+}
+
+/// The worker's schedule and what it carries over from a cycle to the next.
+struct Worker {
+    scheduler: Scheduler,
+    // `ttl-granularity` the schedule was built with.
+    granularity: u64,
+    schedule: Schedule,
+    gc_sampler: gc::Sampler,
+    last_gc: Instant,
+    pool: pool::Pool,
+    limiter: throttle::RateLimiter,
+    last_backlog: Instant,
+    statsd: statsd::Emitter,
+}
+
+impl Worker {
+    fn new(threads: usize) -> Self {
+        let scheduler = *SCHEDULER.lock().unwrap();
+        let granularity = TTL_GRANULARITY.load(Ordering::Relaxed).max(0) as u64;
+        Worker {
+            scheduler,
+            granularity,
+            schedule: Schedule::new(scheduler, granularity),
+            gc_sampler: gc::Sampler::new(),
+            last_gc: Instant::now(),
+            pool: pool::Pool::start(threads),
+            limiter: throttle::RateLimiter::new(),
+            last_backlog: Instant::now(),
+            statsd: statsd::Emitter::new(),
+        }
+    }
+
+    /// Expires the due members, at most `expire-batch` of them when already holding the GIL.
+    /// Returns when the next cycle should run, None when nothing is left to wait for.
+    fn cycle(&mut self, gil: &Gil) -> Option<Deadline> {
+        // Calls of EXPIREMEMBER.DEBUG CYCLE waiting for this cycle, which then runs even if paused.
+        #[cfg(feature = "debug")]
+        let (forced, expired) = (debug::take_cycles(), pool::expired());
+        #[cfg(feature = "debug")]
+        let force = !forced.is_empty();
+        #[cfg(not(feature = "debug"))]
+        let force = false;
+
+        let started = Instant::now();
+        let next = self.expire_due(gil, force);
+        info::cycle_ended(self.schedule.len(), self.schedule.next_deadline(), next.is_none(), started.elapsed());
+        #[cfg(feature = "debug")]
+        debug::cycle_ran(forced, pool::expired() - expired);
+        match (next, self.statsd.tick(gil)) {
+            (Some(next), Some(metrics_due)) => Some(next.min(metrics_due)),
+            (next, metrics_due) => next.or(metrics_due),
+        }
+    }
+
+    fn expire_due(&mut self, gil: &Gil, force: bool) -> Option<Deadline> {
+        let now = Deadline::now();
+        let mut deferred = Vec::new();
+        let schedule = &mut self.schedule;
+
+        // Entries left behind by rescheduled or cancelled expirations are dropped once they
+        // outnumber the live ones, so the schedule's size follows the tracked expirations. A new
+        // `ttl-granularity` or `scheduler` takes effect the same way.
+        let bloated = schedule.len() >= COMPACTION_MIN_ENTRIES && schedule.len() > 2 * EXPIRATION_TIMES.len();
+        let granularity = TTL_GRANULARITY.load(Ordering::Relaxed).max(0) as u64;
+        let scheduler = *SCHEDULER.lock().unwrap();
+        if HEAP_REBUILD.swap(false, Ordering::SeqCst) || bloated || granularity != self.granularity || scheduler != self.scheduler {
+            // Queued members are tracked before being queued, so draining the queue first
+            // misses none of them.
+            while EXPIRATION_QUEUE.try_pop().is_some() {}
+            (self.scheduler, self.granularity) = (scheduler, granularity);
+            *schedule = Schedule::new(self.scheduler, granularity);
+            schedule.extend(EXPIRATION_TIMES.values());
+        }
+
+        while let Some(member) = EXPIRATION_QUEUE.try_pop() {
+            schedule.push(member);
+        }
+
+        check::audit_heap(schedule);
+
+        // Due members stay in the schedule while paused. Replicas leave deletions to their primary,
+        // and otherwise they are caught up once the fork child exits or EXPIREMEMBER.DEBUG RESUME.
+        let paused = IS_REPLICA.load(Ordering::SeqCst)
+            || (EXPIRY_PAUSED.load(Ordering::SeqCst) && !force)
+            || (PAUSE_DURING_FORK.load(Ordering::Relaxed)
+                && schedule.next_deadline().is_some_and(|deadline| deadline <= now)
+                && gil.with(|ctx| ctx.get_flags().contains(ContextFlags::ACTIVE_CHILD)));
+
+        // Due members are checked against EXPIRATION_TIMES in one go per thread, rather than
+        // locking it for each of them. Under `max-deletions-per-second` they are checked one by
+        // one instead, so stale entries do not count against the rate.
+        let rate = MAX_DELETIONS_PER_SECOND.load(Ordering::Relaxed).max(0) as u64;
+        let locked_out = throttle::lock_free_at();
+        let allowance = if locked_out.is_some() { 0 } else { self.limiter.allowance(rate) };
+        let slice = match gil {
+            Gil::Thread(_) => usize::MAX,
+            Gil::Held(_) => EXPIRE_BATCH.load(Ordering::Relaxed).max(1) as usize,
+        }.min(allowance);
+        let mut due = Vec::new();
+        while due.len() < slice {
+            let Some(member) = schedule.pop_due(now) else { break };
+            if paused {
+                if EXPIRATION_TIMES.is_current(&member) {
+                    schedule.push(member);
+                    break;
+                }
+            } else if rate == 0 || EXPIRATION_TIMES.is_current(&member) {
+                due.push(member);
+            }
+        }
+        let throttled = due.len() >= allowance && schedule.next_deadline().is_some_and(|deadline| deadline <= now);
+        let sliced = due.len() >= slice;
+
+        // Orphans are looked for in cycles with nothing to expire, `gc-effort` of them per
+        // interval however often or seldom the worker wakes up, up to 10 intervals' worth.
+        let gc_effort = GC_EFFORT.load(Ordering::Relaxed).max(0) as usize;
+        if !due.is_empty() {
+            // The GIL is released between batches, so clients are served while many members
+            // expire at once.
+            let popped = due.len();
+            deferred = self.pool.expire(gil, ExpiryHooks::load(), due);
+            self.limiter.take(popped - deferred.len());
+        } else if gc_effort > 0 && !paused && locked_out.is_none() && self.last_gc.elapsed() >= worker_interval() {
+            let intervals = (self.last_gc.elapsed().as_millis() / worker_interval().as_millis()).min(10) as usize;
+            self.last_gc = Instant::now();
+            let sample = self.gc_sampler.next(gc_effort * intervals);
+            if !sample.is_empty() {
+                gil.with(|ctx| gc::collect_orphans(ctx, sample));
+            }
+        }
+
+        schedule.extend(deferred);
+
+        EVENT_LOG.lock().unwrap().wake_subscribers();
+
+        // Due members left for later cycles are counted at most once an interval, scanning the
+        // heap is O(n), up to `backlog-threshold`. Without one, they are only counted for INFO,
+        // once a second and in full.
+        let still_due = schedule.next_deadline().is_some_and(|deadline| deadline <= now);
+        let backlog_threshold = BACKLOG_THRESHOLD.load(Ordering::Relaxed).max(0) as usize;
+        let (backlog_interval, backlog_limit) = match backlog_threshold {
+            0 => (Duration::from_secs(1), usize::MAX),
+            threshold => (worker_interval(), threshold),
+        };
+        if !still_due {
+            limits::set_backlog(0);
+        } else if self.last_backlog.elapsed() >= backlog_interval {
+            self.last_backlog = Instant::now();
+            limits::set_backlog(schedule.overdue(now, backlog_limit));
+        }
+
+        // Sleeps until the next deadline or subscriber timeout, at most `max-sleep`. Members
+        // still due (paused or held back) are retried after an interval, the rest of a slice
+        // right away, those over the deletion rate once a tenth of it is allowed. With neither,
+        // the worker is parked until a schedule or subscriber notifies it.
+        let cycle_end = Deadline::now();
+        if let Some(free_at) = throttle::lock_free_at().filter(|_| !paused && still_due) {
+            return Some(free_at);
+        }
+        if throttled {
+            return Some(cycle_end + self.limiter.refill_time(rate));
+        }
+        if sliced {
+            return Some(cycle_end);
+        }
+        let next_deadline = schedule.next_deadline();
+        let next_timeout = EVENT_LOG.lock().unwrap().next_timeout();
+        if next_deadline.is_none() && next_timeout.is_none() {
+            return None;
+        }
+        let mut wake_at = cycle_end + Duration::from_millis(MAX_SLEEP.load(Ordering::Relaxed).max(1) as u64);
+        match next_deadline {
+            Some(deadline) if deadline > now => wake_at = wake_at.min(deadline),
+            Some(_) => wake_at = wake_at.min(cycle_end + worker_interval()),
+            None => {}
+        }
+        if let Some(timeout) = next_timeout {
+            wake_at = wake_at.min(timeout);
+        }
+        Some(wake_at)
+    }
+}
+
+/// Deletes due members, grouped by database and key, and forgets their expirations. Must hold the GIL.
+///
+/// Returns the members of migrating slots, which are held back.
+fn expire_members(ctx: &Context, hooks: &ExpiryHooks, mut members_to_expire: MembersByKey) -> Vec<ExpiringMember> {
+    let backend = *BACKEND.lock().unwrap();
+    let mut deferred = Vec::new();
+
+    // Skip what was rescheduled or cancelled while waiting for the GIL.
+    for members in members_to_expire.values_mut() {
+        members.retain(|member| EXPIRATION_TIMES.is_current(member));
+    }
+
+    // Keys of slots served elsewhere (e.g. after resharding) are only forgotten, keys of
+    // migrating slots wait for the migration to end since their expirations may move along.
+    let slot_states = cluster::slot_states(ctx);
+    if let Some(states) = &slot_states {
+        members_to_expire.retain(|(_, key), members| {
+            let migrating = states.migrating[cluster::key_slot(key)];
+            if migrating {
+                deferred.append(members);
+            }
+            !migrating
+        });
+    }
+
+    let mut disabled = Vec::new();
+    for ((db, key), members) in &members_to_expire {
+        let local = slot_states.as_ref().is_none_or(|states| states.owned[cluster::key_slot(key)]);
+        with_db(ctx, *db, || {
+            let container = if local { Container::type_of(ctx, key).ok().flatten() } else { None };
+            // Expirations of a type disabled by `track-*` stay tracked, out of the schedule until
+            // it is tracked again.
+            if container.is_some_and(|container| !container.is_tracked()) {
+                disabled.push((*db, key.clone()));
+                return;
+            }
+            if let Some(container) = container {
+                // One removal per key. Only the side effects need to know which members were
+                // still there, they are looked up in one go beforehand.
+                let names: Vec<&str> = members.iter().map(|member| &*member.member).collect();
+                let found = if hooks.is_active() { container.lookup(ctx, key, &names) } else { Vec::new() };
+                // Sent ahead of the removal's own events, before a `del` of the emptied key.
+                #[cfg(feature = "notifications")]
+                if found.iter().any(Option::is_some) {
+                    notify::keyspace(ctx, hooks.keyspace_class, container, key);
+                }
+                if let Err(err) = container.remove_all(ctx, key, &names) {
+                    stats::failed(ctx, Counter::DeletionsFailed, names.len() as u64,
+                        || format!("Could not delete {} expired members of '{}': {}", names.len(), key, err));
+                }
+                for (member, found) in members.iter().zip(found) {
+                    if let Some(value) = found {
+                        hooks.member_expired(ctx, member, value.filter(|_| hooks.wants_value()));
+                    }
+                }
+            }
+            for member in members {
+                if local {
+                    shadow::forget(ctx, backend, key, &member.member);
+                }
+                // Replicas keep the expiration until the primary is done with it.
+                ctx.replicate(&commands::name("EXPIREMEMBER"), &[key.as_ref(), &*member.member, "-1"]);
+            }
+        });
+    }
+
+    for key in &disabled {
+        members_to_expire.remove(key);
+    }
+
+    // Still under the GIL, so a fork never sees a write lock held.
+    let now = Deadline::now();
+    for member in members_to_expire.values().flatten() {
+        EXPIRATION_TIMES.remove(member.db, &member.key, &member.member);
+        lag::record(now.since(member.expire_at));
+    }
+    trace::expired(ctx, members_to_expire.values().flatten(), now);
+    stats::add(Counter::Expired, members_to_expire.values().map(Vec::len).sum::<usize>() as u64);
+    stats::add_keys(members_to_expire.iter().map(|((db, key), members)| (*db, key, members.len())));
+    deferred
+}
+
+/// Snapshot of the configured side effects of an expiration, taken once per cycle.
+struct ExpiryHooks {
+    channel: String,
+    stream: String,
+    #[cfg(feature = "notifications")]
+    stream_maxlen: usize,
+    keyspace_class: EventClass,
+    include_value: bool,
+    function: String,
+    script_sha: String,
+    log_size: usize,
+}
+
+impl ExpiryHooks {
+    fn load() -> Self {
+        ExpiryHooks {
+            channel: EVENTS_CHANNEL.lock().unwrap().clone(),
+            stream: EVENTS_STREAM.lock().unwrap().clone(),
+            #[cfg(feature = "notifications")]
+            stream_maxlen: EVENTS_STREAM_MAXLEN.load(Ordering::Relaxed).max(0) as usize,
+            keyspace_class: *KEYSPACE_EVENT_CLASS.lock().unwrap(),
+            include_value: EVENTS_INCLUDE_VALUE.load(Ordering::Relaxed),
+            function: EXPIRE_FUNCTION.lock().unwrap().clone(),
+            script_sha: EXPIRE_SCRIPT_SHA.lock().unwrap().clone(),
+            log_size: EVENT_LOG_SIZE.load(Ordering::Relaxed).max(0) as usize,
+        }
+    }
+
+    fn wants_value(&self) -> bool {
+        (!self.channel.is_empty() || !self.stream.is_empty()) && self.include_value
+    }
+
+    /// Whether expirations have any side effect beyond the deletion.
+    fn is_active(&self) -> bool {
+        self.log_size > 0 || !self.channel.is_empty() || !self.stream.is_empty() || self.keyspace_class != EventClass::none
+            || !self.function.is_empty() || !self.script_sha.is_empty()
+    }
+
+    /// Runs the configured hooks for a member that has just been removed.
+    #[cfg_attr(not(feature = "notifications"), allow(unused_variables))]
+    fn member_expired(&self, ctx: &Context, member: &ExpiringMember, value: Option<String>) {
+        if self.log_size > 0 {
+            EVENT_LOG.lock().unwrap().push(member, self.log_size);
+        }
+        #[cfg(feature = "notifications")]
+        if !self.channel.is_empty() {
+            publish_expired_event(ctx, &self.channel, member, value.clone().filter(|_| self.include_value));
+        }
+        #[cfg(feature = "notifications")]
+        if !self.stream.is_empty() {
+            notify::stream(ctx, &self.stream, self.stream_maxlen, member, value.as_deref().filter(|_| self.include_value));
+        }
+        if !self.function.is_empty() || !self.script_sha.is_empty() {
+            let numkeys = ctx.create_string("1");
+            let key = ctx.create_string(member.key.as_bytes());
+            let member = ctx.create_string(member.member.as_bytes());
+            if !self.function.is_empty() {
+                let function = ctx.create_string(self.function.as_bytes());
+                if let Err(err) = ctx.call("FCALL", &[&function, &numkeys, &key, &member]) {
+                    stats::failed(ctx, Counter::NotificationsFailed, 1, || format!("Expiry function '{}' failed: {}", self.function, err));
+                }
+            }
+            if !self.script_sha.is_empty() {
+                let sha = ctx.create_string(self.script_sha.as_bytes());
+                if let Err(err) = ctx.call("EVALSHA", &[&sha, &numkeys, &key, &member]) {
+                    stats::failed(ctx, Counter::NotificationsFailed, 1, || format!("Expiry script {} failed: {}", self.script_sha, err));
+                }
+            }
+        }
+    }
+}
+
+/// Publishes an expiry event for `member` as a JSON object on `channel`.
+#[cfg(feature = "notifications")]
+fn publish_expired_event(ctx: &Context, channel: &str, member: &ExpiringMember, value: Option<String>) {
+    let expired_at = member.expire_at.unix_ms();
+    let mut payload = format!(
+        "{{\"key\":{},\"member\":{},\"expired_at\":{}",
+        json_string(&member.key), json_string(&member.member), expired_at
+    );
+    if let Some(value) = value {
+        payload.push_str(",\"value\":");
+        payload.push_str(&json_string(&value));
+    }
+    payload.push('}');
+
+    let channel = ctx.create_string(channel.as_bytes());
+    let payload = ctx.create_string(payload.as_bytes());
+    if let Err(err) = ctx.call("PUBLISH", &[&channel, &payload]) {
+        stats::failed(ctx, Counter::NotificationsFailed, 1, || format!("Could not publish an expiry event: {}", err));
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Keyspace notification handler for key events: `del`, sent by DEL and UNLINK and when the
+/// last member of a key is removed, `expired` of keys reaching their own TTL, `evicted` under maxmemory, `copy_to` of COPY, the
+/// `move_from`/`move_to` pair of MOVE and the `rename_from`/`rename_to` pair of RENAME. The
+/// database of the event is selected in `ctx`.
+fn key_event(ctx: &Context, _event_type: NotifyEvent, event: &str, key: &[u8]) {
+    let db = selected_db(ctx);
+    let key = String::from_utf8_lossy(key);
+    // Shadow keys come and go with the expirations they hold.
+    if shadow::tracked_key_name(&key).is_some() {
+        return;
+    }
+    match event {
+        "copy_to" => copy_key(ctx, db, &key),
+        "move_from" => *MOVE_SOURCE.lock().unwrap() = Some((db, key.into_owned())),
+        "move_to" => {
+            let source = MOVE_SOURCE.lock().unwrap().take();
+            if let Some((from_db, _)) = source.filter(|(_, source)| *source == key) {
+                move_key(ctx, &key, from_db, db);
+            }
+        }
+        "rename_from" => *RENAME_SOURCE.lock().unwrap() = Some(key.into_owned()),
+        "rename_to" => {
+            let source = RENAME_SOURCE.lock().unwrap().take();
+            if let Some(source) = source {
+                rename_key(ctx, db, &source, &key);
+            }
+        }
+        "del" | "expired" | "evicted" => forget_key(ctx, db, &key),
+        _ => {}
+    }
+}
+
+/// Keyspace events of commands that may remove members other than by deleting the key.
+const MEMBER_REMOVAL_EVENTS: &[&str] = &[
+    "hdel", "hexpired", "srem", "spop", "zrem", "zpopmin", "zpopmax", "zremrangebyscore", "zremrangebyrank", "zremrangebylex",
+    "lrem", "lpop", "rpop", "ltrim", "lset",
+    // RedisJSON's, which may remove paths below the one they write to.
+    "json.del", "json.set", "json.mset", "json.merge", "json.clear", "json.arrpop", "json.arrtrim",
+];
+
+/// Keyspace events of commands that write members, possibly overwriting them.
+const MEMBER_WRITE_EVENTS: &[&str] = &["hset", "hincrby", "hincrbyfloat", "zadd", "zincr"];
+
+/// Keyspace events of commands that overwrite their destination key.
+const STORE_EVENTS: &[&str] = &[
+    "sinterstore", "sunionstore", "sdiffstore", "zinterstore", "zunionstore", "zdiffstore", "zrangestore",
+];
+
+/// Keyspace notification handler for hash, set, zset, list and module events, RedisJSON's. Members removed by a command
+/// are forgotten, so a member created again later does not inherit the old expiration, and
+/// members written are subject to the overwrite policy of their key.
+fn member_event(ctx: &Context, _event_type: NotifyEvent, event: &str, key: &[u8]) {
+    // The module forgets the members it removes itself.
+    if REMOVING_MEMBER.load(Ordering::Relaxed) {
+        return;
+    }
+    let db = selected_db(ctx);
+    let key = String::from_utf8_lossy(key);
+    if shadow::tracked_key_name(&key).is_some() {
+        return;
+    }
+    if STORE_EVENTS.contains(&event) {
+        forget_key(ctx, db, &key);
+    } else if MEMBER_REMOVAL_EVENTS.contains(&event) {
+        forget_removed_members(ctx, db, &key);
+    } else if MEMBER_WRITE_EVENTS.contains(&event) {
+        if let Some(members) = filter::written_members(event, &key) {
+            overwrite::members_written(ctx, db, &key, members);
+        }
+    }
+}
+
+/// Forgets the expirations of the tracked members `key` no longer has. Must hold the GIL, with
+/// database `db` selected.
+fn forget_removed_members(ctx: &Context, db: i32, key: &str) {
+    let tracked: Vec<Arc<str>> = EXPIRATION_TIMES.of_key(db, key).into_iter().map(|tracked| tracked.member).collect();
+    if tracked.is_empty() {
+        return;
+    }
+    // A key left empty is deleted, and forgotten on the `del` event that follows.
+    let Ok(Some(container)) = Container::of(ctx, key) else {
+        return;
+    };
+    let names: Vec<&str> = tracked.iter().map(|member| &**member).collect();
+    let removed: Vec<String> = names.iter().zip(container.lookup(ctx, key, &names))
+        .filter(|(_, found)| found.is_none())
+        .map(|(member, _)| member.to_string())
+        .collect();
+    forget_members(ctx, db, key, removed);
+}
+
+/// Forgets the expirations of `members` of `key` from a keyspace notification handler. Must hold the GIL.
+fn forget_members(ctx: &Context, db: i32, key: &str, members: Vec<String>) {
+    if members.is_empty() {
+        return;
+    }
+    let cancelled = members.iter().filter(|member| EXPIRATION_TIMES.remove(db, key, member).is_some()).count();
+    stats::add(Counter::Cancelled, cancelled as u64);
+
+    let backend = *BACKEND.lock().unwrap();
+    if backend != Backend::memory {
+        let key = key.to_string();
+        shadow::after_notification(ctx, move |ctx| {
+            for member in &members {
+                shadow::forget(ctx, backend, &key, member);
+            }
+        });
+    }
+}
+
+/// Forgets every expiration tracked for `key` along with its shadow key, so a key later created
+/// under the same name starts clean. Must hold the GIL, with database `db` selected.
+fn forget_key(ctx: &Context, db: i32, key: &str) {
+    overwrite::forget(db, key);
+    let forgotten = EXPIRATION_TIMES.remove_key(db, key);
+    // A key emptied by the module's own removal held expired members, counted as such.
+    if !REMOVING_MEMBER.load(Ordering::Relaxed) {
+        stats::add(Counter::Cancelled, forgotten.len() as u64);
+    }
+    if !forgotten.is_empty() {
+        shadow::remove(ctx, *BACKEND.lock().unwrap(), key);
+    }
+}
+
+/// Forgets the expirations `to` had before being overwritten by a COPY and, with
+/// `copy-expirations`, schedules those of the source on it. Must hold the GIL, with database
+/// `db` of `to` selected.
+fn copy_key(ctx: &Context, db: i32, to: &str) {
+    forget_key(ctx, db, to);
+    let Some(from) = filter::copy_source(to) else {
+        return;
+    };
+    // Replicas get the copies from their primary.
+    if !COPY_EXPIRATIONS.load(Ordering::Relaxed) || IS_REPLICA.load(Ordering::SeqCst) {
+        return;
+    }
+
+    let copies: Vec<ExpiringMember> = EXPIRATION_TIMES.of_key(db, &from).into_iter()
+        .map(|tracked| ExpiringMember::new(db, to.to_string(), tracked.member, tracked.expire_at))
+        .collect();
+    if !copies.is_empty() {
+        shadow::after_notification(ctx, move |ctx| {
+            for member in copies {
+                let key = member.key.clone();
+                if let Err(err) = schedule_member(ctx, member) {
+                    stats::failed(ctx, Counter::SchedulesDropped, 1, || format!("Could not copy a member expiration to '{}': {}", key, err));
+                }
+            }
+        });
+    }
+}
+
+/// Moves the expirations of `from` over to `to`, replacing those `to` had before. The worker's
+/// entries for the old names lapse, like those of any cancelled expiration. Must hold the GIL,
+/// with database `db` selected.
+fn rename_key(ctx: &Context, db: i32, from: &str, to: &str) {
+    overwrite::rename(db, from, to);
+    let replaced = EXPIRATION_TIMES.remove_key(db, to);
+    let moved = EXPIRATION_TIMES.remove_key(db, from);
+    let changed = !moved.is_empty() || !replaced.is_empty();
+    let to_key: Arc<str> = to.into();
+    for mut member in moved {
+        member.key = to_key.clone();
+        EXPIRATION_QUEUE.add_member(EXPIRATION_TIMES.insert(member));
+    }
+
+    if changed {
+        shadow::rename(ctx, *BACKEND.lock().unwrap(), from, to);
+        WORKER_WAKEUP.notify();
+    }
+}
+
+/// Moves the expirations of `key` from database `from_db` over to `to_db`, replacing any left
+/// there. Must hold the GIL, with `to_db` selected.
+fn move_key(ctx: &Context, key: &str, from_db: i32, to_db: i32) {
+    overwrite::move_key(key, from_db, to_db);
+    let replaced = EXPIRATION_TIMES.remove_key(to_db, key);
+    let moved = EXPIRATION_TIMES.remove_key(from_db, key);
+    let changed = !moved.is_empty() || !replaced.is_empty();
+    for mut member in moved {
+        member.db = to_db;
+        EXPIRATION_QUEUE.add_member(EXPIRATION_TIMES.insert(member));
+    }
+
+    if changed {
+        shadow::move_key(ctx, *BACKEND.lock().unwrap(), key, from_db, to_db);
+        WORKER_WAKEUP.notify();
+    }
+}
+
+fn init(ctx: &Context, args: &[RedisString]) -> Status {
+    // Lets a truncated or corrupt aux field fail the load instead of aborting the server.
+    ctx.set_module_options(ModuleOptions::HANDLE_IO_ERRORS);
+    server::detect(ctx);
+
+    if let Err(err) = arguments::apply(ctx, args).and_then(|_| config::loaded()) {
+        ctx.log_warning(&err);
+        return Status::Err;
+    }
+    profile::loaded(ctx);
+
+    if let Err(err) = commands::register(ctx) {
+        ctx.log_warning(&err);
+        return Status::Err;
+    }
+
+    #[cfg(feature = "rules")]
+    {
+        if let Err(err) = rules::reload(ctx) {
+            ctx.log_warning(&err);
+            return Status::Err;
+        }
+        rules::watch();
+    }
+
+    // Without the RDB preamble, expirations reach a rewritten AOF through carrier keys, which
+    // follow the AOF settings.
+    if subscribe_config_event(ctx) {
+        persistence::update_carriers(ctx);
+    } else if config_get(ctx, "appendonly").as_deref() == Some("yes")
+        && config_get(ctx, "aof-use-rdb-preamble").as_deref() == Some("no") {
+        ctx.log_warning("aof-use-rdb-preamble is disabled, member expirations will be lost on AOF rewrite and reload");
+    }
+
+    if !filter::register(ctx) {
+        ctx.log_warning("Could not register the command filter, COPY will not copy member expirations");
+    }
+
+    if !acl::register(ctx) {
+        ctx.log_notice("ACL categories of modules are not supported, commands are not in the @expiremember category");
+    }
+
+    if !docs::register(ctx) {
+        ctx.log_notice("Command metadata is not supported, COMMAND DOCS will not describe the module commands");
+    }
+
+    if !subscribe_server_events(ctx) {
+        ctx.log_warning("Could not subscribe to FLUSHDB, SWAPDB and shutdown, member expirations will not follow them");
+    }
+
+    if *DRIVER.lock().unwrap() == Driver::timer {
+        timer::init(ctx);
+        if EXPIRE_THREADS.load(Ordering::Relaxed) > 1 {
+            ctx.log_notice("expire-threads is ignored with the timer driver, expirations run on the main thread");
+        }
+    }
+
+    IS_REPLICA.store(ctx.get_flags().contains(ContextFlags::SLAVE), Ordering::SeqCst);
+
+    // The dataset is already there when loaded with MODULE LOAD, otherwise this finds nothing.
+    shadow::rebuild(ctx, *BACKEND.lock().unwrap());
+    // Running from the start, expirations restored from an RDB or the AOF are deleted as soon
+    // as they are due.
+    start_expiration_thread();
+    Status::Ok
+}
+
+#[distributed_slice(LOADING_SERVER_EVENTS_LIST)]
+fn loading_ended(ctx: &Context, subevent: LoadingSubevent) {
+    if subevent == LoadingSubevent::Ended {
+        shadow::rebuild(ctx, *BACKEND.lock().unwrap());
+        persistence::update_carriers(ctx);
+    }
+}
+
+/// Subscribes to the server events redis-module has no server event list for, FLUSHDB with the
+/// flushed database and SWAPDB. False if the server refused one.
+fn subscribe_server_events(ctx: &Context) -> bool {
+    let events: [(u64, unsafe extern "C" fn(_, _, _, _)); 3] = [
+        (raw::REDISMODULE_EVENT_FLUSHDB, flush_callback),
+        (raw::REDISMODULE_EVENT_SWAPDB, swapdb_callback),
+        (raw::REDISMODULE_EVENT_SHUTDOWN, shutdown_callback),
+    ];
+    events.into_iter().all(|(id, callback)| {
+        let event = raw::RedisModuleEvent { id, dataver: 1 };
+        let status = unsafe { raw::RedisModule_SubscribeToServerEvent.unwrap()(ctx.ctx, event, Some(callback)) };
+        status == raw::REDISMODULE_OK as c_int
+    })
+}
+
+/// Subscribes to configuration changes, for `persistence::update_carriers`. False if the server
+/// refused it, as before Redis 7.
+fn subscribe_config_event(ctx: &Context) -> bool {
+    let event = raw::RedisModuleEvent { id: raw::REDISMODULE_EVENT_CONFIG, dataver: 1 };
+    let status = unsafe { raw::RedisModule_SubscribeToServerEvent.unwrap()(ctx.ctx, event, Some(config_callback)) };
+    status == raw::REDISMODULE_OK as c_int
+}
+
+extern "C" fn config_callback(ctx: *mut raw::RedisModuleCtx, _eid: raw::RedisModuleEvent, subevent: u64, data: *mut c_void) {
+    if subevent != raw::REDISMODULE_SUBEVENT_CONFIG_CHANGE {
+        return;
+    }
+    let change = unsafe { &*(data as *const raw::RedisModuleConfigChange) };
+    let mut names = (0..change.num_changes as usize).map(|i| unsafe { CStr::from_ptr(*change.config_names.add(i)) });
+    if names.any(|name| matches!(name.to_bytes(), b"appendonly" | b"aof-use-rdb-preamble")) {
+        persistence::update_carriers(&Context::new(ctx));
+    }
+}
+
+extern "C" fn flush_callback(_ctx: *mut raw::RedisModuleCtx, _eid: raw::RedisModuleEvent, subevent: u64, data: *mut c_void) {
+    if subevent == raw::REDISMODULE_SUBEVENT_FLUSHDB_END {
+        let info = unsafe { &*(data as *const raw::RedisModuleFlushInfo) };
+        flushed(info.dbnum);
+    }
+}
+
+extern "C" fn swapdb_callback(_ctx: *mut raw::RedisModuleCtx, _eid: raw::RedisModuleEvent, _subevent: u64, data: *mut c_void) {
+    let info = unsafe { &*(data as *const raw::RedisModuleSwapDbInfo) };
+    swapped(info.dbnum_first, info.dbnum_second);
+}
+
+extern "C" fn shutdown_callback(ctx: *mut raw::RedisModuleCtx, _eid: raw::RedisModuleEvent, _subevent: u64, _data: *mut c_void) {
+    pool::shutdown();
+    let ctx = Context::new(ctx);
+    for (i, (members, batches)) in pool::stats().into_iter().enumerate() {
+        ctx.log_notice(&format!("Deletion thread {}: {} members expired in {} batches", i, members, batches));
+    }
+}
+
+/// Forgets the expirations of the flushed database, or of every database for FLUSHALL (`db` -1).
+fn flushed(db: i32) {
+    // Shadow keys are flushed along with the keys they mirror.
+    if db == -1 {
+        EXPIRATION_TIMES.clear();
+        overwrite::clear();
+        while EXPIRATION_QUEUE.try_pop().is_some() {}
+    } else {
+        EXPIRATION_TIMES.remove_db(db);
+        overwrite::forget_db(db);
+    }
+    rebuild_heap();
+}
+
+/// Makes the worker rebuild its heap from EXPIRATION_TIMES, right away.
+fn rebuild_heap() {
+    HEAP_REBUILD.store(true, Ordering::SeqCst);
+    WORKER_WAKEUP.notify();
+}
+
+/// Swaps the expirations of two databases along with their keys, shadow keys included.
+fn swapped(first: i32, second: i32) {
+    if first == second {
+        return;
+    }
+    EXPIRATION_TIMES.swap_dbs(first, second);
+    overwrite::swap_dbs(first, second);
+    rebuild_heap();
+}
+
+/// A promoted replica takes over the expirations it tracked for its primary, a demoted
+/// primary stops deleting and waits for its new primary instead.
+#[distributed_slice(ROLE_CHANGED_SERVER_EVENTS_LIST)]
+fn role_changed(ctx: &Context, role: ServerRole) {
+    IS_REPLICA.store(role == ServerRole::Replica, Ordering::SeqCst);
+    if role == ServerRole::Primary {
+        ctx.log_notice("Promoted to primary, resuming member expirations");
+        rebuild_heap();
+    }
+}
+
+/// The database selected in `ctx`: the client's for commands, the one concerned for events.
+fn selected_db(ctx: &Context) -> i32 {
+    unsafe { raw::RedisModule_GetSelectedDb.unwrap()(ctx.ctx) }
+}
+
+/// Runs `f` with database `db` selected in `ctx`, which keys, calls and replication then
+/// target, and selects the previous database back.
+fn with_db<T>(ctx: &Context, db: i32, f: impl FnOnce() -> T) -> T {
+    let previous = selected_db(ctx);
+    if previous == db {
+        return f();
+    }
+    unsafe { raw::RedisModule_SelectDb.unwrap()(ctx.ctx, db) };
+    let result = f();
+    unsafe { raw::RedisModule_SelectDb.unwrap()(ctx.ctx, previous) };
+    result
+}
+
+fn config_get(ctx: &Context, name: &str) -> Option<String> {
+    match ctx.call("CONFIG", &["GET", name]) {
+        Ok(RedisValue::Array(values)) => match values.get(1) {
+            Some(RedisValue::SimpleString(value)) => Some(value.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 #[cfg(not(test))]
@@ -181,8 +2361,67 @@ redis_module! {
     name: "expiremember",
     version: 1,
     allocator: (redis_module::alloc::RedisAlloc, redis_module::alloc::RedisAlloc),
-    data_types: [],
-    commands: [
-        ["expiremember", expiremember, "", 0, 0, 0],
+    data_types: [EXPIREMEMBER_TYPE, MEMBER_TTL_TYPE],
+    init: init,
+    // Registered by `init`, see commands.rs.
+    commands: [],
+    event_handlers: [
+        [@GENERIC @EXPIRED @EVICTED: key_event],
+        [@HASH @SET @ZSET @LIST @MODULE: member_event],
     ],
+    configurations: [
+        i64: [
+            ["event-log-size", &*EVENT_LOG_SIZE, 0, 0, 10_000_000, ConfigurationFlags::DEFAULT, None],
+            ["stats-keys", &*STATS_KEYS, 1000, 0, 1_000_000, ConfigurationFlags::DEFAULT, None],
+            ["gc-effort", &*GC_EFFORT, 100, 0, 1_000_000, ConfigurationFlags::DEFAULT, None],
+            ["expire-batch", &*EXPIRE_BATCH, 1000, 1, 10_000_000, ConfigurationFlags::DEFAULT, None],
+            ["max-lock-percent", &*MAX_LOCK_PERCENT, 100, 1, 100, ConfigurationFlags::DEFAULT, None],
+            ["max-deletions-per-second", &*MAX_DELETIONS_PER_SECOND, 0, 0, i64::MAX, ConfigurationFlags::DEFAULT, None],
+            ["expire-threads", &*EXPIRE_THREADS, 1, 1, 64, ConfigurationFlags::DEFAULT, None],
+            ["max-sleep", &*MAX_SLEEP, 1000, 1, 60_000, ConfigurationFlags::DEFAULT, None],
+            ["worker-interval", &*WORKER_INTERVAL, 100, 1, 10_000, ConfigurationFlags::DEFAULT, None],
+            ["queue-drain-threshold", &*QUEUE_DRAIN_THRESHOLD, 5000, 1, 10_000_000, ConfigurationFlags::DEFAULT, None],
+            ["ttl-granularity", &*TTL_GRANULARITY, 0, 0, 3_600_000, ConfigurationFlags::DEFAULT, None],
+            ["max-entries", &*MAX_ENTRIES, 0, 0, i64::MAX, ConfigurationFlags::DEFAULT, None],
+            ["max-members-per-key", &*MAX_MEMBERS_PER_KEY, 0, 0, i64::MAX, ConfigurationFlags::DEFAULT, None],
+            ["backlog-threshold", &*BACKLOG_THRESHOLD, 0, 0, i64::MAX, ConfigurationFlags::DEFAULT, None],
+            ["statsd-interval", &*STATSD_INTERVAL, 10_000, 100, 3_600_000, ConfigurationFlags::DEFAULT, None],
+            ["watchdog-timeout", &*WATCHDOG_TIMEOUT, 30_000, 0, 3_600_000, ConfigurationFlags::DEFAULT, None],
+            ["trace-rate", &*TRACE_RATE, 1000, 1, 1_000_000, ConfigurationFlags::DEFAULT, None],
+            ["events-stream-maxlen", &*EVENTS_STREAM_MAXLEN, 10_000, 0, i64::MAX, ConfigurationFlags::DEFAULT, None],
+        ],
+        string: [
+            ["events-channel", &*EVENTS_CHANNEL, "", ConfigurationFlags::DEFAULT, None],
+            ["events-stream", &*EVENTS_STREAM, "", ConfigurationFlags::DEFAULT, None],
+            ["expire-function", &*EXPIRE_FUNCTION, "", ConfigurationFlags::DEFAULT, None],
+            ["expire-script-sha", &*EXPIRE_SCRIPT_SHA, "", ConfigurationFlags::DEFAULT, None],
+            ["statsd-address", &*STATSD_ADDRESS, "", ConfigurationFlags::DEFAULT, Some(Box::new(|_, _, _| WORKER_WAKEUP.notify()))],
+            ["statsd-prefix", &*STATSD_PREFIX, "expiremember", ConfigurationFlags::DEFAULT, None],
+            ["trace-file", &*TRACE_FILE, "", ConfigurationFlags::DEFAULT, None],
+            ["command-prefix", &*COMMAND_PREFIX, "", ConfigurationFlags::IMMUTABLE, None],
+            ["config-file", &*CONFIG_FILE, "", ConfigurationFlags::DEFAULT, None],
+        ],
+        bool: [
+            ["events-include-value", &*EVENTS_INCLUDE_VALUE, false, ConfigurationFlags::DEFAULT, None],
+            ["pause-during-fork", &*PAUSE_DURING_FORK, false, ConfigurationFlags::DEFAULT, None],
+            ["copy-expirations", &*COPY_EXPIRATIONS, false, ConfigurationFlags::DEFAULT, None],
+            ["keydb-compat", &*KEYDB_COMPAT, false, ConfigurationFlags::DEFAULT, None],
+            ["trace", &*TRACE, false, ConfigurationFlags::DEFAULT, None],
+            ["track-hashes", &*TRACK_HASHES, true, ConfigurationFlags::DEFAULT, Some(Box::new(|_, _, _| rebuild_heap()))],
+            ["track-sets", &*TRACK_SETS, true, ConfigurationFlags::DEFAULT, Some(Box::new(|_, _, _| rebuild_heap()))],
+            ["track-zsets", &*TRACK_ZSETS, true, ConfigurationFlags::DEFAULT, Some(Box::new(|_, _, _| rebuild_heap()))],
+            ["track-lists", &*TRACK_LISTS, true, ConfigurationFlags::DEFAULT, Some(Box::new(|_, _, _| rebuild_heap()))],
+            ["track-json", &*TRACK_JSON, true, ConfigurationFlags::DEFAULT, Some(Box::new(|_, _, _| rebuild_heap()))],
+        ],
+        enum: [
+            ["backend", &*BACKEND, Backend::memory, ConfigurationFlags::IMMUTABLE, None],
+            ["scheduler", &*SCHEDULER, Scheduler::heap, ConfigurationFlags::DEFAULT, None],
+            ["driver", &*DRIVER, Driver::thread, ConfigurationFlags::IMMUTABLE, None],
+            ["merge-policy", &*MERGE_POLICY, MergePolicy::arrival, ConfigurationFlags::DEFAULT, None],
+            ["max-entries-policy", &*MAX_ENTRIES_POLICY, MaxEntriesPolicy::reject, ConfigurationFlags::DEFAULT, None],
+            ["keyspace-event-class", &*KEYSPACE_EVENT_CLASS, EventClass::none, ConfigurationFlags::DEFAULT, None],
+            ["profile", &*PROFILE, Profile::custom, ConfigurationFlags::DEFAULT, Some(Box::new(|_, _, _| profile::changed()))],
+        ],
+        module_args_as_configuration: true,
+    ]
 }
@@ -0,0 +1,135 @@
+//! Cap on the number of tracked expirations, `expiremember.max-entries`, and what happens to new
+//! schedules once it is reached, `expiremember.max-entries-policy`: they are rejected, or the
+//! expirations due last are forgotten to make room. A single key is capped separately by
+//! `expiremember.max-members-per-key`, beyond which schedules are always rejected.
+//!
+//! Schedules are also rejected, with a transient error, while more than
+//! `expiremember.backlog-threshold` overdue members wait for the worker, and new ones while the
+//! server is over `maxmemory`.
+
+use redis_module::{enum_configuration, Context, ContextFlags, RedisError, RedisResult};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use crate::{commands, shadow, with_db, ExpiringMember, BACKEND, EXPIRATION_TIMES, MAX_ENTRIES, MAX_ENTRIES_POLICY, MAX_MEMBERS_PER_KEY, BACKLOG_THRESHOLD};
+
+enum_configuration! {
+    /// What happens to a new schedule once `max-entries` expirations are tracked.
+    #[allow(non_camel_case_types)]
+    #[derive(Copy, PartialEq, Eq)]
+    pub enum MaxEntriesPolicy {
+        // The schedule fails with an error.
+        reject = 1,
+        // The expirations with the farthest deadlines are forgotten, their members kept.
+        evict = 2,
+    }
+}
+
+lazy_static! {
+    // Overdue members the worker left scheduled, as of its last count.
+    static ref BACKLOG: AtomicUsize = AtomicUsize::new(0);
+    static ref BACKLOG_REJECTIONS: AtomicU64 = AtomicU64::new(0);
+    static ref OOM_REJECTIONS: AtomicU64 = AtomicU64::new(0);
+    // Whether rejections were logged since the backlog last went under the threshold.
+    static ref BACKLOG_LOGGED: AtomicBool = AtomicBool::new(false);
+}
+
+/// Records the number of overdue members left scheduled, counted up to the threshold if any.
+pub fn set_backlog(overdue: usize) {
+    BACKLOG.store(overdue, Ordering::Relaxed);
+    if overdue < BACKLOG_THRESHOLD.load(Ordering::Relaxed).max(1) as usize {
+        BACKLOG_LOGGED.store(false, Ordering::Relaxed);
+    }
+}
+
+/// (overdue members as last counted, schedules rejected over `backlog-threshold`, over
+/// `maxmemory`).
+pub fn stats() -> (usize, u64, u64) {
+    (BACKLOG.load(Ordering::Relaxed), BACKLOG_REJECTIONS.load(Ordering::Relaxed), OOM_REJECTIONS.load(Ordering::Relaxed))
+}
+
+/// Fails while the backlog is at `backlog-threshold`, clients believing their schedules are
+/// enforced otherwise. Must hold the GIL.
+pub fn check_backlog(ctx: &Context) -> RedisResult<()> {
+    let threshold = BACKLOG_THRESHOLD.load(Ordering::Relaxed);
+    let backlog = BACKLOG.load(Ordering::Relaxed);
+    if threshold <= 0 || backlog < threshold as usize {
+        return Ok(());
+    }
+    BACKLOG_REJECTIONS.fetch_add(1, Ordering::Relaxed);
+    if !BACKLOG_LOGGED.swap(true, Ordering::Relaxed) {
+        ctx.log_warning(&format!("{} or more overdue member expirations are waiting, rejecting new ones until they are below backlog-threshold", backlog));
+    }
+    Err(RedisError::Str("BACKLOG overdue member expirations exceed backlog-threshold, try again later"))
+}
+
+/// Fails for a member not tracked yet while used memory is over `maxmemory`, the server
+/// rejecting writes then. Rescheduling a tracked member takes no memory and deletions free some,
+/// so both go on. Must hold the GIL.
+pub fn check_memory(ctx: &Context, member: &ExpiringMember) -> RedisResult<()> {
+    if !ctx.get_flags().contains(ContextFlags::OOM) || EXPIRATION_TIMES.get(member.db, &member.key, &member.member).is_some() {
+        return Ok(());
+    }
+    OOM_REJECTIONS.fetch_add(1, Ordering::Relaxed);
+    Err(RedisError::Str("OOM used memory is over 'maxmemory', no new member expirations are tracked"))
+}
+
+/// Makes room for the expiration of `member` unless it replaces a tracked one, or fails when its
+/// key is full or under the `reject` policy. Must hold the GIL.
+pub fn make_room(ctx: &Context, member: &ExpiringMember) -> RedisResult<()> {
+    let max_entries = MAX_ENTRIES.load(Ordering::Relaxed);
+    let max_members_per_key = MAX_MEMBERS_PER_KEY.load(Ordering::Relaxed);
+    if (max_entries <= 0 && max_members_per_key <= 0) || EXPIRATION_TIMES.get(member.db, &member.key, &member.member).is_some() {
+        return Ok(());
+    }
+    if max_members_per_key > 0 && EXPIRATION_TIMES.key_len(member.db, &member.key) >= max_members_per_key as usize {
+        return Err(RedisError::Str("ERR max-members-per-key member expirations are tracked for this key already"));
+    }
+    if max_entries <= 0 {
+        return Ok(());
+    }
+    let max_entries = max_entries as usize;
+    let tracked = EXPIRATION_TIMES.len();
+    if tracked < max_entries {
+        return Ok(());
+    }
+
+    match *MAX_ENTRIES_POLICY.lock().unwrap() {
+        MaxEntriesPolicy::reject => Err(RedisError::Str("ERR max-entries member expirations are tracked already")),
+        MaxEntriesPolicy::evict => {
+            // A hundredth of the cap at a time, so the scan is not repeated for every schedule.
+            let evicted = evict(ctx, tracked + 1 - max_entries + max_entries / 100);
+            ctx.log_verbose(&format!("Evicted {} member expirations at max-entries", evicted));
+            Ok(())
+        }
+    }
+}
+
+/// Forgets the `count` expirations due last and propagates that. Returns how many there were.
+fn evict(ctx: &Context, count: usize) -> usize {
+    let mut farthest: BinaryHeap<Reverse<ExpiringMember>> = BinaryHeap::with_capacity(count + 1);
+    for shard in EXPIRATION_TIMES.shards() {
+        for tracked in shard.values() {
+            if farthest.len() == count && farthest.peek().is_some_and(|Reverse(nearest)| nearest.expire_at >= tracked.expire_at) {
+                continue;
+            }
+            farthest.push(Reverse(tracked.clone()));
+            if farthest.len() > count {
+                farthest.pop();
+            }
+        }
+    }
+
+    let backend = *BACKEND.lock().unwrap();
+    let evicted = farthest.len();
+    for Reverse(evicted) in farthest {
+        EXPIRATION_TIMES.remove(evicted.db, &evicted.key, &evicted.member);
+        with_db(ctx, evicted.db, || {
+            shadow::forget(ctx, backend, &evicted.key, &evicted.member);
+            ctx.replicate(&commands::name("EXPIREMEMBER"), &[&*evicted.key, &*evicted.member, "-1"]);
+        });
+    }
+    evicted
+}
@@ -0,0 +1,92 @@
+//! EXPIREMEMBER.MEMORY: an estimate of the memory the module takes, from the sizes of its
+//! structures rather than from the allocator, for capacity planning. Hash tables are counted at
+//! their capacity with a control byte per slot, names once however many structures share them.
+
+use redis_module::{RedisResult, RedisValue};
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::sync::{Arc, RwLock};
+
+use crate::{info, ExpiringMember, ExpiryEvent, Shard, EVENT_LOG, EXPIRATION_QUEUE, EXPIRATION_TIMES, SHARDS};
+
+/// Bytes taken by a hash table of `capacity` slots of `T`.
+fn table<T>(capacity: usize) -> usize {
+    capacity * (size_of::<T>() + 1)
+}
+
+/// Bytes taken by a shared name, counts included.
+fn name(name: &str) -> usize {
+    2 * size_of::<usize>() + name.len()
+}
+
+type Entry = ((i32, Arc<str>, Arc<str>), ExpiringMember);
+type KeyEntry = ((i32, Arc<str>), HashSet<Arc<str>>);
+
+/// (bytes of the table of entries, of the key index, of the names) of `shard`.
+fn shard_bytes(shard: &Shard) -> (usize, usize, usize) {
+    let entries = table::<Entry>(shard.entries.capacity());
+    let mut index = table::<KeyEntry>(shard.keys.capacity());
+    let mut names = 0;
+    for ((_, key), members) in &shard.keys {
+        index += table::<Arc<str>>(members.capacity());
+        names += name(key) + members.iter().map(|member| name(member)).sum::<usize>();
+    }
+    (entries, index, names)
+}
+
+/// A flat list of the estimated bytes of each structure and their total.
+pub fn report() -> RedisResult {
+    let (mut tracked, mut keys, mut entries, mut index, mut names) = (0, 0, 0, 0, 0);
+    for shard in EXPIRATION_TIMES.shards() {
+        let (shard_entries, shard_index, shard_names) = shard_bytes(&shard);
+        tracked += shard.entries.len();
+        keys += shard.keys.len();
+        entries += shard_entries;
+        index += shard_index;
+        names += shard_names;
+    }
+    let shards = SHARDS * size_of::<RwLock<Shard>>();
+    let schedule = info::scheduled() * size_of::<ExpiringMember>();
+    let queue = EXPIRATION_QUEUE.len() * size_of::<ExpiringMember>();
+    let event_log = EVENT_LOG.lock().unwrap().events.capacity() * size_of::<ExpiryEvent>();
+    let total = entries + index + names + shards + schedule + queue + event_log;
+
+    let fields = [
+        ("tracked", tracked),
+        ("keys", keys),
+        ("tracking_map_bytes", entries),
+        ("key_index_bytes", index),
+        ("names_bytes", names),
+        ("shards_bytes", shards),
+        ("schedule_bytes", schedule),
+        ("queue_bytes", queue),
+        ("event_log_bytes", event_log),
+        ("total_bytes", total),
+    ];
+    Ok(RedisValue::Array(fields.into_iter()
+        .flat_map(|(field, value)| [RedisValue::SimpleStringStatic(field), RedisValue::Integer(value as i64)])
+        .collect()))
+}
+
+/// The `count` keys taking the most bytes, each as [key, db, members, bytes], most first. A key's
+/// share of the tracking map is that of its members.
+pub fn report_keys(count: usize) -> RedisResult {
+    let mut keys = Vec::new();
+    for shard in EXPIRATION_TIMES.shards() {
+        let per_entry = table::<Entry>(shard.entries.capacity()) / shard.entries.len().max(1);
+        let per_key = table::<KeyEntry>(shard.keys.capacity()) / shard.keys.len().max(1);
+        for ((db, key), members) in &shard.keys {
+            let bytes = per_key + name(key) + table::<Arc<str>>(members.capacity())
+                + members.iter().map(|member| per_entry + name(member)).sum::<usize>();
+            keys.push(((*db, key.clone()), (members.len(), bytes)));
+        }
+    }
+
+    keys.sort_unstable_by(|(a_key, (_, a_bytes)), (b_key, (_, b_bytes))| b_bytes.cmp(a_bytes).then_with(|| a_key.cmp(b_key)));
+    Ok(RedisValue::Array(keys.into_iter().take(count).map(|((db, key), (members, bytes))| RedisValue::Array(vec![
+        RedisValue::BulkString(key.to_string()),
+        RedisValue::Integer(db as i64),
+        RedisValue::Integer(members as i64),
+        RedisValue::Integer(bytes as i64),
+    ])).collect()))
+}
@@ -0,0 +1,65 @@
+//! EXPIREMEMBER.METRICS: the module's metrics in the Prometheus text exposition format, for an
+//! exporter to pass through as is.
+
+use std::fmt::Write;
+
+use crate::{info, stats};
+
+/// Adds a metric with its HELP and TYPE lines, `samples` being (labels, value) pairs. Labels
+/// starting with `_` are a suffix instead, for the `_sum` and `_count` of a summary.
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(String, f64)]) {
+    let _ = writeln!(out, "# HELP expiremember_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE expiremember_{} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "expiremember_{}{} {}", name, labels, value);
+    }
+}
+
+fn single(value: f64) -> [(String, f64); 1] {
+    [(String::new(), value)]
+}
+
+pub fn render() -> String {
+    let snapshot = info::snapshot();
+    let mut out = String::new();
+    metric(&mut out, "tracked", "gauge", "Member expirations tracked.", &single(snapshot.tracked as f64));
+    metric(&mut out, "scheduled", "gauge", "Entries in the worker's schedule, stale ones included.", &single(snapshot.scheduled as f64));
+    metric(&mut out, "queued", "gauge", "Schedules not taken by the worker yet.", &single(snapshot.queued as f64));
+    metric(&mut out, "overdue", "gauge", "Due members waiting to be deleted.", &single(snapshot.overdue as f64));
+    metric(&mut out, "expired_per_second", "gauge", "Members expired per second over the last one to two seconds.", &single(snapshot.expired_per_sec));
+    metric(&mut out, "worker_started", "gauge", "Whether the expiry cycles were started.", &single(snapshot.started as u8 as f64));
+    metric(&mut out, "worker_parked", "gauge", "Whether the worker waits for a schedule, nothing being scheduled.", &single(snapshot.parked as u8 as f64));
+    if let Some(ago) = snapshot.last_cycle {
+        metric(&mut out, "worker_last_cycle_seconds", "gauge", "Time since the last expiry cycle ended.", &single(ago.as_secs_f64()));
+    }
+    metric(&mut out, "worker_healthy", "gauge", "Whether the worker is alive and its cycles keep ending.", &single((snapshot.health == "ok") as u8 as f64));
+    metric(&mut out, "worker_cycles_total", "counter", "Expiry cycles ended.", &single(snapshot.cycles as f64));
+    metric(&mut out, "worker_stalls_total", "counter", "Times the watchdog found the worker stalled or dead.", &single(snapshot.stalls as f64));
+    metric(&mut out, "worker_cycle_duration_seconds", "gauge", "Time the last expiry cycle took.", &single(snapshot.last_cycle_duration.as_secs_f64()));
+
+    let threads: Vec<(String, f64)> = snapshot.threads.iter().enumerate()
+        .map(|(thread, (members, _))| (format!("{{thread=\"{}\"}}", thread), *members as f64))
+        .collect();
+    metric(&mut out, "expired_members_total", "counter", "Members expired by each deletion thread, the worker being thread 0.", &threads);
+    let batches: Vec<(String, f64)> = snapshot.threads.iter().enumerate()
+        .map(|(thread, (_, batches))| (format!("{{thread=\"{}\"}}", thread), *batches as f64))
+        .collect();
+    metric(&mut out, "batches_total", "counter", "Batches deleted by each deletion thread, the worker being thread 0.", &batches);
+    metric(&mut out, "backlog_rejections_total", "counter", "Schedules rejected over backlog-threshold.", &single(snapshot.backlog_rejections as f64));
+    metric(&mut out, "oom_rejections_total", "counter", "Schedules rejected over maxmemory.", &single(snapshot.oom_rejections as f64));
+    metric(&mut out, "gil_wait_seconds_total", "counter", "Time spent waiting for the server lock.", &single(snapshot.gil_wait.as_secs_f64()));
+    metric(&mut out, "gil_held_seconds_total", "counter", "Time spent holding the server lock.", &single(snapshot.gil_held.as_secs_f64()));
+    let lag = &snapshot.lag;
+    metric(&mut out, "expiry_lag_seconds", "summary", "Time between the deadlines of expired members and their deletion.", &[
+        ("{quantile=\"0.5\"}".to_string(), lag.p50.as_secs_f64()),
+        ("{quantile=\"0.95\"}".to_string(), lag.p95.as_secs_f64()),
+        ("{quantile=\"0.99\"}".to_string(), lag.p99.as_secs_f64()),
+        ("{quantile=\"1\"}".to_string(), lag.max.as_secs_f64()),
+        ("_sum".to_string(), lag.sum.as_secs_f64()),
+        ("_count".to_string(), lag.count as f64),
+    ]);
+    for (name, description, total) in stats::totals() {
+        metric(&mut out, &format!("{}_total", name), "counter", description, &single(total as f64));
+    }
+    out
+}
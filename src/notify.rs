@@ -0,0 +1,76 @@
+//! Expiry signals besides Pub/Sub: a keyspace event per key whose members expired, under the
+//! class `expiremember.keyspace-event-class`, and an entry per expired member appended to the
+//! stream `expiremember.events-stream`. Both are off by default, and compiled out without the
+//! `notifications` feature.
+
+use redis_module::enum_configuration;
+#[cfg(feature = "notifications")]
+use redis_module::{CallOptionsBuilder, CallResult, Context, NotifyEvent};
+
+#[cfg(feature = "notifications")]
+use crate::stats::{self, Counter};
+#[cfg(feature = "notifications")]
+use crate::{Container, ExpiringMember};
+
+/// Name of the keyspace event.
+#[cfg(feature = "notifications")]
+const EVENT: &str = "expiremember";
+
+enum_configuration! {
+    /// Class the keyspace events are sent under, matched against `notify-keyspace-events`.
+    #[allow(non_camel_case_types)]
+    #[derive(Copy, PartialEq, Eq)]
+    pub enum EventClass {
+        // No keyspace events.
+        none = 0,
+        // `g`.
+        generic = 1,
+        // `x`, along with the server's own expirations.
+        expired = 2,
+        // `h`, `s`, `z`, `l` or `d`, the class of the key's type.
+        typed = 3,
+        // `d`, events of modules.
+        module = 4,
+    }
+}
+
+/// Sends the keyspace event for members of `key` having expired. Must hold the GIL, with the
+/// database of `key` selected.
+#[cfg(feature = "notifications")]
+pub fn keyspace(ctx: &Context, class: EventClass, container: Container, key: &str) {
+    let class = match (class, container) {
+        (EventClass::none, _) => return,
+        (EventClass::generic, _) => NotifyEvent::GENERIC,
+        (EventClass::expired, _) => NotifyEvent::EXPIRED,
+        (EventClass::typed, Container::Hash) => NotifyEvent::HASH,
+        (EventClass::typed, Container::Set) => NotifyEvent::SET,
+        (EventClass::typed, Container::ZSet) => NotifyEvent::ZSET,
+        (EventClass::typed, Container::List) => NotifyEvent::LIST,
+        (EventClass::typed, Container::Json) => NotifyEvent::MODULE,
+        (EventClass::module, _) => NotifyEvent::MODULE,
+    };
+    ctx.notify_keyspace_event(class, EVENT, &ctx.create_string(key.as_bytes()));
+}
+
+/// Appends `member` to `stream`, trimmed to about `maxlen` entries unless 0, propagated to
+/// replicas and the AOF like the deletion. Must hold the GIL, with the database of the member
+/// selected.
+#[cfg(feature = "notifications")]
+pub fn stream(ctx: &Context, stream: &str, maxlen: usize, member: &ExpiringMember, value: Option<&str>) {
+    let maxlen = maxlen.to_string();
+    let expired_at = member.expire_at.unix_ms().to_string();
+    let mut args = vec![stream];
+    if maxlen != "0" {
+        args.extend(["MAXLEN", "~", &maxlen]);
+    }
+    args.extend(["*", "key", &member.key, "member", &member.member, "expired_at", &expired_at]);
+    if let Some(value) = value {
+        args.extend(["value", value]);
+    }
+
+    let options = CallOptionsBuilder::new().replicate().build();
+    let result: CallResult = ctx.call_ext("XADD", &options, args.as_slice());
+    if let Err(err) = result {
+        stats::failed(ctx, Counter::NotificationsFailed, 1, || format!("Could not add an expiry event to stream '{}': {}", stream, err));
+    }
+}
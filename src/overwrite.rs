@@ -0,0 +1,182 @@
+//! Per-key policies for tracked members whose value is overwritten, set with EXPIREMEMBER.POLICY.
+
+use lazy_static::lazy_static;
+use redis_module::Context;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::deadline::Deadline;
+#[cfg(feature = "rules")]
+use crate::rules;
+use crate::stats::{self, Counter};
+use crate::{forget_members, schedule_member, shadow, ExpiringMember, EXPIRATION_TIMES, IS_REPLICA};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    // The expiration is kept, the default.
+    Keep,
+    // The expiration is forgotten.
+    Clear,
+    // The member expires this long after the write.
+    Reset(Duration),
+}
+
+// Policies other than `Keep` by (database, key), with the number of databases each key has one
+// in, so the command filter checks a key without going through them all.
+#[derive(Default)]
+struct Policies {
+    by_key: HashMap<(i32, String), OverwritePolicy>,
+    databases: HashMap<String, usize>,
+}
+
+impl Policies {
+    fn insert(&mut self, db: i32, key: String, policy: OverwritePolicy) {
+        if self.by_key.insert((db, key.clone()), policy).is_none() {
+            *self.databases.entry(key).or_default() += 1;
+        }
+    }
+
+    fn remove(&mut self, db: i32, key: &str) -> Option<OverwritePolicy> {
+        let policy = self.by_key.remove(&(db, key.to_string()))?;
+        if let Some(count) = self.databases.get_mut(key) {
+            *count -= 1;
+            if *count == 0 {
+                self.databases.remove(key);
+            }
+        }
+        Some(policy)
+    }
+}
+
+lazy_static! {
+    // Only used on the main thread, so a fork never sees it locked.
+    static ref POLICIES: Mutex<Policies> = Mutex::new(Policies::default());
+}
+
+/// The policy of `key`, or else of the first rule of the rules file matching it.
+pub fn get(db: i32, key: &str) -> OverwritePolicy {
+    let policy = POLICIES.lock().unwrap().by_key.get(&(db, key.to_string())).copied();
+    policy.or_else(|| rule(key)).unwrap_or(OverwritePolicy::Keep)
+}
+
+#[cfg(feature = "rules")]
+fn rule(key: &str) -> Option<OverwritePolicy> {
+    rules::policy(key)
+}
+
+#[cfg(not(feature = "rules"))]
+fn rule(_key: &str) -> Option<OverwritePolicy> {
+    None
+}
+
+pub fn set(db: i32, key: String, policy: OverwritePolicy) {
+    let mut policies = POLICIES.lock().unwrap();
+    if policy == OverwritePolicy::Keep {
+        policies.remove(db, &key);
+    } else {
+        policies.insert(db, key, policy);
+    }
+}
+
+/// Whether `key` has a policy in any database, for the command filter, which cannot tell the
+/// database of a command.
+pub fn has_policy(key: &str) -> bool {
+    POLICIES.lock().unwrap().databases.contains_key(key)
+        || rule(key).is_some_and(|policy| policy != OverwritePolicy::Keep)
+}
+
+/// Drops the policy of a deleted key.
+pub fn forget(db: i32, key: &str) {
+    POLICIES.lock().unwrap().remove(db, key);
+}
+
+/// Drops the policies of a flushed database.
+pub fn forget_db(db: i32) {
+    let mut policies = POLICIES.lock().unwrap();
+    let keys: Vec<String> = policies.by_key.keys().filter(|(policy_db, _)| *policy_db == db).map(|(_, key)| key.clone()).collect();
+    for key in keys {
+        policies.remove(db, &key);
+    }
+}
+
+pub fn rename(db: i32, from: &str, to: &str) {
+    let mut policies = POLICIES.lock().unwrap();
+    if let Some(policy) = policies.remove(db, from) {
+        policies.insert(db, to.to_string(), policy);
+    } else {
+        policies.remove(db, to);
+    }
+}
+
+pub fn move_key(key: &str, from_db: i32, to_db: i32) {
+    let mut policies = POLICIES.lock().unwrap();
+    if let Some(policy) = policies.remove(from_db, key) {
+        policies.insert(to_db, key.to_string(), policy);
+    } else {
+        policies.remove(to_db, key);
+    }
+}
+
+pub fn swap_dbs(first: i32, second: i32) {
+    let mut policies = POLICIES.lock().unwrap();
+    // Every key keeps its number of databases.
+    policies.by_key = std::mem::take(&mut policies.by_key).into_iter()
+        .map(|((db, key), policy)| {
+            let db = if db == first { second } else if db == second { first } else { db };
+            ((db, key), policy)
+        })
+        .collect();
+}
+
+pub fn clear() {
+    *POLICIES.lock().unwrap() = Policies::default();
+}
+
+/// Every policy as (database, key, policy), for the RDB aux data.
+pub fn all() -> Vec<(i32, String, OverwritePolicy)> {
+    POLICIES.lock().unwrap().by_key.iter().map(|((db, key), policy)| (*db, key.clone(), *policy)).collect()
+}
+
+pub fn is_empty() -> bool {
+    POLICIES.lock().unwrap().by_key.is_empty()
+}
+
+pub fn replace(loaded: Vec<(i32, String, OverwritePolicy)>) {
+    let mut policies = Policies::default();
+    for (db, key, policy) in loaded {
+        policies.insert(db, key, policy);
+    }
+    *POLICIES.lock().unwrap() = policies;
+}
+
+/// Applies the policy of `key` to the tracked ones among `members`, just written by a command.
+/// Must hold the GIL, with database `db` selected.
+pub fn members_written(ctx: &Context, db: i32, key: &str, members: Vec<String>) {
+    let policy = get(db, key);
+    if policy == OverwritePolicy::Keep {
+        return;
+    }
+    let tracked: Vec<String> = members.into_iter()
+        .filter(|member| EXPIRATION_TIMES.get(db, key, member).is_some())
+        .collect();
+
+    match policy {
+        OverwritePolicy::Keep => {}
+        OverwritePolicy::Clear => forget_members(ctx, db, key, tracked),
+        // Replicas get the new schedules from their primary.
+        OverwritePolicy::Reset(ttl) if !tracked.is_empty() && !IS_REPLICA.load(Ordering::SeqCst) => {
+            let key = key.to_string();
+            shadow::after_notification(ctx, move |ctx| {
+                let expire_at = Deadline::after(ttl);
+                for member in &tracked {
+                    if let Err(err) = schedule_member(ctx, ExpiringMember::new(db, key.clone(), member.clone(), expire_at)) {
+                        stats::failed(ctx, Counter::SchedulesDropped, 1, || format!("Could not reset a member expiration of '{}': {}", key, err));
+                    }
+                }
+            });
+        }
+        OverwritePolicy::Reset(_) => {}
+    }
+}
@@ -0,0 +1,234 @@
+use redis_module::{error::Error, native_types::RedisType, raw, Context};
+use std::collections::BTreeSet;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_longlong, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::overwrite::{self, OverwritePolicy};
+use crate::deadline::Deadline;
+use crate::schedule::Priority;
+use crate::{commands, config_get, rebuild_heap, with_db, Backend, ExpiringMember, MergePolicy, BACKEND, EXPIRATION_TIMES, LOGICAL_CLOCK, MERGE_POLICY};
+
+// 2 added the logical clock of each expiration, 3 the overwrite policies, 4 the database of
+// both, 5 the priority of each expiration.
+const ENCODING_VERSION: i32 = 5;
+
+/// Name of the AOF carrier keys, see `update_carriers`.
+pub const CARRIER_KEY: &str = "expiremember:aof";
+
+// Whether the memory backend keeps AOF carrier keys.
+static AOF_CARRIERS: AtomicBool = AtomicBool::new(false);
+
+/// Carrier type for the module's RDB aux data. Its only keys are the AOF carriers, holding
+/// nothing: a rewrite of the AOF without the RDB preamble calls `aof_rewrite` on them, which writes
+/// the expirations of the memory backend there.
+pub static EXPIREMEMBER_TYPE: RedisType = RedisType::new(
+    "expmember",
+    ENCODING_VERSION,
+    raw::RedisModuleTypeMethods {
+        version: raw::REDISMODULE_TYPE_METHOD_VERSION as u64,
+        rdb_load: Some(carrier_rdb_load),
+        rdb_save: Some(carrier_rdb_save),
+        aof_rewrite: Some(aof_rewrite),
+        free: Some(carrier_free),
+
+        mem_usage: None,
+        digest: None,
+
+        aux_load: Some(aux_load),
+        aux_save: Some(aux_save),
+        // Redis 7.2+ skips the aux field entirely when nothing is tracked, so
+        // such RDB files can be loaded without the module.
+        aux_save2: Some(aux_save2),
+        aux_save_triggers: raw::REDISMODULE_AUX_BEFORE_RDB as c_int,
+
+        free_effort: None,
+        unlink: None,
+        copy: None,
+        defrag: None,
+
+        copy2: None,
+        free_effort2: None,
+        mem_usage2: None,
+        unlink2: None,
+    },
+);
+
+/// Value of an AOF carrier key.
+pub struct AofCarrier;
+
+/// Serializes every tracked expiration as (key, member, unix ms deadline, clock, db, priority),
+/// then every overwrite policy as (key, kind, ttl ms, db).
+///
+/// This may run in the BGSAVE child, where only read access to the state is safe.
+unsafe extern "C" fn aux_save(rdb: *mut raw::RedisModuleIO, _when: c_int) {
+    // Nothing writes meanwhile, in the child or while SAVE holds the GIL, so the count holds.
+    raw::save_unsigned(rdb, EXPIRATION_TIMES.len() as u64);
+    for shard in EXPIRATION_TIMES.shards() {
+        for member in shard.values() {
+            raw::save_string(rdb, &member.key);
+            raw::save_string(rdb, &member.member);
+            raw::save_unsigned(rdb, member.expire_at.unix_ms());
+            raw::save_unsigned(rdb, member.clock);
+            raw::save_unsigned(rdb, member.db as u64);
+            raw::save_unsigned(rdb, member.priority.as_u64());
+        }
+    }
+
+    let policies = overwrite::all();
+    raw::save_unsigned(rdb, policies.len() as u64);
+    for (db, key, policy) in &policies {
+        let (kind, ttl) = match policy {
+            OverwritePolicy::Keep => (0, 0),
+            OverwritePolicy::Clear => (1, 0),
+            OverwritePolicy::Reset(ttl) => (2, ttl.as_millis() as u64),
+        };
+        raw::save_string(rdb, key);
+        raw::save_unsigned(rdb, kind);
+        raw::save_unsigned(rdb, ttl);
+        raw::save_unsigned(rdb, *db as u64);
+    }
+}
+
+unsafe extern "C" fn aux_save2(rdb: *mut raw::RedisModuleIO, when: c_int) {
+    if !EXPIRATION_TIMES.is_empty() || !overwrite::is_empty() {
+        aux_save(rdb, when);
+    }
+}
+
+unsafe extern "C" fn aux_load(rdb: *mut raw::RedisModuleIO, encver: c_int, _when: c_int) -> c_int {
+    if encver > ENCODING_VERSION {
+        return raw::REDISMODULE_ERR as c_int;
+    }
+
+    let loaded = load_members(rdb, encver).and_then(|members| {
+        let policies = if encver >= 3 { load_policies(rdb, encver)? } else { Vec::new() };
+        Ok((members, policies))
+    });
+    match loaded {
+        Ok((members, policies)) => {
+            // The loaded dataset replaces the current one, and so do its expirations and policies.
+            overwrite::replace(policies);
+            EXPIRATION_TIMES.clear();
+            for member in members {
+                LOGICAL_CLOCK.fetch_max(member.clock, Ordering::SeqCst);
+                EXPIRATION_TIMES.insert(member);
+            }
+
+            rebuild_heap();
+            raw::REDISMODULE_OK as c_int
+        }
+        Err(_) => raw::REDISMODULE_ERR as c_int,
+    }
+}
+
+fn load_members(rdb: *mut raw::RedisModuleIO, encver: c_int) -> Result<Vec<ExpiringMember>, Error> {
+    let count = raw::load_unsigned(rdb)?;
+    let mut members = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let key = raw::load_string(rdb)?.to_string();
+        let member = raw::load_string(rdb)?.to_string();
+        let expire_at = Deadline::from_unix(Duration::from_millis(raw::load_unsigned(rdb)?));
+        let clock = if encver >= 2 { raw::load_unsigned(rdb)? } else { 0 };
+        // Earlier versions applied every expiration to database 0.
+        let db = if encver >= 4 { raw::load_unsigned(rdb)? as i32 } else { 0 };
+        let priority = if encver >= 5 { Priority::from_u64(raw::load_unsigned(rdb)?) } else { Priority::Normal };
+        members.push(ExpiringMember { clock, priority, ..ExpiringMember::new(db, key, member, expire_at) });
+    }
+    Ok(members)
+}
+
+fn load_policies(rdb: *mut raw::RedisModuleIO, encver: c_int) -> Result<Vec<(i32, String, OverwritePolicy)>, Error> {
+    let count = raw::load_unsigned(rdb)?;
+    let mut policies = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let key = raw::load_string(rdb)?.to_string();
+        let policy = match (raw::load_unsigned(rdb)?, raw::load_unsigned(rdb)?) {
+            (1, _) => OverwritePolicy::Clear,
+            (2, ttl) => OverwritePolicy::Reset(Duration::from_millis(ttl)),
+            _ => OverwritePolicy::Keep,
+        };
+        let db = if encver >= 4 { raw::load_unsigned(rdb)? as i32 } else { 0 };
+        policies.push((db, key, policy));
+    }
+    Ok(policies)
+}
+
+/// Keeps an AOF carrier key in every database with tracked expirations when the memory backend
+/// runs with `appendonly yes` and `aof-use-rdb-preamble no`, and deletes them otherwise. Called
+/// at load, once loading ended and when either setting changes. Must hold the GIL.
+pub fn update_carriers(ctx: &Context) {
+    let needed = *BACKEND.lock().unwrap() == Backend::memory
+        && config_get(ctx, "appendonly").as_deref() == Some("yes")
+        && config_get(ctx, "aof-use-rdb-preamble").as_deref() == Some("no");
+    let had = AOF_CARRIERS.swap(needed, Ordering::SeqCst);
+    if needed {
+        let dbs: BTreeSet<i32> = EXPIRATION_TIMES.collect(|member| Some(member.db)).into_iter().collect();
+        for db in dbs {
+            with_db(ctx, db, || store_carrier(ctx));
+        }
+    } else if had {
+        let databases = config_get(ctx, "databases").and_then(|databases| databases.parse().ok()).unwrap_or(16);
+        for db in 0..databases {
+            with_db(ctx, db, || {
+                let key = ctx.open_key_writable(&ctx.create_string(CARRIER_KEY));
+                if let Ok(Some(_)) = key.get_value::<AofCarrier>(&EXPIREMEMBER_TYPE) {
+                    let _ = key.delete();
+                }
+            });
+        }
+    }
+}
+
+/// Creates the AOF carrier key of the selected database if needed. Must hold the GIL.
+pub fn store_carrier(ctx: &Context) {
+    if !AOF_CARRIERS.load(Ordering::Relaxed) {
+        return;
+    }
+    let key = ctx.open_key_writable(&ctx.create_string(CARRIER_KEY));
+    // The name taken by a key of another type is left alone.
+    if key.is_empty() {
+        let _ = key.set_value(&EXPIREMEMBER_TYPE, AofCarrier);
+    }
+}
+
+unsafe extern "C" fn carrier_rdb_save(_rdb: *mut raw::RedisModuleIO, _value: *mut c_void) {}
+
+unsafe extern "C" fn carrier_rdb_load(_rdb: *mut raw::RedisModuleIO, _encver: c_int) -> *mut c_void {
+    Box::into_raw(Box::new(AofCarrier)).cast::<c_void>()
+}
+
+unsafe extern "C" fn carrier_free(value: *mut c_void) {
+    drop(Box::from_raw(value.cast::<AofCarrier>()));
+}
+
+/// Rewrites a carrier key as an `EXPIREMEMBERAT key member <unix-ms> ms` for every expiration
+/// tracked in its database, with its clock under the `clock` merge policy and its priority unless
+/// normal. Only the rewritten AOF receives them.
+///
+/// This runs in the AOF rewrite child, where only read access to the state is safe.
+unsafe extern "C" fn aof_rewrite(aof: *mut raw::RedisModuleIO, _key: *mut raw::RedisModuleString, _value: *mut c_void) {
+    let db = raw::RedisModule_GetDbIdFromIO.unwrap()(aof);
+    let clock_policy = *MERGE_POLICY.lock().unwrap() == MergePolicy::clock;
+    let emit = raw::RedisModule_EmitAOF.unwrap();
+    let command = CString::new(commands::name("EXPIREMEMBERAT")).unwrap();
+    for shard in EXPIRATION_TIMES.shards() {
+        for member in shard.values().filter(|member| member.db == db) {
+            let (key, field) = (member.key.as_ptr().cast::<c_char>(), member.member.as_ptr().cast::<c_char>());
+            let (key_len, field_len) = (member.key.len(), member.member.len());
+            let deadline = member.expire_at.unix_ms() as c_longlong;
+            let clock = member.clock as c_longlong;
+            let priority = CString::new(member.priority.name()).unwrap();
+            match (clock_policy, member.priority != Priority::Normal) {
+                (false, false) => emit(aof, command.as_ptr(), c"bblc".as_ptr(), key, key_len, field, field_len, deadline, c"ms".as_ptr()),
+                (true, false) => emit(aof, command.as_ptr(), c"bblccl".as_ptr(), key, key_len, field, field_len, deadline, c"ms".as_ptr(),
+                    c"CLOCK".as_ptr(), clock),
+                (false, true) => emit(aof, command.as_ptr(), c"bblccc".as_ptr(), key, key_len, field, field_len, deadline, c"ms".as_ptr(),
+                    c"PRIORITY".as_ptr(), priority.as_ptr()),
+                (true, true) => emit(aof, command.as_ptr(), c"bblcclcc".as_ptr(), key, key_len, field, field_len, deadline, c"ms".as_ptr(),
+                    c"CLOCK".as_ptr(), clock, c"PRIORITY".as_ptr(), priority.as_ptr()),
+            }
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Deletion threads for large expiry waves. With `expire-threads` above 1, the due members of a
+//! cycle are split by key among the worker and `expire-threads - 1` threads next to it, each
+//! checking its share against the tracked expirations and deleting it in batches with its own
+//! thread-safe context. Deletions still take turns on the GIL, the threads overlap the work
+//! around them. A new `expire-threads` takes effect on the next wave.
+
+use redis_module::ThreadSafeContext;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+use lazy_static::lazy_static;
+
+use crate::{expire_members, throttle, watchdog, ExpiringMember, ExpiryHooks, Gil, MembersByKey, EXPIRATION_TIMES, EXPIRE_BATCH, EXPIRE_THREADS};
+
+/// Work done by one deletion thread, the worker being the first.
+#[derive(Default)]
+pub struct ThreadStats {
+    pub members: AtomicU64,
+    pub batches: AtomicU64,
+}
+
+lazy_static! {
+    static ref STATS: Mutex<Vec<Arc<ThreadStats>>> = Mutex::new(Vec::new());
+    // Set on server shutdown, deletions stop from then on.
+    static ref SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+    // Threads of the current pool, the worker included.
+    static ref THREADS: AtomicUsize = AtomicUsize::new(0);
+}
+
+struct Job {
+    share: Vec<ExpiringMember>,
+    hooks: Arc<ExpiryHooks>,
+    // Receives the members held back.
+    done: Sender<Vec<ExpiringMember>>,
+}
+
+pub struct Pool {
+    threads: Vec<Sender<Job>>,
+    stats: Arc<ThreadStats>,
+}
+
+impl Pool {
+    /// The worker's pool, with `threads - 1` deletion threads started.
+    /// The stats of a pool started before, by a worker that panicked, are carried on.
+    pub fn start(threads: usize) -> Self {
+        let mut pool = Pool { threads: Vec::new(), stats: thread_stats(0) };
+        pool.resize(threads);
+        pool
+    }
+
+    /// Starts or stops deletion threads for `threads` in all. Stopped threads exit once they have
+    /// no job left, which is right away, the pool waiting for its jobs within a wave.
+    fn resize(&mut self, threads: usize) {
+        let threads = threads.max(1);
+        self.threads.truncate(threads - 1);
+        for thread in self.threads.len() + 1..threads {
+            let (jobs, receiver) = mpsc::channel();
+            let stats = thread_stats(thread);
+            thread::spawn(move || run(receiver, stats));
+            self.threads.push(jobs);
+        }
+        THREADS.store(threads, Ordering::Relaxed);
+    }
+
+    /// Expires the members of `due` still tracked with the same schedule, split by key among the
+    /// threads unless `gil` is held already. Returns the members held back.
+    pub fn expire(&mut self, gil: &Gil, hooks: ExpiryHooks, due: Vec<ExpiringMember>) -> Vec<ExpiringMember> {
+        if matches!(gil, Gil::Held(_)) {
+            return expire_share(gil, &hooks, due, &self.stats);
+        }
+        let threads = EXPIRE_THREADS.load(Ordering::Relaxed).max(1) as usize;
+        if threads != self.threads.len() + 1 {
+            self.resize(threads);
+        }
+        if self.threads.is_empty() {
+            return expire_share(gil, &hooks, due, &self.stats);
+        }
+
+        let mut shares: Vec<Vec<ExpiringMember>> = (0..=self.threads.len()).map(|_| Vec::new()).collect();
+        let count = shares.len();
+        for member in due {
+            shares[EXPIRATION_TIMES.shard_index(&member.key) % count].push(member);
+        }
+        let own = shares.pop().unwrap_or_default();
+
+        let hooks = Arc::new(hooks);
+        let (done, results) = mpsc::channel();
+        let mut pending = 0;
+        for (jobs, share) in self.threads.iter().zip(shares) {
+            if !share.is_empty() && jobs.send(Job { share, hooks: hooks.clone(), done: done.clone() }).is_ok() {
+                pending += 1;
+            }
+        }
+        // Every job reports back, see `run`.
+        drop(done);
+        let mut deferred = expire_share(gil, &hooks, own, &self.stats);
+        for held_back in results.iter().take(pending) {
+            deferred.extend(held_back);
+        }
+        deferred
+    }
+}
+
+/// The stats of the `thread`th deletion thread, the worker being the first.
+fn thread_stats(thread: usize) -> Arc<ThreadStats> {
+    let mut all = STATS.lock().unwrap();
+    if all.len() <= thread {
+        all.push(Arc::new(ThreadStats::default()));
+    }
+    all[thread].clone()
+}
+
+/// Runs the jobs of a deletion thread. A job that panicked holds its whole share back, so it is
+/// retried by a later cycle, which skips the members it expired already.
+fn run(jobs: Receiver<Job>, stats: Arc<ThreadStats>) {
+    let thread_ctx = ThreadSafeContext::new();
+    for job in jobs {
+        let share = job.share.clone();
+        let expired = panic::catch_unwind(AssertUnwindSafe(|| expire_share(&Gil::Thread(&thread_ctx), &job.hooks, job.share, &stats)));
+        let held_back = expired.unwrap_or_else(|panic| {
+            watchdog::panicked(panic);
+            share
+        });
+        let _ = job.done.send(held_back);
+    }
+}
+
+/// Expires the current members of `share` in batches of `expire-batch`, releasing the GIL in
+/// between. Returns the members held back.
+fn expire_share(gil: &Gil, hooks: &ExpiryHooks, share: Vec<ExpiringMember>, stats: &ThreadStats) -> Vec<ExpiringMember> {
+    let mut members_to_expire = HashMap::new();
+    for member in EXPIRATION_TIMES.retain_current(share) {
+        members_to_expire.entry((member.db, member.key.clone()))
+                         .or_insert_with(Vec::new)
+                         .push(member);
+    }
+
+    let mut deferred = Vec::new();
+    let batch_size = EXPIRE_BATCH.load(Ordering::Relaxed).max(1) as usize;
+    for (i, batch) in into_batches(members_to_expire, batch_size).into_iter().enumerate() {
+        if i > 0 {
+            // Over `max-lock-percent`, the rest is held back until the GIL may be taken again.
+            if throttle::lock_free_at().is_some() {
+                deferred.extend(batch.into_values().flatten());
+                continue;
+            }
+            thread::yield_now();
+        }
+        // The shutdown event runs under the GIL, nothing is deleted once it has.
+        let size = batch.values().map(Vec::len).sum::<usize>();
+        let held_back = gil.with(|ctx| match SHUTTING_DOWN.load(Ordering::SeqCst) {
+            true => batch.into_values().flatten().collect(),
+            false => expire_members(ctx, hooks, batch),
+        });
+        stats.batches.fetch_add(1, Ordering::Relaxed);
+        stats.members.fetch_add((size - held_back.len()) as u64, Ordering::Relaxed);
+        deferred.extend(held_back);
+    }
+    deferred
+}
+
+/// Splits the members to expire into batches of at most `size` members. Keys take turns, each
+/// batch shared evenly among the keys left, smallest first, so a key with many due members does
+/// not hold back the others until it is done: those with a handful are all in the first batch.
+fn into_batches(members_to_expire: MembersByKey, size: usize) -> Vec<MembersByKey> {
+    let mut keys: Vec<_> = members_to_expire.into_iter().collect();
+    keys.sort_by_key(|(_, members)| members.len());
+    let mut keys = VecDeque::from(keys);
+
+    let mut batches = Vec::new();
+    while !keys.is_empty() {
+        let mut batch = HashMap::new();
+        let mut room = size;
+        // Keys yet to take their turn in this batch, those with members left go to the back.
+        let mut turns = keys.len();
+        while room > 0 && turns > 0 {
+            let Some((key, mut members)) = keys.pop_front() else { break };
+            let share = room.div_ceil(turns).min(members.len());
+            let rest = members.split_off(share);
+            room -= share;
+            turns -= 1;
+            if !rest.is_empty() {
+                keys.push_back((key.clone(), rest));
+            }
+            batch.insert(key, members);
+        }
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Threads the due members are split among, the worker included.
+#[cfg(feature = "debug")]
+pub fn threads() -> usize {
+    THREADS.load(Ordering::Relaxed)
+}
+
+/// (members, batches) expired by each deletion thread so far, the worker first, including
+/// threads stopped since.
+pub fn stats() -> Vec<(u64, u64)> {
+    STATS.lock().unwrap().iter()
+        .map(|stats| (stats.members.load(Ordering::Relaxed), stats.batches.load(Ordering::Relaxed)))
+        .collect()
+}
+
+/// Members expired by every deletion thread so far.
+#[cfg(feature = "debug")]
+pub fn expired() -> u64 {
+    STATS.lock().unwrap().iter().map(|stats| stats.members.load(Ordering::Relaxed)).sum()
+}
+
+/// Stops every deletion, called on server shutdown. Threads waiting for the GIL give their
+/// batch up once they get it.
+pub fn shutdown() {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+}
@@ -0,0 +1,86 @@
+//! Configuration profiles, `expiremember.profile`: presets of the knobs trading how soon members
+//! are deleted after their deadline against the CPU and lock time spent deleting them. Choosing
+//! one at runtime sets all of its knobs. At load, where the configuration file may set some of
+//! them as well, it only sets those left at their default.
+
+use redis_module::{enum_configuration, Context};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use crate::{
+    config, EXPIRE_BATCH, EXPIRE_THREADS, GC_EFFORT, MAX_LOCK_PERCENT, MAX_SLEEP, PROFILE, QUEUE_DRAIN_THRESHOLD,
+    TTL_GRANULARITY, WORKER_INTERVAL, WORKER_WAKEUP,
+};
+
+enum_configuration! {
+    /// Preset of the expiry knobs.
+    #[allow(non_camel_case_types)]
+    #[derive(Copy, PartialEq, Eq)]
+    pub enum Profile {
+        // The knobs as set one by one.
+        custom = 0,
+        // Deleted within milliseconds of the deadline, with more wakeups and a deletion thread.
+        low_latency = 1,
+        // The defaults.
+        balanced = 2,
+        // Deleted within a second or so of the deadline, in fewer and larger batches, holding
+        // the lock at most a quarter of the time.
+        low_cpu = 3,
+    }
+}
+
+/// Each knob with its default, then its value under `low_latency`, `balanced` and `low_cpu`.
+fn knobs() -> [(&'static str, &'static AtomicI64, i64, [i64; 3]); 8] {
+    [
+        ("worker-interval", &WORKER_INTERVAL, 100, [10, 100, 500]),
+        ("max-sleep", &MAX_SLEEP, 1000, [100, 1000, 5000]),
+        ("expire-batch", &EXPIRE_BATCH, 1000, [200, 1000, 5000]),
+        ("expire-threads", &EXPIRE_THREADS, 1, [2, 1, 1]),
+        ("max-lock-percent", &MAX_LOCK_PERCENT, 100, [100, 100, 25]),
+        ("queue-drain-threshold", &QUEUE_DRAIN_THRESHOLD, 5000, [500, 5000, 50_000]),
+        ("ttl-granularity", &TTL_GRANULARITY, 0, [0, 0, 1000]),
+        ("gc-effort", &GC_EFFORT, 100, [100, 100, 20]),
+    ]
+}
+
+fn preset(profile: Profile) -> Option<usize> {
+    match profile {
+        Profile::custom => None,
+        Profile::low_latency => Some(0),
+        Profile::balanced => Some(1),
+        Profile::low_cpu => Some(2),
+    }
+}
+
+/// Sets the knobs of a profile chosen with `CONFIG SET`. Those from the configuration file are
+/// left to `loaded`.
+pub fn changed() {
+    if !config::is_loaded() {
+        return;
+    }
+    let Some(preset) = preset(*PROFILE.lock().unwrap()) else {
+        return;
+    };
+    for (_, knob, _, values) in knobs() {
+        knob.store(values[preset], Ordering::Relaxed);
+    }
+    // A shorter `max-sleep` is waited for no longer than it takes.
+    WORKER_WAKEUP.notify();
+}
+
+/// Sets the knobs of the profile loaded that are still at their default.
+pub fn loaded(ctx: &Context) {
+    let profile = *PROFILE.lock().unwrap();
+    let Some(preset) = preset(profile) else {
+        return;
+    };
+    let mut kept = Vec::new();
+    for (name, knob, default, values) in knobs() {
+        match knob.load(Ordering::Relaxed) == default {
+            true => knob.store(values[preset], Ordering::Relaxed),
+            false => kept.push(name),
+        }
+    }
+    if !kept.is_empty() {
+        ctx.log_notice(&format!("expiremember.profile applied, except to the settings given: {}", kept.join(", ")));
+    }
+}
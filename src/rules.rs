@@ -0,0 +1,173 @@
+//! Rules file, `expiremember.config-file`: exclusions, default overwrite policies by key pattern
+//! and module settings, kept outside redis.conf so they can be managed like any other file. The
+//! file is reloaded when it changes, or with EXPIREMEMBER.RELOADCONF. One directive per line,
+//! `#` starting a comment:
+//!
+//! ```text
+//! exclude <pattern>
+//! policy <pattern> keep | clear | reset <ttl> [s | ms]
+//! set <name> <value>
+//! ```
+//!
+//! A file that does not parse, or whose settings are rejected, changes nothing: the rules loaded
+//! before are kept.
+
+use lazy_static::lazy_static;
+use redis_module::{logging, Context, RedisError, RedisString, RedisValue, ThreadSafeContext};
+use std::fs;
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::deadline::MAX_AHEAD;
+use crate::overwrite::OverwritePolicy;
+use crate::snapshot::glob_match;
+use crate::CONFIG_FILE;
+
+/// How often the file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct Rules {
+    // Patterns of keys whose members cannot be scheduled.
+    excludes: Vec<String>,
+    // Pattern and policy, the first matching a key applying.
+    policies: Vec<(String, OverwritePolicy)>,
+    // Module configurations, without their `expiremember.` prefix, and values.
+    settings: Vec<(String, String)>,
+}
+
+lazy_static! {
+    // Only replaced holding the GIL, so a fork never sees it locked.
+    static ref RULES: RwLock<Rules> = RwLock::new(Rules::default());
+    // Path and modification time of the file last loaded or tried, for the watcher.
+    static ref SEEN: Mutex<(String, Option<SystemTime>)> = Mutex::new((String::new(), None));
+}
+
+fn parse_line(line: &str, rules: &mut Rules) -> Result<(), String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["exclude", pattern] => rules.excludes.push(pattern.to_string()),
+        ["policy", pattern, policy @ ..] => {
+            let policy = match policy {
+                ["keep"] => OverwritePolicy::Keep,
+                ["clear"] => OverwritePolicy::Clear,
+                ["reset", ttl, unit @ ..] => {
+                    let ttl = ttl.parse::<u64>().ok().filter(|ttl| *ttl > 0).ok_or_else(|| format!("invalid ttl '{}'", ttl))?;
+                    let ttl = match unit {
+                        [] | ["s"] => Duration::from_secs(ttl),
+                        ["ms"] => Duration::from_millis(ttl),
+                        _ => return Err("the unit must be s or ms".to_string()),
+                    };
+                    match ttl <= MAX_AHEAD {
+                        true => OverwritePolicy::Reset(ttl),
+                        false => return Err("the ttl is too long".to_string()),
+                    }
+                }
+                _ => return Err("the policy must be keep, clear or reset <ttl> [s | ms]".to_string()),
+            };
+            rules.policies.push((pattern.to_string(), policy));
+        }
+        ["set", "config-file", ..] => return Err("config-file cannot be set from itself".to_string()),
+        ["set", name, _, ..] => {
+            let value = line.trim_start()[3..].trim_start()[name.len()..].trim();
+            rules.settings.push((name.to_string(), value.to_string()));
+        }
+        [directive, ..] => return Err(format!("unknown or incomplete directive '{}'", directive)),
+        [] => {}
+    }
+    Ok(())
+}
+
+fn parse(path: &str) -> Result<Rules, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path, err))?;
+    let mut rules = Rules::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(line, _)| line);
+        parse_line(line, &mut rules).map_err(|err| format!("{} line {}: {}", path, number + 1, err))?;
+    }
+    Ok(rules)
+}
+
+/// A path to a file that parses, or empty.
+pub fn check_config_file(path: &RedisString) -> Result<(), String> {
+    let path = path.try_as_str().map_err(|_| "config-file must be a path".to_string())?;
+    match path.is_empty() {
+        true => Ok(()),
+        false => parse(path).map(|_| ()),
+    }
+}
+
+/// Loads the rules of `expiremember.config-file`, none when it is empty, applying its settings
+/// first. Must hold the GIL.
+pub fn reload(ctx: &Context) -> Result<(), String> {
+    let path = CONFIG_FILE.lock().unwrap().clone();
+    let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+    *SEEN.lock().unwrap() = (path.clone(), modified);
+
+    let rules = match path.is_empty() {
+        true => Rules::default(),
+        false => parse(&path)?,
+    };
+    if !rules.settings.is_empty() {
+        let names: Vec<String> = rules.settings.iter().map(|(name, _)| format!("expiremember.{}", name)).collect();
+        let mut args = vec!["SET"];
+        for ((_, value), name) in rules.settings.iter().zip(&names) {
+            args.extend([name.as_str(), value.as_str()]);
+        }
+        // Set at once, so one rejected value leaves all of them as they were.
+        ctx.call("CONFIG", args.as_slice()).map_err(|err| format!("{}: {}", path, err))?;
+    }
+    *RULES.write().unwrap() = rules;
+    Ok(())
+}
+
+/// Checks the file every `WATCH_INTERVAL`, reloading it when its path or modification time
+/// changed. A file that cannot be loaded is logged once per change.
+pub fn watch() {
+    thread::spawn(|| {
+        let thread_ctx = ThreadSafeContext::new();
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let path = CONFIG_FILE.lock().unwrap().clone();
+            let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+            if *SEEN.lock().unwrap() == (path.clone(), modified) {
+                continue;
+            }
+            let ctx = thread_ctx.lock();
+            match reload(&ctx) {
+                Ok(()) if path.is_empty() => logging::log_notice("expiremember.config-file unset, rules cleared"),
+                Ok(()) => logging::log_notice(format!("Reloaded {}", path)),
+                Err(err) => logging::log_warning(format!("{}, keeping the rules loaded before", err)),
+            }
+        }
+    });
+}
+
+/// Fails when `key` matches an `exclude` rule.
+pub fn check_excluded(key: &str) -> Result<(), RedisError> {
+    let rules = RULES.read().unwrap();
+    match rules.excludes.iter().any(|pattern| glob_match(pattern.as_bytes(), key.as_bytes())) {
+        true => Err(RedisError::Str("ERR members of this key cannot expire, it is excluded by expiremember.config-file")),
+        false => Ok(()),
+    }
+}
+
+/// Policy of the first `policy` rule matching `key`.
+pub fn policy(key: &str) -> Option<OverwritePolicy> {
+    let rules = RULES.read().unwrap();
+    rules.policies.iter().find(|(pattern, _)| glob_match(pattern.as_bytes(), key.as_bytes())).map(|(_, policy)| *policy)
+}
+
+/// Number of exclusions, policies and settings loaded, for EXPIREMEMBER.RELOADCONF.
+pub fn counts() -> RedisValue {
+    let rules = RULES.read().unwrap();
+    RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("excludes"),
+        RedisValue::Integer(rules.excludes.len() as i64),
+        RedisValue::SimpleStringStatic("policies"),
+        RedisValue::Integer(rules.policies.len() as i64),
+        RedisValue::SimpleStringStatic("settings"),
+        RedisValue::Integer(rules.settings.len() as i64),
+    ])
+}
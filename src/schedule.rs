@@ -0,0 +1,214 @@
+//! The worker's schedule of expirations, a binary heap or a timer wheel (`expiremember.scheduler`)
+//! per priority class, due members of a higher class coming out first. With `expiremember.ttl-granularity`, either is replaced by buckets of members whose deadlines
+//! round up to the same multiple of the granularity. Either way, entries for deadlines that were
+//! changed or removed stay in the schedule until they come up, and are then skipped.
+
+use redis_module::enum_configuration;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::time::Duration;
+
+use crate::deadline::Deadline;
+use crate::wheel::TimerWheel;
+use crate::ExpiringMember;
+
+enum_configuration! {
+    /// How the worker keeps the scheduled expirations in order.
+    #[allow(non_camel_case_types)]
+    #[derive(Copy, PartialEq, Eq)]
+    pub enum Scheduler {
+        // A binary heap, O(log n) per schedule and expiration.
+        heap = 1,
+        // A hierarchical timer wheel with 1ms ticks, O(1) per schedule, for millions of members.
+        wheel = 2,
+    }
+}
+
+/// Priority class of a schedule, `PRIORITY high|normal|low`. When the worker cannot expire every
+/// due member at once, higher classes go first.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn parse(name: &str) -> Option<Priority> {
+        Priority::ALL.into_iter().find(|priority| priority.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+
+    /// As persisted.
+    pub fn from_u64(value: u64) -> Priority {
+        Priority::ALL.get(value as usize).copied().unwrap_or_default()
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self as u64
+    }
+}
+
+pub struct Schedule {
+    // One for each priority class, in order.
+    timelines: Vec<Timeline>,
+}
+
+impl Schedule {
+    /// A schedule of `scheduler`, or of buckets `granularity` ms wide unless 0.
+    pub fn new(scheduler: Scheduler, granularity: u64) -> Self {
+        Schedule { timelines: Priority::ALL.iter().map(|_| Timeline::new(scheduler, granularity)).collect() }
+    }
+
+    pub fn push(&mut self, member: ExpiringMember) {
+        self.timelines[member.priority as usize].push(member);
+    }
+
+    /// Removes a member due at `now`, if any, from the highest class with one.
+    pub fn pop_due(&mut self, now: Deadline) -> Option<ExpiringMember> {
+        self.timelines.iter_mut().find_map(|timeline| timeline.pop_due(now))
+    }
+
+    pub fn next_deadline(&self) -> Option<Deadline> {
+        self.timelines.iter().filter_map(Timeline::next_deadline).min()
+    }
+
+    /// How many entries are due at `now`, counting up to `limit`.
+    pub fn overdue(&self, now: Deadline, limit: usize) -> usize {
+        self.timelines.iter().map(|timeline| timeline.overdue(now, limit)).sum::<usize>().min(limit)
+    }
+
+    pub fn len(&self) -> usize {
+        self.timelines.iter().map(Timeline::len).sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ExpiringMember> {
+        self.timelines.iter().flat_map(Timeline::iter)
+    }
+}
+
+/// The members of one priority class in order of their deadlines.
+enum Timeline {
+    Heap(BinaryHeap<Reverse<ExpiringMember>>),
+    // Ticks count ms from `origin`.
+    Wheel { wheel: TimerWheel<ExpiringMember>, origin: Deadline },
+    // Buckets count `granularity` ms from `origin`, `len` members in all.
+    Buckets { buckets: BTreeMap<u64, Vec<ExpiringMember>>, origin: Deadline, granularity: u64, len: usize },
+}
+
+impl Timeline {
+    /// A timeline of `scheduler`, or of buckets `granularity` ms wide unless 0.
+    fn new(scheduler: Scheduler, granularity: u64) -> Self {
+        if granularity > 0 {
+            return Timeline::Buckets { buckets: BTreeMap::new(), origin: Deadline::now(), granularity, len: 0 };
+        }
+        match scheduler {
+            Scheduler::heap => Timeline::Heap(BinaryHeap::new()),
+            Scheduler::wheel => Timeline::Wheel { wheel: TimerWheel::new(), origin: Deadline::now() },
+        }
+    }
+
+    fn push(&mut self, member: ExpiringMember) {
+        match self {
+            Timeline::Heap(heap) => heap.push(Reverse(member)),
+            Timeline::Wheel { wheel, origin } => {
+                // Rounded up, so members never expire before their deadline.
+                let tick = member.expire_at.since(*origin).as_nanos().div_ceil(1_000_000) as u64;
+                wheel.insert(tick, member);
+            }
+            Timeline::Buckets { buckets, origin, granularity, len } => {
+                // Rounded up as well.
+                let bucket = member.expire_at.since(*origin).as_nanos().div_ceil(*granularity as u128 * 1_000_000) as u64;
+                buckets.entry(bucket).or_default().push(member);
+                *len += 1;
+            }
+        }
+    }
+
+    /// Removes a member due at `now`, if any, the earliest one first for the heap.
+    fn pop_due(&mut self, now: Deadline) -> Option<ExpiringMember> {
+        match self {
+            Timeline::Heap(heap) => match heap.peek() {
+                Some(Reverse(member)) if member.expire_at <= now => heap.pop().map(|Reverse(member)| member),
+                _ => None,
+            },
+            Timeline::Wheel { wheel, origin } => wheel.pop_due(now.since(*origin).as_millis() as u64).map(|(_, member)| member),
+            Timeline::Buckets { buckets, origin, granularity, len } => {
+                let mut bucket = buckets.first_entry()?;
+                if *origin + Duration::from_millis(bucket.key() * *granularity) > now {
+                    return None;
+                }
+                let member = bucket.get_mut().pop();
+                if bucket.get().is_empty() {
+                    bucket.remove();
+                }
+                *len -= 1;
+                member
+            }
+        }
+    }
+
+    /// When the next member is due, or a little earlier for the wheel, or the end of its bucket.
+    fn next_deadline(&self) -> Option<Deadline> {
+        match self {
+            Timeline::Heap(heap) => heap.peek().map(|Reverse(member)| member.expire_at),
+            Timeline::Wheel { wheel, origin } => wheel.next_tick().map(|tick| *origin + Duration::from_millis(tick)),
+            Timeline::Buckets { buckets, origin, granularity, .. } => {
+                buckets.keys().next().map(|bucket| *origin + Duration::from_millis(bucket * granularity))
+            }
+        }
+    }
+
+    /// How many entries are due at `now`, counting up to `limit`. For the wheel, those found due
+    /// when it last advanced.
+    fn overdue(&self, now: Deadline, limit: usize) -> usize {
+        match self {
+            Timeline::Heap(heap) => heap.iter().filter(|Reverse(member)| member.expire_at <= now).take(limit).count(),
+            Timeline::Wheel { wheel, .. } => wheel.due_len().min(limit),
+            Timeline::Buckets { buckets, origin, granularity, .. } => {
+                let mut count = 0;
+                for (bucket, members) in buckets {
+                    if count >= limit || *origin + Duration::from_millis(bucket * *granularity) > now {
+                        break;
+                    }
+                    count += members.len();
+                }
+                count.min(limit)
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Timeline::Heap(heap) => heap.len(),
+            Timeline::Wheel { wheel, .. } => wheel.len(),
+            Timeline::Buckets { len, .. } => *len,
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &ExpiringMember> + '_> {
+        match self {
+            Timeline::Heap(heap) => Box::new(heap.iter().map(|Reverse(member)| member)),
+            Timeline::Wheel { wheel, .. } => Box::new(wheel.iter()),
+            Timeline::Buckets { buckets, .. } => Box::new(buckets.values().flatten()),
+        }
+    }
+}
+
+impl Extend<ExpiringMember> for Schedule {
+    fn extend<I: IntoIterator<Item = ExpiringMember>>(&mut self, members: I) {
+        for member in members {
+            self.push(member);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! What differs between the servers the module loads into, Redis and Valkey, which forked from
+//! Redis 7.2 and keeps its module API. The server is told apart at load from `INFO server`, and
+//! optional API functions are looked up under the names it uses for them.
+//!
+//! Valkey loads the module through the entry points of Redis, and with the `valkey` feature
+//! through its own as well.
+
+use lazy_static::lazy_static;
+use redis_module::{raw, Context, RedisValue};
+use std::ffi::CString;
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static VALKEY: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    // Version of the server, Valkey's own rather than the Redis version it reports for clients.
+    static ref VERSION: Mutex<String> = Mutex::new(String::new());
+}
+
+/// Tells the server apart, logging it. Must hold the GIL.
+pub fn detect(ctx: &Context) {
+    let info = match ctx.call("INFO", &["server"]) {
+        Ok(RedisValue::SimpleString(info) | RedisValue::BulkString(info)) => info,
+        _ => String::new(),
+    };
+    let field = |name: &str| info.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix(':')).map(str::trim);
+    let valkey = field("server_name") == Some("valkey") || field("valkey_version").is_some();
+    let version = if valkey { field("valkey_version") } else { None }.or_else(|| field("redis_version")).unwrap_or("unknown");
+
+    VALKEY.store(valkey, Ordering::Relaxed);
+    *VERSION.lock().unwrap() = version.to_string();
+    ctx.log_notice(&format!("Loaded into {} {}", name(), version));
+}
+
+pub fn is_valkey() -> bool {
+    VALKEY.load(Ordering::Relaxed)
+}
+
+/// `redis` or `valkey`.
+pub fn name() -> &'static str {
+    if is_valkey() { "valkey" } else { "redis" }
+}
+
+#[cfg(feature = "debug")]
+pub fn version() -> String {
+    VERSION.lock().unwrap().clone()
+}
+
+/// The optional API function `name`, e.g. `AddACLCategory`, None if the server lacks it. Looked
+/// up by name since the bundled API header predates some of them.
+///
+/// # Safety
+///
+/// `F` must be the `extern "C"` function pointer type of the function.
+pub unsafe fn api<F: Copy>(name: &str) -> Option<F> {
+    let prefixes: &[&str] = if is_valkey() { &["ValkeyModule_", "RedisModule_"] } else { &["RedisModule_"] };
+    prefixes.iter().find_map(|prefix| {
+        let name = CString::new(format!("{}{}", prefix, name)).unwrap();
+        let mut function: Option<F> = None;
+        let status = raw::RedisModule_GetApi.unwrap()(name.as_ptr(), (&mut function as *mut Option<F>).cast::<c_void>());
+        if status == raw::REDISMODULE_OK as c_int { function } else { None }
+    })
+}
+
+/// Valkey's entry point, looked up before that of Redis.
+#[cfg(all(feature = "valkey", not(test)))]
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn ValkeyModule_OnLoad(ctx: *mut raw::RedisModuleCtx, argv: *mut *mut raw::RedisModuleString, argc: c_int) -> c_int {
+    crate::RedisModule_OnLoad(ctx, argv, argc)
+}
+
+#[cfg(all(feature = "valkey", not(test)))]
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn ValkeyModule_OnUnload(ctx: *mut raw::RedisModuleCtx) -> c_int {
+    crate::RedisModule_OnUnload(ctx)
+}
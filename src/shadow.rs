@@ -0,0 +1,166 @@
+//! Mirrors of the in-memory index kept in the keyspace by the `datatype` and `zset` backends.
+
+use redis_module::{enum_configuration, Context, KeysCursor, KeyType, RedisValue};
+use std::time::Duration;
+
+use crate::deadline::Deadline;
+use crate::{cluster, config_get, datatype, persistence, rebuild_heap, with_db, ExpiringMember, EXPIRATION_TIMES};
+
+enum_configuration! {
+    /// Where expirations are kept besides the in-memory index.
+    #[allow(non_camel_case_types)]
+    #[derive(Copy, PartialEq, Eq)]
+    pub enum Backend {
+        // Only in memory, persisted through the RDB aux field.
+        memory = 1,
+        // Also in a `memberttl` shadow key per tracked key, see datatype.rs.
+        datatype = 2,
+        // Also in a shadow zset per tracked key, scored by unix ms deadline.
+        zset = 3,
+    }
+}
+
+const SHADOW_PREFIX: &str = "expiremember:";
+
+/// Shadow keys are named `expiremember:{<key>}`, or `expiremember:{<tag>}:<key>` for keys with a
+/// hash tag, which keeps them in the tracked key's slot so they move along when resharding.
+pub fn shadow_key_name(key: &str) -> String {
+    match cluster::hash_tag(key) {
+        Some(tag) => format!("{}{{{}}}:{}", SHADOW_PREFIX, tag, key),
+        None => format!("{}{{{}}}", SHADOW_PREFIX, key),
+    }
+}
+
+pub fn tracked_key_name(shadow: &str) -> Option<&str> {
+    let name = shadow.strip_prefix(SHADOW_PREFIX)?.strip_prefix('{')?;
+    let tagged = name.split_once("}:").and_then(|(tag, key)| (cluster::hash_tag(key) == Some(tag)).then_some(key));
+    tagged.or_else(|| name.strip_suffix('}').filter(|key| cluster::hash_tag(key).is_none()))
+}
+
+/// Records the expiration in the shadow key of `member.key`, or for the memory backend makes sure
+/// an AOF rewrite without the preamble gets to it.
+pub fn store(ctx: &Context, backend: Backend, member: &ExpiringMember) {
+    match backend {
+        Backend::memory => persistence::store_carrier(ctx),
+        Backend::datatype => datatype::store(ctx, member),
+        Backend::zset => {
+            let deadline = member.expire_at.unix_ms().to_string();
+            let shadow = shadow_key_name(&member.key);
+            // Fails when the shadow name is taken by a key of another type, which is left alone.
+            let _ = ctx.call("ZADD", &[shadow.as_str(), deadline.as_str(), &*member.member]);
+        }
+    }
+}
+
+/// Drops `member` from the shadow key of `key`, which is deleted once empty.
+pub fn forget(ctx: &Context, backend: Backend, key: &str, member: &str) {
+    match backend {
+        Backend::memory => {}
+        Backend::datatype => datatype::forget(ctx, key, member),
+        Backend::zset => {
+            let shadow = shadow_key_name(key);
+            let _ = ctx.call("ZREM", &[shadow.as_str(), member]);
+        }
+    }
+}
+
+/// Deletes the shadow key of `key`.
+pub fn remove(ctx: &Context, backend: Backend, key: &str) {
+    if backend == Backend::memory {
+        return;
+    }
+    let shadow = shadow_key_name(key);
+    after_notification(ctx, move |ctx| {
+        let _ = ctx.call("DEL", &[shadow.as_str()]);
+    });
+}
+
+/// Moves the shadow key of `from` to that of `to`, replacing it.
+pub fn rename(ctx: &Context, backend: Backend, from: &str, to: &str) {
+    if backend == Backend::memory {
+        return;
+    }
+    let (from, to) = (shadow_key_name(from), shadow_key_name(to));
+    after_notification(ctx, move |ctx| {
+        let _ = ctx.call("DEL", &[to.as_str()]);
+        // Fails when `from` had no shadow key, leaving `to` without one as well.
+        let _ = ctx.call("RENAME", &[from.as_str(), to.as_str()]);
+    });
+}
+
+/// Moves the shadow key of `key` from database `from_db` to `to_db`, replacing any left there.
+/// `ctx` has `to_db` selected.
+pub fn move_key(ctx: &Context, backend: Backend, key: &str, from_db: i32, to_db: i32) {
+    if backend == Backend::memory {
+        return;
+    }
+    let shadow = shadow_key_name(key);
+    after_notification(ctx, move |ctx| {
+        let _ = ctx.call("DEL", &[shadow.as_str()]);
+        let to_db = to_db.to_string();
+        // Fails when `key` had no shadow key.
+        let _ = with_db(ctx, from_db, || ctx.call("MOVE", &[shadow.as_str(), to_db.as_str()]));
+    });
+}
+
+/// Writes are not safe in keyspace notification handlers, so `job` waits for a post
+/// notification job where supported (Redis 7.2+) and runs right away otherwise.
+pub fn after_notification<F: FnOnce(&Context) + Clone + 'static>(ctx: &Context, job: F) {
+    if ctx.add_post_notification_job(job.clone()).is_err() {
+        job(ctx);
+    }
+}
+
+/// Scans every database for shadow keys and tracks their expirations, so a restart or a
+/// `MODULE LOAD` resumes expiring where the shadow storage left off.
+pub fn rebuild(ctx: &Context, backend: Backend) {
+    if backend == Backend::memory {
+        return;
+    }
+
+    let databases = config_get(ctx, "databases").and_then(|databases| databases.parse().ok()).unwrap_or(16);
+    let mut found = Vec::new();
+    for db in 0..databases {
+        with_db(ctx, db, || {
+            let cursor = KeysCursor::new();
+            let collect = |ctx: &Context, key_name: redis_module::RedisString, _key: Option<&redis_module::key::RedisKey>| {
+                let name = key_name.to_string();
+                let Some(tracked) = tracked_key_name(&name) else {
+                    return;
+                };
+                let key = ctx.open_key(&key_name);
+                let members = match key.key_type() {
+                    KeyType::Module => datatype::members(&key),
+                    KeyType::ZSet => zset_members(ctx, &name),
+                    _ => None,
+                };
+                for (member, deadline) in members.unwrap_or_default() {
+                    found.push(ExpiringMember::new(db, tracked.to_string(), member, Deadline::from_unix(Duration::from_millis(deadline))));
+                }
+            };
+            while cursor.scan(ctx, &collect) {}
+        });
+    }
+    if found.is_empty() {
+        return;
+    }
+
+    ctx.log_notice(&format!("Rebuilt {} member expirations from shadow keys", found.len()));
+    for member in found {
+        EXPIRATION_TIMES.insert(member);
+    }
+
+    rebuild_heap();
+}
+
+fn zset_members(ctx: &Context, shadow: &str) -> Option<Vec<(String, u64)>> {
+    let Ok(RedisValue::Array(reply)) = ctx.call("ZRANGE", &[shadow, "0", "-1", "WITHSCORES"]) else {
+        return None;
+    };
+    Some(reply.chunks(2).filter_map(|pair| match pair {
+        [RedisValue::SimpleString(member), RedisValue::SimpleString(score)] => {
+            Some((member.clone(), score.parse::<f64>().ok()? as u64))
+        }
+        _ => None,
+    }).collect())
+}
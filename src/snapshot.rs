@@ -0,0 +1,273 @@
+//! Background jobs behind EXPIREMEMBER.EXPORT, EXPIREMEMBER.IMPORT and EXPIREMEMBER.MIGRATE.
+
+use redis_module::ThreadSafeContext;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::deadline::Deadline;
+use crate::schedule::Priority;
+use crate::{commands, delete_member, dump, schedule_member, with_db, ExpiringMember, EXPIRATION_TIMES};
+
+/// Expirations applied per GIL acquisition while importing, so clients are served in between.
+const IMPORT_BATCH: usize = 1000;
+
+static JOB_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Whether an export, import or migration is running.
+pub fn is_running() -> bool {
+    JOB_RUNNING.load(Ordering::SeqCst)
+}
+
+/// Claims the single job slot, false if an export, import or migration is already running.
+pub fn try_start_job() -> bool {
+    !JOB_RUNNING.swap(true, Ordering::SeqCst)
+}
+
+/// Writes every tracked expiration to `path`.
+pub fn export(path: String) {
+    thread::spawn(move || {
+        let entries: Vec<dump::SnapshotEntry> = EXPIRATION_TIMES.collect(|tracked| Some((
+            tracked.db,
+            tracked.key.to_string(),
+            tracked.member.to_string(),
+            tracked.expire_at.unix_ms(),
+            tracked.priority,
+        )));
+        let result = fs::write(&path, dump::encode_snapshot(&entries));
+
+        let thread_ctx = ThreadSafeContext::new();
+        let ctx = thread_ctx.lock();
+        match result {
+            Ok(()) => ctx.log_notice(&format!("Exported {} member expirations to {}", entries.len(), path)),
+            Err(err) => ctx.log_warning(&format!("Exporting member expirations to {} failed: {}", path, err)),
+        }
+        drop(ctx);
+        JOB_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Schedules every expiration found in `path`, in batches.
+pub fn import(path: String) {
+    thread::spawn(move || {
+        let thread_ctx = ThreadSafeContext::new();
+        let entries = match fs::read(&path) {
+            Ok(snapshot) => dump::decode_snapshot(&snapshot).ok_or_else(|| "corrupt or unsupported file".to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(err) => {
+                thread_ctx.lock().log_warning(&format!("Importing member expirations from {} failed: {}", path, err));
+                JOB_RUNNING.store(false, Ordering::SeqCst);
+                return;
+            }
+        };
+
+        for batch in entries.chunks(IMPORT_BATCH) {
+            let ctx = thread_ctx.lock();
+            let now = Deadline::now();
+            for (db, key, member, deadline, priority) in batch {
+                let expire_at = Deadline::from_unix(Duration::from_millis(*deadline));
+                // Members of keys that no longer have a supported type are skipped.
+                let _ = with_db(&ctx, *db, || if expire_at <= now {
+                    delete_member(&ctx, key.clone(), member.clone())
+                } else {
+                    let expiring_member = ExpiringMember::new(*db, key.clone(), member.clone(), expire_at);
+                    schedule_member(&ctx, ExpiringMember { priority: *priority, ..expiring_member })
+                });
+            }
+        }
+
+        thread_ctx.lock().log_notice(&format!("Imported {} member expirations from {}", entries.len(), path));
+        JOB_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Target and options of EXPIREMEMBER.MIGRATE.
+pub struct Migration {
+    pub host: String,
+    pub port: u16,
+    pub pattern: Option<String>,
+    pub batch: usize,
+    pub auth: Option<String>,
+}
+
+/// Replays the tracked expirations of keys matching the pattern on another instance as
+/// `EXPIREMEMBERAT key member <unix-ms> ms [PRIORITY priority]`, pipelined in batches, each database's after a
+/// `SELECT` of it.
+pub fn migrate(migration: Migration) {
+    thread::spawn(move || {
+        let thread_ctx = ThreadSafeContext::new();
+        let target = format!("{}:{}", migration.host, migration.port);
+        let mut entries: Vec<(i32, String, String, u64, Priority)> = EXPIRATION_TIMES.collect(|tracked| {
+            migration.pattern.as_deref().is_none_or(|pattern| glob_match(pattern.as_bytes(), tracked.key.as_bytes())).then(|| (
+                tracked.db,
+                tracked.key.to_string(),
+                tracked.member.to_string(),
+                tracked.expire_at.unix_ms(),
+                tracked.priority,
+            ))
+        });
+        entries.sort_by_key(|(db, ..)| *db);
+
+        let result = (|| -> io::Result<usize> {
+            let stream = TcpStream::connect(&target)?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut writer = stream;
+            if let Some(password) = &migration.auth {
+                writer.write_all(&encode_command(&["AUTH", password]))?;
+                read_reply(&mut reader)?;
+            }
+
+            let expirememberat = commands::name("EXPIREMEMBERAT");
+            let mut failed = 0;
+            let mut selected = None;
+            for (done, batch) in entries.chunks(migration.batch).enumerate() {
+                let mut pipeline = Vec::new();
+                // Whether each command sent is a SELECT, in pipeline order.
+                let mut sent_selects = Vec::with_capacity(batch.len() + 1);
+                for (db, key, member, deadline, priority) in batch {
+                    if selected != Some(*db) {
+                        pipeline.extend(encode_command(&["SELECT", &db.to_string()]));
+                        selected = Some(*db);
+                        sent_selects.push(true);
+                    }
+                    let deadline = deadline.to_string();
+                    let mut command = vec![expirememberat.as_str(), key, member, &deadline, "ms"];
+                    if *priority != Priority::Normal {
+                        command.extend(["PRIORITY", priority.name()]);
+                    }
+                    pipeline.extend(encode_command(&command));
+                    sent_selects.push(false);
+                }
+                writer.write_all(&pipeline)?;
+                for is_select in sent_selects {
+                    match read_reply(&mut reader) {
+                        // Members would land in the wrong database past a failed SELECT.
+                        Err(err) if is_select => return Err(err),
+                        Err(err) if err.kind() == io::ErrorKind::Other => failed += 1,
+                        Err(err) => return Err(err),
+                        Ok(()) => {}
+                    }
+                }
+                let sent = (done * migration.batch + batch.len()).min(entries.len());
+                thread_ctx.lock().log_notice(&format!("Migrated {}/{} member expirations to {}", sent, entries.len(), target));
+            }
+            Ok(failed)
+        })();
+
+        let ctx = thread_ctx.lock();
+        match result {
+            Ok(0) => ctx.log_notice(&format!("Migrated {} member expirations to {}", entries.len(), target)),
+            Ok(failed) => ctx.log_warning(&format!("Migrating member expirations to {}: {} of {} were rejected", target, failed, entries.len())),
+            Err(err) => ctx.log_warning(&format!("Migrating member expirations to {} failed: {}", target, err)),
+        }
+        drop(ctx);
+        JOB_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+fn encode_command(args: &[&str]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+/// Reads one reply, an error reply becomes an `Err` of kind `Other`.
+fn read_reply(reader: &mut impl BufRead) -> io::Result<()> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    match line.as_bytes().first() {
+        Some(b'-') => Err(io::Error::other(line.trim_end().to_string())),
+        Some(b'$') => {
+            if let Ok(len) = line[1..].trim_end().parse::<usize>() {
+                reader.read_exact(&mut vec![0; len + 2])?;
+            }
+            Ok(())
+        }
+        Some(b'*') => {
+            for _ in 0..line[1..].trim_end().parse::<i64>().unwrap_or(0) {
+                read_reply(reader)?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Glob-style matching as in KEYS and SCAN: `*`, `?`, `[abc]`, `[^a-z]` and `\` escapes.
+///
+/// Like the server's `stringmatchlen`, a mismatch only resumes from the last `*`, one character
+/// further into the string, so no pattern takes more than quadratic time.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // The pattern past the last `*` and where in the string its match would resume.
+    let mut star = None;
+    while s < string.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, s));
+            continue;
+        }
+        if let Some(len) = match_one(&pattern[p..], string[s]) {
+            p += len;
+            s += 1;
+            continue;
+        }
+        let Some((star_p, star_s)) = star else { return false };
+        p = star_p;
+        s = star_s + 1;
+        star = Some((star_p, s));
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// Matches `c` against the element at the start of `pattern`, other than `*`, returning the
+/// length of that element.
+fn match_one(pattern: &[u8], c: u8) -> Option<usize> {
+    match pattern {
+        [] => None,
+        [b'?', ..] => Some(1),
+        [b'[', rest @ ..] => {
+            let (negate, mut class) = match rest.split_first() {
+                Some((b'^', class)) => (true, class),
+                _ => (false, rest),
+            };
+            let mut matched = false;
+            loop {
+                match class {
+                    [] => return None,
+                    [b']', after @ ..] => {
+                        class = after;
+                        break;
+                    }
+                    [b'\\', escaped, after @ ..] => {
+                        matched |= *escaped == c;
+                        class = after;
+                    }
+                    [start, b'-', end, after @ ..] if *end != b']' => {
+                        matched |= (*start.min(end)..=*start.max(end)).contains(&c);
+                        class = after;
+                    }
+                    [other, after @ ..] => {
+                        matched |= *other == c;
+                        class = after;
+                    }
+                }
+            }
+            (matched != negate).then_some(pattern.len() - class.len())
+        }
+        [b'\\', escaped, ..] => (*escaped == c).then_some(2),
+        [other, ..] => (*other == c).then_some(1),
+    }
+}
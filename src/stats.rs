@@ -0,0 +1,179 @@
+//! EXPIREMEMBER.STATS: counters of schedules and deletions since the module was loaded, and
+//! over the last minute.
+//!
+//! EXPIREMEMBER.STATS KEYS: members expired per key, for the `expiremember.stats-keys` keys that
+//! most recently had one, to find the keys driving the expiry load.
+
+use lazy_static::lazy_static;
+use redis_module::{Context, RedisResult, RedisValue};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::STATS_KEYS;
+
+#[derive(Clone, Copy)]
+pub enum Counter {
+    // Expirations of members not tracked yet.
+    Scheduled,
+    // Schedules replacing a tracked expiration.
+    Overridden,
+    // Expirations removed with -1, or along with their member or key, before their deadline.
+    Cancelled,
+    Expired,
+    // Members whose removal the server refused.
+    DeletionsFailed,
+    // Schedules that found the queue at `queue-drain-threshold`.
+    QueueOverflows,
+    // Expiry events or hooks the server refused.
+    NotificationsFailed,
+    // Expirations copied by COPY or reset by EXPIREMEMBER.POLICY that could not be scheduled.
+    SchedulesDropped,
+    // Panics caught in the worker or a deletion thread.
+    WorkerPanics,
+}
+
+const NAMES: [&str; 9] = [
+    "schedules_set",
+    "schedules_overridden",
+    "schedules_cancelled",
+    "members_expired",
+    "deletions_failed",
+    "queue_overflows",
+    "notifications_failed",
+    "schedules_dropped",
+    "worker_panics",
+];
+
+const DESCRIPTIONS: [&str; NAMES.len()] = [
+    "Expirations scheduled for members not tracked yet.",
+    "Schedules replacing a tracked expiration.",
+    "Expirations removed before their deadline.",
+    "Members expired by the worker.",
+    "Members whose removal the server refused.",
+    "Schedules that found queue-drain-threshold schedules waiting.",
+    "Expiry events, functions or scripts that failed.",
+    "Copied or reset expirations that could not be scheduled.",
+    "Panics caught in the worker or a deletion thread, which then started over.",
+];
+
+/// Seconds counted as recent, one slot each.
+const WINDOW_SECS: u64 = 60;
+
+/// Failures of a kind are logged at most this often.
+const WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref STARTED: Instant = Instant::now();
+    static ref TOTALS: [AtomicU64; NAMES.len()] = Default::default();
+    // The counts of each second of the window, tagged with the second since `STARTED` they are
+    // for, so slots of an earlier minute are reset rather than added to.
+    static ref RECENT: Mutex<Vec<(u64, [u64; NAMES.len()])>> = Mutex::new(vec![(0, [0; NAMES.len()]); WINDOW_SECS as usize]);
+    // When failures of each kind were last logged, and how many there were since.
+    static ref WARNINGS: Mutex<[(Option<Instant>, u64); NAMES.len()]> = Mutex::new([(None, 0); NAMES.len()]);
+    static ref KEYS: Mutex<KeyCounts> = Mutex::new(KeyCounts { tick: 0, keys: HashMap::new() });
+}
+
+/// Members expired per key, with the tick each key last had one at, unique to it, for the keys least recently
+/// having one to be forgotten first.
+struct KeyCounts {
+    tick: u64,
+    keys: HashMap<(i32, Arc<str>), (u64, u64)>,
+}
+
+pub fn add(counter: Counter, count: u64) {
+    if count == 0 {
+        return;
+    }
+    TOTALS[counter as usize].fetch_add(count, Ordering::Relaxed);
+    let second = STARTED.elapsed().as_secs();
+    let mut recent = RECENT.lock().unwrap();
+    let slot = &mut recent[(second % WINDOW_SECS) as usize];
+    if slot.0 != second {
+        *slot = (second, [0; NAMES.len()]);
+    }
+    slot.1[counter as usize] += count;
+}
+
+/// Counts `count` failures and logs the first as a warning, then at most one every
+/// `WARNING_INTERVAL` along with how many were not logged, for a failing path to show in the
+/// server log without flooding it.
+pub fn failed(ctx: &Context, counter: Counter, count: u64, message: impl FnOnce() -> String) {
+    add(counter, count);
+    let mut warnings = WARNINGS.lock().unwrap();
+    let (logged_at, unlogged) = &mut warnings[counter as usize];
+    if logged_at.is_some_and(|at| at.elapsed() < WARNING_INTERVAL) {
+        *unlogged += count;
+        return;
+    }
+    let message = match *unlogged {
+        0 => message(),
+        _ => format!("{} ({} more {} since the last warning)", message(), unlogged, NAMES[counter as usize]),
+    };
+    (*logged_at, *unlogged) = (Some(Instant::now()), 0);
+    drop(warnings);
+    ctx.log_warning(&message);
+}
+
+/// Each counter's name, description and count since the module was loaded.
+pub fn totals() -> Vec<(&'static str, &'static str, u64)> {
+    (0..NAMES.len()).map(|i| (NAMES[i], DESCRIPTIONS[i], TOTALS[i].load(Ordering::Relaxed))).collect()
+}
+
+/// A flat list of counter names and values, each counter followed by its count over the last
+/// minute as `<name>_last_minute`.
+pub fn report() -> RedisResult {
+    let second = STARTED.elapsed().as_secs();
+    let mut last_minute = [0; NAMES.len()];
+    for (slot_second, counts) in RECENT.lock().unwrap().iter() {
+        if second - slot_second < WINDOW_SECS {
+            last_minute.iter_mut().zip(counts).for_each(|(sum, count)| *sum += count);
+        }
+    }
+
+    let mut reply = Vec::with_capacity(NAMES.len() * 4);
+    for (i, name) in NAMES.iter().enumerate() {
+        reply.push(RedisValue::SimpleStringStatic(name));
+        reply.push(RedisValue::Integer(TOTALS[i].load(Ordering::Relaxed) as i64));
+        reply.push(RedisValue::SimpleString(format!("{}_last_minute", name)));
+        reply.push(RedisValue::Integer(last_minute[i] as i64));
+    }
+    Ok(RedisValue::Array(reply))
+}
+
+/// Counts the members expired per key, given as (db, key, members).
+pub fn add_keys<'a>(expired: impl Iterator<Item = (i32, &'a Arc<str>, usize)>) {
+    let capacity = STATS_KEYS.load(Ordering::Relaxed).max(0) as usize;
+    let mut counts = KEYS.lock().unwrap();
+    if capacity == 0 {
+        counts.keys.clear();
+        return;
+    }
+    for (db, key, members) in expired {
+        counts.tick += 1;
+        let tick = counts.tick;
+        let count = counts.keys.entry((db, key.clone())).or_insert((0, tick));
+        *count = (count.0 + members as u64, tick);
+    }
+
+    // A tenth of the capacity at a time, so the keys are not sorted for every batch.
+    if counts.keys.len() > capacity {
+        let mut ticks: Vec<u64> = counts.keys.values().map(|(_, tick)| *tick).collect();
+        let excess = (counts.keys.len() - capacity + capacity / 10).min(ticks.len() - 1);
+        let oldest_kept = *ticks.select_nth_unstable(excess).1;
+        counts.keys.retain(|_, (_, tick)| *tick >= oldest_kept);
+    }
+}
+
+/// The `count` keys with the most expired members, each as [key, db, members], most first.
+pub fn report_keys(count: usize) -> RedisResult {
+    let counts = KEYS.lock().unwrap();
+    let mut keys: Vec<(&(i32, Arc<str>), u64)> = counts.keys.iter().map(|(key, (members, _))| (key, *members)).collect();
+    keys.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    Ok(RedisValue::Array(keys.into_iter().take(count).map(|((db, key), members)| RedisValue::Array(vec![
+        RedisValue::BulkString(key.to_string()),
+        RedisValue::Integer(*db as i64),
+        RedisValue::Integer(members as i64),
+    ])).collect()))
+}
@@ -0,0 +1,82 @@
+//! Optional StatsD emitter: with `expiremember.statsd-address` set, the worker pushes its key
+//! metrics over UDP every `expiremember.statsd-interval` ms, for environments that receive
+//! metrics but cannot scrape the server.
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::deadline::Deadline;
+use crate::{info, Gil, STATSD_ADDRESS, STATSD_INTERVAL, STATSD_PREFIX};
+
+/// Where the worker sends its metrics to, resolved again whenever the address changes.
+pub struct Emitter {
+    address: String,
+    target: Option<(UdpSocket, SocketAddr)>,
+    next_at: Deadline,
+    // Members expired as of the last push, the counter being sent as a delta.
+    expired: u64,
+}
+
+impl Emitter {
+    pub fn new() -> Self {
+        Emitter { address: String::new(), target: None, next_at: Deadline::now(), expired: 0 }
+    }
+
+    /// Pushes the metrics once they are due. Returns when they are next due, None while
+    /// disabled.
+    pub fn tick(&mut self, gil: &Gil) -> Option<Deadline> {
+        let address = STATSD_ADDRESS.lock().unwrap().clone();
+        if address.is_empty() {
+            self.address.clear();
+            self.target = None;
+            return None;
+        }
+        if address != self.address {
+            self.target = match connect(&address) {
+                Ok(target) => Some(target),
+                Err(err) => {
+                    gil.with(|ctx| ctx.log_warning(&format!("Cannot send metrics to StatsD at {}: {}", address, err)));
+                    None
+                }
+            };
+            self.address = address;
+            self.next_at = Deadline::now();
+        }
+
+        let now = Deadline::now();
+        if self.next_at > now {
+            return Some(self.next_at);
+        }
+        self.next_at = now + Duration::from_millis(STATSD_INTERVAL.load(Ordering::Relaxed).max(1) as u64);
+        if let Some((socket, target)) = &self.target {
+            let snapshot = info::snapshot();
+            let prefix = STATSD_PREFIX.lock().unwrap().clone();
+            let prefix = if prefix.is_empty() { String::new() } else { format!("{}.", prefix) };
+            let lines = [
+                format!("{}tracked:{}|g", prefix, snapshot.tracked),
+                format!("{}scheduled:{}|g", prefix, snapshot.scheduled),
+                format!("{}queued:{}|g", prefix, snapshot.queued),
+                format!("{}overdue:{}|g", prefix, snapshot.overdue),
+                format!("{}expired_per_sec:{:.2}|g", prefix, snapshot.expired_per_sec),
+                format!("{}expired:{}|c", prefix, snapshot.expired.saturating_sub(self.expired)),
+                format!("{}cycle_duration:{:.3}|ms", prefix, snapshot.last_cycle_duration.as_secs_f64() * 1000.0),
+                format!("{}lag_p99:{:.3}|g", prefix, snapshot.lag.p99.as_secs_f64() * 1000.0),
+            ];
+            self.expired = snapshot.expired;
+            // Lost packets are lost metrics, nothing to retry.
+            let _ = socket.send_to(lines.join("\n").as_bytes(), target);
+        }
+        Some(self.next_at)
+    }
+}
+
+/// A socket of the address family of `address`, host:port, and the address it resolves to.
+fn connect(address: &str) -> std::io::Result<(UdpSocket, SocketAddr)> {
+    let target = address.to_socket_addrs()?.next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address found"))?;
+    let local: SocketAddr = if target.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_nonblocking(true)?;
+    Ok((socket, target))
+}
@@ -0,0 +1,71 @@
+//! Throttling of the worker's deletions, so an expiry storm is spread over time rather than
+//! competing with clients all at once: `expiremember.max-deletions-per-second` caps the deletion
+//! rate, `expiremember.max-lock-percent` the share of the time the worker holds the GIL. Members
+//! held back stay in the schedule and are picked up by later cycles.
+
+use lazy_static::lazy_static;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::deadline::Deadline;
+use crate::MAX_LOCK_PERCENT;
+
+lazy_static! {
+    // When the worker may take the GIL again under `max-lock-percent`.
+    static ref LOCK_FREE_AT: Mutex<Option<Deadline>> = Mutex::new(None);
+}
+
+/// Token bucket for `max-deletions-per-second`, holding at most a second's worth, so a wave of
+/// due members after a quiet period is not deleted at once either.
+pub struct RateLimiter {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        RateLimiter { tokens: 0.0, refilled: Instant::now() }
+    }
+
+    /// How many deletions are allowed right now at `rate` per second, any number when 0.
+    pub fn allowance(&mut self, rate: u64) -> usize {
+        if rate == 0 {
+            return usize::MAX;
+        }
+        let refilled = Instant::now();
+        let elapsed = refilled.duration_since(self.refilled);
+        self.refilled = refilled;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate as f64).min(rate as f64);
+        self.tokens as usize
+    }
+
+    pub fn take(&mut self, deletions: usize) {
+        self.tokens = (self.tokens - deletions as f64).max(0.0);
+    }
+
+    /// How long until a tenth of a second's worth of deletions is allowed at `rate`, so throttled
+    /// cycles take the GIL for a chunk of deletions rather than for each of them.
+    pub fn refill_time(&self, rate: u64) -> Duration {
+        let chunk = (rate as f64 / 10.0).max(1.0);
+        Duration::from_secs_f64((chunk - self.tokens).max(0.0) / rate.max(1) as f64)
+    }
+}
+
+/// Accounts for the worker, or one of the deletion threads, having held the GIL for `held`.
+/// Under `max-lock-percent`, they then stay off it long enough for that share to hold.
+pub fn charge_lock(held: Duration) {
+    let percent = MAX_LOCK_PERCENT.load(Ordering::Relaxed).clamp(1, 100) as u32;
+    if percent == 100 {
+        return;
+    }
+    let free_at = Deadline::now() + held * (100 - percent) / percent;
+    let mut lock_free_at = LOCK_FREE_AT.lock().unwrap();
+    *lock_free_at = Some(lock_free_at.map_or(free_at, |at| at.max(free_at)));
+}
+
+/// When the worker may take the GIL again, None if it may now.
+pub fn lock_free_at() -> Option<Deadline> {
+    let now = Deadline::now();
+    LOCK_FREE_AT.lock().unwrap().filter(|at| *at > now)
+}
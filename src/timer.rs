@@ -0,0 +1,95 @@
+//! Timer driver, chosen with the `driver timer` module argument: expiry cycles run on the main
+//! thread from module timers instead of in a background thread, each expiring at most
+//! `expire-batch` members so clients are served in between. Nodes with a single CPU are spared
+//! the context switches and the GIL handoffs.
+
+use lazy_static::lazy_static;
+use redis_module::{enum_configuration, raw, Context};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::deadline::Deadline;
+use crate::{watchdog, Gil, Worker};
+
+enum_configuration! {
+    /// What runs the expiry cycles.
+    #[allow(non_camel_case_types)]
+    #[derive(Copy, PartialEq, Eq)]
+    pub enum Driver {
+        // A background thread taking the GIL.
+        thread = 1,
+        // Module timers on the main thread.
+        timer = 2,
+    }
+}
+
+// Context the timers are created with, detached from any client.
+static TIMER_CTX: AtomicPtr<raw::RedisModuleCtx> = AtomicPtr::new(ptr::null_mut());
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+// Not read from WORKER, which is locked during cycles that may wake the worker themselves.
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref WORKER: Mutex<Option<Worker>> = Mutex::new(None);
+    // Token and deadline of the timer armed last. Timers armed before it are ignored when they
+    // fire, rather than stopped.
+    static ref ARMED: Mutex<Option<(u64, Deadline)>> = Mutex::new(None);
+}
+
+/// Sets up the context of the timers. Called on load.
+pub fn init(ctx: &Context) {
+    TIMER_CTX.store(unsafe { raw::RedisModule_GetDetachedThreadSafeContext.unwrap()(ctx.ctx) }, Ordering::SeqCst);
+}
+
+/// Hands `worker` to the timers and runs its first cycle right away. Must hold the GIL.
+pub fn start(worker: Worker) {
+    *WORKER.lock().unwrap() = Some(worker);
+    STARTED.store(true, Ordering::SeqCst);
+    arm(Deadline::now());
+}
+
+pub fn is_started() -> bool {
+    STARTED.load(Ordering::SeqCst)
+}
+
+/// Makes a cycle run by `at`, unless one is due to run earlier already. Must hold the GIL.
+pub fn arm(at: Deadline) {
+    let mut armed = ARMED.lock().unwrap();
+    if armed.is_some_and(|(_, deadline)| deadline <= at) {
+        return;
+    }
+    let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    *armed = Some((token, at));
+    let ctx = Context::new(TIMER_CTX.load(Ordering::SeqCst));
+    ctx.create_timer(at.remaining(), fire, token);
+}
+
+fn fire(ctx: &Context, token: u64) {
+    {
+        let mut armed = ARMED.lock().unwrap();
+        if armed.is_none_or(|(armed_token, _)| armed_token != token) {
+            return;
+        }
+        *armed = None;
+    }
+    let mut worker = WORKER.lock().unwrap();
+    let Some(running) = worker.as_mut() else {
+        return;
+    };
+    // Unwinding into the server would abort it.
+    let next = match panic::catch_unwind(AssertUnwindSafe(|| running.cycle(&Gil::Held(ctx)))) {
+        Ok(next) => next,
+        Err(panic) => {
+            watchdog::panicked(panic);
+            *worker = Some(Worker::new(1));
+            Some(Deadline::now() + watchdog::RESTART_DELAY)
+        }
+    };
+    drop(worker);
+    // Otherwise the next schedule arms a timer.
+    if let Some(next) = next {
+        arm(next);
+    }
+}
@@ -0,0 +1,84 @@
+//! Trace mode, `expiremember.trace`: every member the worker expires is logged with its key,
+//! deadline and deletion time, to the server log or to `expiremember.trace-file`, for finding out
+//! what deleted a member. At most `expiremember.trace-rate` members are logged a second.
+
+use lazy_static::lazy_static;
+use redis_module::Context;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use crate::deadline::Deadline;
+use crate::{json_string, ExpiringMember, TRACE, TRACE_FILE, TRACE_RATE};
+
+struct Tracer {
+    // The trace file as last opened, None when it could not be.
+    file: Option<(String, Option<File>)>,
+    // The second lines are counted for, and how many were logged and skipped in it.
+    second: u64,
+    logged: u64,
+    skipped: u64,
+}
+
+lazy_static! {
+    static ref TRACER: Mutex<Tracer> = Mutex::new(Tracer { file: None, second: 0, logged: 0, skipped: 0 });
+}
+
+/// Logs `members`, deleted at `deleted_at`, when tracing. Must hold the GIL.
+pub fn expired<'a>(ctx: &Context, members: impl Iterator<Item = &'a ExpiringMember>, deleted_at: Deadline) {
+    if !TRACE.load(Ordering::Relaxed) {
+        return;
+    }
+    let rate = TRACE_RATE.load(Ordering::Relaxed).max(1) as u64;
+    let deleted_at = deleted_at.unix_ms();
+    let mut tracer = TRACER.lock().unwrap();
+    let mut lines = Vec::new();
+
+    for member in members {
+        let second = deleted_at / 1000;
+        if second != tracer.second {
+            if tracer.skipped > 0 {
+                lines.push(format!("{} expirations not traced over trace-rate", tracer.skipped));
+            }
+            (tracer.second, tracer.logged, tracer.skipped) = (second, 0, 0);
+        }
+        if tracer.logged >= rate {
+            tracer.skipped += 1;
+            continue;
+        }
+        tracer.logged += 1;
+        lines.push(format!(
+            "expired db={} key={} member={} deadline={} deleted_at={}",
+            member.db, json_string(&member.key), json_string(&member.member), member.expire_at.unix_ms(), deleted_at
+        ));
+    }
+    if lines.is_empty() {
+        return;
+    }
+
+    let path = TRACE_FILE.lock().unwrap().clone();
+    if path.is_empty() {
+        tracer.file = None;
+        drop(tracer);
+        for line in lines {
+            ctx.log_notice(&line);
+        }
+        return;
+    }
+    if tracer.file.as_ref().is_none_or(|(opened, _)| *opened != path) {
+        let file = OpenOptions::new().create(true).append(true).open(&path)
+            .inspect_err(|err| ctx.log_warning(&format!("Cannot open trace file {}: {}", path, err)))
+            .ok();
+        tracer.file = Some((path.clone(), file));
+    }
+    if let Some((_, file @ Some(_))) = &mut tracer.file {
+        let mut text = lines.join("\n");
+        text.push('\n');
+        if let Err(err) = file.as_mut().unwrap().write_all(text.as_bytes()) {
+            // Not retried until the file is set again, rather than logging every batch.
+            ctx.log_warning(&format!("Cannot write to trace file {}, tracing to it stopped: {}", path, err));
+            *file = None;
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Watchdog: a thread of its own checking that the worker is alive and its cycles keep ending,
+//! so expirations that stop, because the worker's thread exited or is stuck, are logged and
+//! reported as the worker's `health` rather than going unnoticed.
+//!
+//! Panics of the worker and the deletion threads are caught rather than ending them: the worker
+//! starts over with its schedule rebuilt from the tracked expirations, see `panicked`.
+
+use lazy_static::lazy_static;
+use redis_module::logging;
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::stats::{self, Counter};
+use crate::{info, EXPIRATION_QUEUE, HEAP_REBUILD, MAX_SLEEP, WATCHDOG_TIMEOUT};
+
+/// How often the worker is checked on.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the worker waits before starting over after a panic, so one that repeats does not
+/// keep a CPU busy.
+pub const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Health {
+    Ok = 0,
+    // No cycle ended for `watchdog-timeout` while members were scheduled.
+    Stalled = 1,
+    // The worker's thread exited.
+    Dead = 2,
+}
+
+static HEALTH: AtomicU8 = AtomicU8::new(Health::Ok as u8);
+static STALLS: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Starts watching the worker, running on `worker` under the thread driver.
+pub fn start(worker: Option<JoinHandle<()>>) {
+    let started = Instant::now();
+    thread::spawn(move || {
+        // When schedules were first seen queued for a parked worker, which they should wake up.
+        let mut queued_since = None;
+        loop {
+            thread::sleep(CHECK_INTERVAL);
+            let timeout = Duration::from_millis(WATCHDOG_TIMEOUT.load(Ordering::Relaxed).max(0) as u64);
+            let (last_cycle, parked) = info::liveness();
+            queued_since = (parked && EXPIRATION_QUEUE.len() > 0).then(|| queued_since.unwrap_or_else(Instant::now));
+
+            // Unless parked, the worker ends a cycle at least every `max-sleep`.
+            let max_sleep = Duration::from_millis(MAX_SLEEP.load(Ordering::Relaxed).max(1) as u64);
+            let stalled = match queued_since {
+                Some(since) => since.elapsed() > timeout,
+                None => !parked && last_cycle.unwrap_or_else(|| started.elapsed()) > timeout + max_sleep,
+            };
+            let health = if worker.as_ref().is_some_and(|worker| worker.is_finished()) {
+                Health::Dead
+            } else if !timeout.is_zero() && stalled {
+                Health::Stalled
+            } else {
+                Health::Ok
+            };
+
+            let previous = HEALTH.swap(health as u8, Ordering::Relaxed);
+            if previous == health as u8 {
+                continue;
+            }
+            match health {
+                Health::Dead => logging::log_warning("The expiry thread exited, members are no longer expired"),
+                Health::Stalled => logging::log_warning(format!("No expiry cycle ended for {} ms, members are not expired on time", timeout.as_millis())),
+                Health::Ok => logging::log_notice("Expiry cycles resumed"),
+            }
+            if previous == Health::Ok as u8 {
+                STALLS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+}
+
+/// Records a panic caught in the worker or a deletion thread, and has the worker's schedule
+/// rebuilt from the tracked expirations, since due members may have been taken off it without
+/// being deleted.
+pub fn panicked(payload: Box<dyn Any + Send>) {
+    let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown cause".to_string());
+    logging::log_warning(format!("The expiry thread panicked, starting it over: {}", message));
+    stats::add(Counter::WorkerPanics, 1);
+    *LAST_PANIC.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(message);
+    HEAP_REBUILD.store(true, Ordering::SeqCst);
+}
+
+/// The message of the last panic caught, None without one.
+#[cfg(feature = "debug")]
+pub fn last_panic() -> Option<String> {
+    LAST_PANIC.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+/// The worker's health, `ok`, `stalled` or `dead`.
+pub fn health() -> &'static str {
+    match HEALTH.load(Ordering::Relaxed) {
+        0 => "ok",
+        1 => "stalled",
+        _ => "dead",
+    }
+}
+
+/// Times the worker was found stalled or dead.
+pub fn stalls() -> u64 {
+    STALLS.load(Ordering::Relaxed)
+}
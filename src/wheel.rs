@@ -0,0 +1,131 @@
+//! Hierarchical timer wheel, an alternative to the worker's heap for large numbers of scheduled
+//! members: O(1) insertion, and due entries are collected a slot at a time.
+//!
+//! Time is counted in ticks (ms). Level `n` has 64 slots of 64^n ticks each, entries sit at the
+//! level where their deadline first differs from the current tick and move down a level each
+//! time their slot comes up, until they are due. Deadlines past the top level wrap around and
+//! are put back until they are in range.
+
+const LEVELS: usize = 6;
+const SLOTS: usize = 64;
+const SLOT_BITS: u32 = 6;
+
+struct Level<T> {
+    slots: Vec<Vec<(u64, T)>>,
+    // Bit `n` is set when slot `n` holds entries.
+    occupied: u64,
+}
+
+pub struct TimerWheel<T> {
+    levels: Vec<Level<T>>,
+    // Ticks up to this one have been processed.
+    elapsed: u64,
+    // Entries due at or before `elapsed`.
+    due: Vec<(u64, T)>,
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    pub fn new() -> Self {
+        TimerWheel {
+            levels: (0..LEVELS).map(|_| Level { slots: (0..SLOTS).map(|_| Vec::new()).collect(), occupied: 0 }).collect(),
+            elapsed: 0,
+            due: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Number of entries found due when last advanced and not popped yet.
+    pub fn due_len(&self) -> usize {
+        self.due.len()
+    }
+
+    pub fn insert(&mut self, tick: u64, item: T) {
+        self.len += 1;
+        self.place(tick, item);
+    }
+
+    fn place(&mut self, tick: u64, item: T) {
+        if tick <= self.elapsed {
+            self.due.push((tick, item));
+            return;
+        }
+        let level = level_for(self.elapsed, tick);
+        let slot = ((tick >> (level as u32 * SLOT_BITS)) as usize) % SLOTS;
+        self.levels[level].slots[slot].push((tick, item));
+        self.levels[level].occupied |= 1 << slot;
+    }
+
+    /// Removes an entry due at or before `now`, if any.
+    pub fn pop_due(&mut self, now: u64) -> Option<(u64, T)> {
+        if self.due.is_empty() {
+            self.advance(now);
+        }
+        let entry = self.due.pop()?;
+        self.len -= 1;
+        Some(entry)
+    }
+
+    /// Processes every slot starting at or before `now`, moving their entries down a level or
+    /// to the due ones.
+    fn advance(&mut self, now: u64) {
+        while let Some((level, slot, start)) = self.next_slot() {
+            if start > now {
+                break;
+            }
+            self.elapsed = start;
+            self.levels[level].occupied &= !(1 << slot);
+            for (tick, item) in std::mem::take(&mut self.levels[level].slots[slot]) {
+                self.place(tick, item);
+            }
+        }
+        self.elapsed = self.elapsed.max(now);
+    }
+
+    /// The tick the next entry may be due at: exact for the due ones and those at the lowest
+    /// level, the start of their slot otherwise.
+    pub fn next_tick(&self) -> Option<u64> {
+        match self.due.first() {
+            Some(_) => Some(self.elapsed),
+            None => self.next_slot().map(|(_, _, start)| start),
+        }
+    }
+
+    /// (level, slot, starting tick) of the first occupied slot. Slots of a lower level always
+    /// start before those of a higher one.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        self.levels.iter().enumerate().find_map(|(level, entries)| {
+            if entries.occupied == 0 {
+                return None;
+            }
+            let slot_range = 1u64 << (level as u32 * SLOT_BITS);
+            let level_range = slot_range << SLOT_BITS;
+            let now_slot = ((self.elapsed / slot_range) % SLOTS as u64) as u32;
+            let slot = (entries.occupied.rotate_right(now_slot).trailing_zeros() + now_slot) as usize % SLOTS;
+            let mut start = (self.elapsed & !(level_range - 1)) + slot as u64 * slot_range;
+            // Only at the top level, for deadlines wrapping around.
+            if start <= self.elapsed {
+                start += level_range;
+            }
+            Some((level, slot, start))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.levels.iter()
+            .flat_map(|level| level.slots.iter().flatten())
+            .chain(self.due.iter())
+            .map(|(_, item)| item)
+    }
+}
+
+/// The level of an entry due at `tick`: the one of the highest 6-bit group it differs from
+/// `elapsed` in, capped at the top level.
+fn level_for(elapsed: u64, tick: u64) -> usize {
+    let significant = 63 - ((elapsed ^ tick) | (SLOTS as u64 - 1)).leading_zeros();
+    (significant / SLOT_BITS).min(LEVELS as u32 - 1) as usize
+}
@@ -3,7 +3,7 @@ mod tests {
     use redis::RedisResult;
     use std::process::{Command, Child};
     use std::env;
-    use std::sync::{Arc, Mutex, atomic::AtomicUsize};
+    use std::sync::{Arc, Mutex, atomic::{AtomicU16, AtomicUsize, Ordering}};
     use std::time::Duration;
     use ctor::{ctor, dtor};
     use lazy_static::lazy_static;
@@ -20,6 +20,8 @@ mod tests {
         let child = Command::new(redis_server_bin)
             .arg("--port")
             .arg("34123")
+            .arg("--enable-debug-command")
+            .arg("local")
             .arg("--loadmodule")
             .arg("target/debug/libredis_expiremember_module.so")
             .spawn()
@@ -431,4 +433,2760 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    #[cfg(feature = "notifications")]
+    fn test_expiremember_event_includes_value() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+        let mut sub_con = client.get_connection()?;
+
+        let _: () = redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("expiremember.events-channel")
+            .arg("expiremember_events")
+            .arg("expiremember.events-include-value")
+            .arg("yes")
+            .query(&mut con)?;
+
+        let mut pubsub = sub_con.as_pubsub();
+        pubsub.subscribe("expiremember_events")?;
+        pubsub.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let _: () = redis::cmd("HSET")
+            .arg("myhash_events")
+            .arg("field")
+            .arg("value1")
+            .query(&mut con)?;
+
+        let _: () = redis::cmd("EXPIREMEMBER")
+            .arg("myhash_events")
+            .arg("field")
+            .arg(500)
+            .arg("ms")
+            .query(&mut con)?;
+
+        // Other tests may expire members concurrently, so skip unrelated events
+        loop {
+            let payload: String = pubsub.get_message()?.get_payload()?;
+            if payload.contains("\"key\":\"myhash_events\"") {
+                assert!(payload.contains("\"member\":\"field\""), "Unexpected event payload {}", payload);
+                assert!(payload.contains("\"value\":\"value1\""), "The event should carry the expired value, got {}", payload);
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_script_hook() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let sha: String = redis::cmd("SCRIPT")
+            .arg("LOAD")
+            .arg("redis.call('SADD', 'expire_hook_log', KEYS[1] .. ':' .. ARGV[1])")
+            .query(&mut con)?;
+        let _: () = redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("expiremember.expire-script-sha")
+            .arg(&sha)
+            .query(&mut con)?;
+
+        let _: () = redis::cmd("SADD")
+            .arg("myset_hook")
+            .arg("member1")
+            .query(&mut con)?;
+
+        let _: () = redis::cmd("EXPIREMEMBER")
+            .arg("myset_hook")
+            .arg("member1")
+            .arg(500)
+            .arg("ms")
+            .query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(1500));
+
+        let logged: u8 = redis::cmd("SISMEMBER")
+            .arg("expire_hook_log")
+            .arg("myset_hook:member1")
+            .query(&mut con)?;
+
+        let _: () = redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("expiremember.expire-script-sha")
+            .arg("")
+            .query(&mut con)?;
+
+        assert!(logged == 1, "The script hook should have been invoked for the expired member");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "notifications")]
+    type SubscribeReply = (u64, Vec<(u64, String, String, u64)>);
+
+    #[test]
+    #[cfg(feature = "notifications")]
+    fn test_expiremember_subscribe_functionality() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("expiremember.event-log-size")
+            .arg(100000)
+            .query(&mut con)?;
+
+        // Start from the current end of the log
+        let (mut cursor, _): SubscribeReply = redis::cmd("EXPIREMEMBER.SUBSCRIBE")
+            .arg("$")
+            .query(&mut con)?;
+
+        let _: () = redis::cmd("HSET")
+            .arg("myhash_subscribe")
+            .arg("field")
+            .arg("value")
+            .query(&mut con)?;
+
+        let _: () = redis::cmd("EXPIREMEMBER")
+            .arg("myhash_subscribe")
+            .arg("field")
+            .arg(300)
+            .arg("ms")
+            .query(&mut con)?;
+
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < Duration::from_secs(5), "The expiration should have been streamed to the subscriber");
+
+            let reply: Option<SubscribeReply> = redis::cmd("EXPIREMEMBER.SUBSCRIBE")
+                .arg(cursor)
+                .arg("BLOCK")
+                .arg(1000)
+                .query(&mut con)?;
+            let Some((next_cursor, events)) = reply else { continue };
+            assert!(next_cursor > cursor, "The cursor should advance past the returned events");
+            cursor = next_cursor;
+
+            if events.iter().any(|(_, key, member, _)| key == "myhash_subscribe" && member == "field") {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_survives_debug_reload() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET")
+            .arg("myhash_reload")
+            .arg("field")
+            .arg("value")
+            .query(&mut con)?;
+
+        let _: () = redis::cmd("EXPIREMEMBER")
+            .arg("myhash_reload")
+            .arg("field")
+            .arg(2)
+            .query(&mut con)?;
+
+        // Saves an RDB, flushes everything and loads it back
+        let _: () = redis::cmd("DEBUG")
+            .arg("RELOAD")
+            .query(&mut con)?;
+
+        let exists: u8 = redis::cmd("HEXISTS")
+            .arg("myhash_reload")
+            .arg("field")
+            .query(&mut con)?;
+        assert!(exists == 1, "The field should still exist right after the reload");
+
+        std::thread::sleep(Duration::from_secs(3));
+
+        let exists: u8 = redis::cmd("HEXISTS")
+            .arg("myhash_reload")
+            .arg("field")
+            .query(&mut con)?;
+        assert!(exists == 0, "The expiration should have been restored from the RDB");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expirememberat_functionality() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET")
+            .arg("myhash_at")
+            .arg("field1")
+            .arg("value")
+            .arg("field2")
+            .arg("value")
+            .query(&mut con)?;
+
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+
+        // A deadline in the past deletes the member right away
+        let _: () = redis::cmd("EXPIREMEMBERAT")
+            .arg("myhash_at")
+            .arg("field1")
+            .arg(now_ms / 1000 - 10)
+            .query(&mut con)?;
+
+        let exists: u8 = redis::cmd("HEXISTS")
+            .arg("myhash_at")
+            .arg("field1")
+            .query(&mut con)?;
+        assert!(exists == 0, "A deadline in the past should delete the field immediately");
+
+        let _: () = redis::cmd("EXPIREMEMBERAT")
+            .arg("myhash_at")
+            .arg("field2")
+            .arg(now_ms + 1000)
+            .arg("ms")
+            .query(&mut con)?;
+
+        let exists: u8 = redis::cmd("HEXISTS")
+            .arg("myhash_at")
+            .arg("field2")
+            .query(&mut con)?;
+        assert!(exists == 1, "The field should still exist before its deadline");
+
+        std::thread::sleep(Duration::from_secs(2));
+
+        let exists: u8 = redis::cmd("HEXISTS")
+            .arg("myhash_at")
+            .arg("field2")
+            .query(&mut con)?;
+        assert!(exists == 0, "The field should be deleted after its deadline");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_expire_times_are_rejected() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("myhash_invalid").arg("field1").arg("value").query(&mut con)?;
+
+        let errors = [
+            redis::cmd("EXPIREMEMBER").arg("myhash_invalid").arg("field1").arg(-2).clone(),
+            redis::cmd("EXPIREMEMBER").arg("myhash_invalid").arg("field1").arg(i64::MAX).clone(),
+            redis::cmd("EXPIREMEMBERAT").arg("myhash_invalid").arg("field1").arg(i64::MAX).clone(),
+            redis::cmd("EXPIREMEMBERAT").arg("myhash_invalid").arg("field1").arg(i64::MAX).arg("ms").clone(),
+        ];
+        for command in errors {
+            let error = command.query::<i64>(&mut con).unwrap_err();
+            assert!(error.to_string().contains("invalid expire time"), "expected invalid expire time, got {}", error);
+        }
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("myhash_invalid").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "A rejected expire time should leave the field in place");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backend_is_fixed_at_load_time() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let backend: Vec<String> = redis::cmd("CONFIG")
+            .arg("GET")
+            .arg("expiremember.backend")
+            .query(&mut con)?;
+        assert_eq!(backend, vec!["expiremember.backend", "memory"]);
+
+        let result: RedisResult<()> = redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("expiremember.backend")
+            .arg("datatype")
+            .query(&mut con);
+        assert!(result.is_err(), "The backend should not be changeable at runtime");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_dump_restore() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("dump_src").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("dump_dst").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("dump_src").arg("field1").arg(2).query(&mut con)?;
+
+        let payload: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("dump_src").query(&mut con)?;
+        let restored: i64 = redis::cmd("EXPIREMEMBER.RESTORE").arg("dump_dst").arg(&payload).query(&mut con)?;
+        assert_eq!(restored, 1);
+
+        let mut corrupt = payload.clone();
+        corrupt[0] ^= 0xff;
+        let result: RedisResult<i64> = redis::cmd("EXPIREMEMBER.RESTORE").arg("dump_dst").arg(corrupt).query(&mut con);
+        assert!(result.is_err(), "A corrupt payload should be rejected");
+
+        std::thread::sleep(Duration::from_secs(3));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("dump_dst").arg("field1").query(&mut con)?;
+        assert!(exists == 0, "The restored expiration should delete the field at the original deadline");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_export_import() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+        let path = std::env::temp_dir().join("expiremember_export_test.snapshot");
+
+        let _: () = redis::cmd("HSET").arg("export_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("export_hash").arg("field1").arg(3).query(&mut con)?;
+
+        let _: () = redis::cmd("EXPIREMEMBER.EXPORT").arg(path.to_str().unwrap()).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(path.exists(), "The export should have written the snapshot file");
+
+        // Forget the expiration, then bring it back from the snapshot.
+        let _: () = redis::cmd("EXPIREMEMBER").arg("export_hash").arg("field1").arg(-1).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER.IMPORT").arg(path.to_str().unwrap()).query(&mut con)?;
+
+        std::thread::sleep(Duration::from_secs(4));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("export_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 0, "The imported expiration should delete the field");
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_priority_survives_dump_and_export() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+        let path = std::env::temp_dir().join("expiremember_priority_test.snapshot");
+
+        let _: () = redis::cmd("HSET").arg("priority_src").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("priority_dst").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("priority_src").arg("field1").arg(60).arg("PRIORITY").arg("high").query(&mut con)?;
+
+        // The record ends with the priority, high being 0, before the checksum.
+        let payload: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("priority_src").query(&mut con)?;
+        assert_eq!(payload[payload.len() - 9], 0);
+        let _: () = redis::cmd("EXPIREMEMBER.RESTORE").arg("priority_dst").arg(&payload).query(&mut con)?;
+        let restored: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("priority_dst").query(&mut con)?;
+        assert_eq!(restored, payload, "The restored expiration should keep its priority");
+
+        let _: () = redis::cmd("EXPIREMEMBER.EXPORT").arg(path.to_str().unwrap()).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+        let _: () = redis::cmd("EXPIREMEMBER").arg("priority_src").arg("field1").arg(-1).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER.IMPORT").arg(path.to_str().unwrap()).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+        let imported: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("priority_src").query(&mut con)?;
+        assert_eq!(imported, payload, "The imported expiration should keep its priority");
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    /// Ports of the servers started by the tests, past those of the shared server and its
+    /// neighbours, each test taking its own.
+    static NEXT_PORT: AtomicU16 = AtomicU16::new(34200);
+
+    /// A port no other test uses.
+    fn free_port() -> u16 {
+        NEXT_PORT.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Another server started by a test, killed and waited on when dropped, so it is stopped
+    /// however the test ends, failed assertions included.
+    struct Server {
+        child: Child,
+        port: u16,
+    }
+
+    impl Server {
+        /// The URL clients connect to it with.
+        fn url(&self) -> String {
+            format!("redis://127.0.0.1:{}/", self.port)
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    /// Starts another server with the module on a free port and waits until `ready` holds for
+    /// its `INFO` output.
+    fn start_server(args: &[&str], ready: impl Fn(&str) -> bool) -> RedisResult<(Server, redis::Connection)> {
+        start_server_with_module_args(args, &[], ready)
+    }
+
+    /// `start_server`, passing `module_args` to the module.
+    fn start_server_with_module_args(args: &[&str], module_args: &[&str], ready: impl Fn(&str) -> bool) -> RedisResult<(Server, redis::Connection)> {
+        start_server_on(free_port(), args, module_args, ready)
+    }
+
+    /// `start_server_with_module_args` on `port`, for a server restarted on the same one.
+    fn start_server_on(port: u16, args: &[&str], module_args: &[&str], ready: impl Fn(&str) -> bool) -> RedisResult<(Server, redis::Connection)> {
+        let redis_server_bin = env::var("REDIS_SERVER_BIN").unwrap_or_else(|_| "redis-server".to_string());
+        let child = Command::new(redis_server_bin)
+            .arg("--port")
+            .arg(port.to_string())
+            .args(args)
+            .arg("--loadmodule")
+            .arg("target/debug/libredis_expiremember_module.so")
+            .args(module_args)
+            .spawn()
+            .expect("Failed to start another Redis server with the module");
+        wait_for_server(child, port, ready)
+    }
+
+    /// Waits until `ready` holds for the `INFO` output of `child`, listening on `port`.
+    fn wait_for_server(child: Child, port: u16, ready: impl Fn(&str) -> bool) -> RedisResult<(Server, redis::Connection)> {
+        let server = Server { child, port };
+        let start = Instant::now();
+        loop {
+            std::thread::sleep(Duration::from_millis(200));
+            let client = redis::Client::open(format!("redis://127.0.0.1:{}/", port))?;
+            if let Ok(mut con) = client.get_connection() {
+                let info: String = redis::cmd("INFO").query(&mut con)?;
+                if ready(&info) {
+                    return Ok((server, con));
+                }
+            }
+            assert!(start.elapsed() <= Duration::from_secs(10), "The server on port {} should become ready", port);
+        }
+    }
+
+    /// Starts a replica of the test server and waits until it is in sync.
+    fn start_replica() -> RedisResult<(Server, redis::Connection)> {
+        start_server(&["--replicaof", "127.0.0.1", "34123"], |info| info.contains("master_link_status:up"))
+    }
+
+    #[test]
+    fn test_replica_follows_primary_expirations() -> RedisResult<()> {
+        let mut con = redis::Client::open("redis://127.0.0.1:34123/")?.get_connection()?;
+        let (_replica_server, mut replica) = start_replica()?;
+
+        let _: () = redis::cmd("HSET").arg("replica_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("replica_hash").arg("field1").arg(1).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("replica_hash").arg("field1").query(&mut replica)?;
+        assert!(exists == 1, "The replica should have the field before its deadline");
+
+        std::thread::sleep(Duration::from_secs(2));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("replica_hash").arg("field1").query(&mut replica)?;
+        assert!(exists == 0, "The primary's deletion should reach the replica");
+        Ok(())
+    }
+
+    #[test]
+    fn test_promoted_replica_takes_over_expirations() -> RedisResult<()> {
+        let mut con = redis::Client::open("redis://127.0.0.1:34123/")?.get_connection()?;
+        let (_replica_server, mut replica) = start_replica()?;
+
+        let _: () = redis::cmd("HSET").arg("promoted_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("promoted_hash").arg("field1").arg(2).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+
+        // Cut the link before the primary's deletion can be replicated.
+        let _: () = redis::cmd("REPLICAOF").arg("NO").arg("ONE").query(&mut replica)?;
+
+        std::thread::sleep(Duration::from_secs(3));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("promoted_hash").arg("field1").query(&mut replica)?;
+        assert!(exists == 0, "The promoted replica should expire the field itself");
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_command_keys_and_flags() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let keys: Vec<String> = redis::cmd("COMMAND")
+            .arg("GETKEYS")
+            .arg("EXPIREMEMBER")
+            .arg("myhash")
+            .arg("field1")
+            .arg(10)
+            .query(&mut con)?;
+        assert_eq!(keys, vec!["myhash"]);
+
+        let keys: Vec<String> = redis::cmd("COMMAND")
+            .arg("GETKEYS")
+            .arg("EXPIREMEMBERAT")
+            .arg("myhash")
+            .arg("field1")
+            .arg(10)
+            .query(&mut con)?;
+        assert_eq!(keys, vec!["myhash"]);
+
+        // The key is optional, routed when given.
+        let keys: Vec<String> = redis::cmd("COMMAND").arg("GETKEYS").arg("EXPIREMEMBER.SYNC").arg("myhash").query(&mut con)?;
+        assert_eq!(keys, vec!["myhash"]);
+
+        let info: redis::Value = redis::cmd("COMMAND").arg("INFO").arg("EXPIREMEMBER").query(&mut con)?;
+        let info = format!("{:?}", info);
+        assert!(info.contains("write") && info.contains("denyoom"), "EXPIREMEMBER should be flagged as a write command: {}", info);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_dumpslot() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        // The {foo} hash tag maps to slot 12182.
+        let _: () = redis::cmd("SADD").arg("{foo}slot_set").arg("member1").query(&mut con)?;
+        let _: () = redis::cmd("SADD").arg("{foo}slot_set_copy").arg("member1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("{foo}slot_set").arg("member1").arg(2).query(&mut con)?;
+
+        let dumped: Vec<Vec<u8>> = redis::cmd("EXPIREMEMBER.DUMPSLOT").arg(12182).query(&mut con)?;
+        let position = dumped.chunks(2).position(|pair| pair[0] == b"{foo}slot_set");
+        let payload = dumped[position.expect("The slot dump should contain the key") * 2 + 1].clone();
+
+        let result: RedisResult<()> = redis::cmd("EXPIREMEMBER.DUMPSLOT").arg(16384).query(&mut con);
+        assert!(result.is_err(), "Slots past 16383 should be rejected");
+
+        let _: () = redis::cmd("EXPIREMEMBER.RESTORE").arg("{foo}slot_set_copy").arg(payload).query(&mut con)?;
+        std::thread::sleep(Duration::from_secs(3));
+
+        let exists: u8 = redis::cmd("SISMEMBER").arg("{foo}slot_set_copy").arg("member1").query(&mut con)?;
+        assert!(exists == 0, "The expiration restored from the slot dump should delete the member");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_migrate() -> RedisResult<()> {
+        let mut con = redis::Client::open("redis://127.0.0.1:34123/")?.get_connection()?;
+        let (target_server, mut target) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("migrate_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("migrate_hash").arg("field1").arg("value1").query(&mut target)?;
+        let _: () = redis::cmd("HSET").arg("unmatched_hash").arg("field1").arg("value1").query(&mut target)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("migrate_hash").arg("field1").arg(2).query(&mut con)?;
+
+        // A member the target rejects does not hold back the next database's.
+        let mut db1 = redis::Client::open("redis://127.0.0.1:34123/1")?.get_connection()?;
+        let mut target_db1 = redis::Client::open(format!("{}1", target_server.url()))?.get_connection()?;
+        let _: () = redis::cmd("HSET").arg("migrate_rejected").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("SET").arg("migrate_rejected").arg("value1").query(&mut target)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("migrate_rejected").arg("field1").arg(60).query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("migrate_db1").arg("field1").arg("value1").query(&mut db1)?;
+        let _: () = redis::cmd("HSET").arg("migrate_db1").arg("field1").arg("value1").query(&mut target_db1)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("migrate_db1").arg("field1").arg(2).query(&mut db1)?;
+
+        let _: () = redis::cmd("EXPIREMEMBER.MIGRATE")
+            .arg("127.0.0.1")
+            .arg(target_server.port)
+            .arg("MATCH")
+            .arg("migrate_*")
+            .arg("BATCH")
+            .arg(10)
+            .query(&mut con)?;
+
+        std::thread::sleep(Duration::from_secs(3));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("migrate_hash").arg("field1").query(&mut target)?;
+        assert!(exists == 0, "The migrated expiration should delete the field on the target");
+        let exists: u8 = redis::cmd("HEXISTS").arg("unmatched_hash").arg("field1").query(&mut target)?;
+        assert!(exists == 1, "Keys not matching the pattern should be left alone");
+        let exists: u8 = redis::cmd("HEXISTS").arg("migrate_db1").arg("field1").query(&mut target_db1)?;
+        assert!(exists == 0, "Members after a rejected one should still be migrated");
+        Ok(())
+    }
+
+    #[test]
+    fn test_expirememberat_clock_merge_policy() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.merge-policy").arg("clock").query(&mut con)?;
+        let result = (|| -> RedisResult<()> {
+            let now_ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64;
+            let _: () = redis::cmd("HSET").arg("clock_hash").arg("field1").arg("value1").query(&mut con)?;
+
+            // A schedule from far in the logical future...
+            let applied: i64 = redis::cmd("EXPIREMEMBERAT")
+                .arg("clock_hash").arg("field1").arg(now_ms + 1000).arg("ms")
+                .arg("CLOCK").arg(now_ms * 2)
+                .query(&mut con)?;
+            assert_eq!(applied, 1);
+
+            // ...wins over an older one arriving later, whatever its deadline.
+            let applied: i64 = redis::cmd("EXPIREMEMBERAT")
+                .arg("clock_hash").arg("field1").arg(now_ms + 60_000).arg("ms")
+                .arg("CLOCK").arg(1)
+                .query(&mut con)?;
+            assert_eq!(applied, 0, "A schedule with an older clock should be ignored");
+
+            std::thread::sleep(Duration::from_secs(2));
+
+            let exists: u8 = redis::cmd("HEXISTS").arg("clock_hash").arg("field1").query(&mut con)?;
+            assert!(exists == 0, "The schedule with the newest clock should be kept");
+            Ok(())
+        })();
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.merge-policy").arg("arrival").query(&mut con)?;
+        result
+    }
+
+    #[test]
+    fn test_expiry_deletes_show_in_monitor() -> RedisResult<()> {
+        use std::io::{BufRead, BufReader, Write};
+
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let mut monitor = std::net::TcpStream::connect("127.0.0.1:34123")?;
+        monitor.set_read_timeout(Some(Duration::from_secs(5)))?;
+        monitor.write_all(b"MONITOR\r\n")?;
+        let mut lines = BufReader::new(monitor).lines();
+
+        let _: () = redis::cmd("HSET").arg("monitor_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("monitor_hash").arg("field1").arg(1).query(&mut con)?;
+
+        let seen = lines.any(|line| line.is_ok_and(|line| line.to_lowercase().contains("\"hdel\" \"monitor_hash\" \"field1\"")));
+        assert!(seen, "The background deletion should be visible in MONITOR");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_sync() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("sync_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("sync_hash").arg("field2").arg("value2").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("sync_hash").arg("field1").arg(5).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("sync_hash").arg("field2").arg(60).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(10));
+
+        // The background thread may get there first, but never after the barrier.
+        let expired: i64 = redis::cmd("EXPIREMEMBER.SYNC").arg("sync_hash").query(&mut con)?;
+        assert!(expired <= 1, "Only the overdue member should be expired");
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("sync_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 0, "The overdue field should be gone once EXPIREMEMBER.SYNC returns");
+        let exists: u8 = redis::cmd("HEXISTS").arg("sync_hash").arg("field2").query(&mut con)?;
+        assert!(exists == 1, "Fields that are not due yet should be kept");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deleted_key_forgets_expirations() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("deleted_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("deleted_hash").arg("field1").arg(500).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("DEL").arg("deleted_hash").query(&mut con)?;
+
+        // A key recreated under the same name must not inherit the old expiration.
+        let _: () = redis::cmd("HSET").arg("deleted_hash").arg("field1").arg("value2").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1000));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("deleted_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "The expiration should be forgotten when its key is deleted");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flushall_forgets_expirations() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("flushed_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("flushed_hash").arg("field1").arg(500).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("FLUSHALL").query(&mut con)?;
+
+        let _: () = redis::cmd("HSET").arg("flushed_hash").arg("field1").arg("value2").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1000));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("flushed_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "Expirations should be forgotten by FLUSHALL");
+        Ok(())
+    }
+
+    #[test]
+    fn test_renamed_key_keeps_expirations() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("rename_src").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("rename_src").arg("field2").arg("value2").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("rename_src").arg("field1").arg(500).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("RENAME").arg("rename_src").arg("rename_dst").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("rename_src").arg("field1").arg("value1").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1000));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("rename_dst").arg("field1").query(&mut con)?;
+        assert!(exists == 0, "The expiration should follow the key to its new name");
+        let exists: u8 = redis::cmd("HEXISTS").arg("rename_dst").arg("field2").query(&mut con)?;
+        assert!(exists == 1, "Members without an expiration should be kept");
+        let exists: u8 = redis::cmd("HEXISTS").arg("rename_src").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "A key created under the old name should not inherit the expiration");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_expirations() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.copy-expirations").arg("yes").query(&mut con)?;
+        let result = (|| -> RedisResult<()> {
+            let _: () = redis::cmd("HSET").arg("copy_src").arg("field1").arg("value1").query(&mut con)?;
+            let _: () = redis::cmd("HSET").arg("copy_src").arg("field2").arg("value2").query(&mut con)?;
+            let _: () = redis::cmd("EXPIREMEMBER").arg("copy_src").arg("field1").arg(500).arg("ms").query(&mut con)?;
+            let _: () = redis::cmd("COPY").arg("copy_src").arg("copy_dst").query(&mut con)?;
+            std::thread::sleep(Duration::from_millis(1000));
+
+            let exists: u8 = redis::cmd("HEXISTS").arg("copy_dst").arg("field1").query(&mut con)?;
+            assert!(exists == 0, "The copy should get the expirations of the source");
+            let exists: u8 = redis::cmd("HEXISTS").arg("copy_dst").arg("field2").query(&mut con)?;
+            assert!(exists == 1, "Members without an expiration should be kept");
+            let exists: u8 = redis::cmd("HEXISTS").arg("copy_src").arg("field1").query(&mut con)?;
+            assert!(exists == 0, "The source should keep its expirations");
+            Ok(())
+        })();
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.copy-expirations").arg("no").query(&mut con)?;
+        result
+    }
+
+    #[test]
+    fn test_moved_key_keeps_expirations() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+        let mut db1 = redis::Client::open("redis://127.0.0.1:34123/1")?.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("moved_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("moved_hash").arg("field1").arg(500).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("MOVE").arg("moved_hash").arg(1).query(&mut con)?;
+
+        // A key created in database 0 under the same name must not inherit the expiration.
+        let _: () = redis::cmd("HSET").arg("moved_hash").arg("field1").arg("value2").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1000));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("moved_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "The expiration should not stay behind in database 0");
+        let exists: u8 = redis::cmd("HEXISTS").arg("moved_hash").arg("field1").query(&mut db1)?;
+        assert!(exists == 0, "The expiration should follow the key to database 1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_removed_member_forgets_expiration() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("removed_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("removed_hash").arg("field2").arg("value2").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("removed_hash").arg("field1").arg(500).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("HDEL").arg("removed_hash").arg("field1").query(&mut con)?;
+
+        // A field created again must not inherit the expiration of the deleted one.
+        let _: () = redis::cmd("HSET").arg("removed_hash").arg("field1").arg("value3").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1000));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("removed_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "The expiration should be forgotten when its member is removed");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_policy() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("policy_clear").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("policy_reset").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER.POLICY").arg("policy_clear").arg("CLEAR").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER.POLICY").arg("policy_reset").arg("RESET").arg(1500).arg("ms").query(&mut con)?;
+        let policy: (String, i64) = redis::cmd("EXPIREMEMBER.POLICY").arg("policy_reset").query(&mut con)?;
+        assert_eq!(policy, ("reset".to_string(), 1500));
+
+        let _: () = redis::cmd("EXPIREMEMBER").arg("policy_clear").arg("field1").arg(500).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("policy_reset").arg("field1").arg(500).arg("ms").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(200));
+        let _: () = redis::cmd("HSET").arg("policy_clear").arg("field1").arg("value2").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("policy_reset").arg("field1").arg("value2").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(800));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("policy_clear").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "CLEAR should forget the expiration of an overwritten member");
+        let exists: u8 = redis::cmd("HEXISTS").arg("policy_reset").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "RESET should push the expiration of an overwritten member back");
+
+        std::thread::sleep(Duration::from_millis(1200));
+        let exists: u8 = redis::cmd("HEXISTS").arg("policy_reset").arg("field1").query(&mut con)?;
+        assert!(exists == 0, "RESET should expire the member the configured time after the write");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expired_key_forgets_expirations() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("expired_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("expired_hash").arg("field1").arg(800).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("PEXPIRE").arg("expired_hash").arg(100).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(200));
+
+        // Accessing the key expires it if the server has not done so already.
+        let exists: u8 = redis::cmd("EXISTS").arg("expired_hash").query(&mut con)?;
+        assert!(exists == 0, "The key should have expired");
+        let _: () = redis::cmd("HSET").arg("expired_hash").arg("field1").arg("value2").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1000));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("expired_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "The expirations of an expired key should be forgotten");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evicted_key_forgets_expirations() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--maxmemory-policy", "volatile-lru"], |_| true)?;
+
+        let untracked: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("evicted_hash").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("evicted_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("evicted_hash").arg("field1").arg(60).query(&mut con)?;
+        // The only key with a TTL, so the first to be evicted.
+        let _: () = redis::cmd("EXPIRE").arg("evicted_hash").arg(3600).query(&mut con)?;
+
+        let info: String = redis::cmd("INFO").arg("memory").query(&mut con)?;
+        let used_memory: u64 = info.lines()
+            .find_map(|line| line.strip_prefix("used_memory:"))
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap();
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("maxmemory").arg(used_memory + 256 * 1024).query(&mut con)?;
+
+        let filler = "x".repeat(1024);
+        for i in 0..1000 {
+            let exists: u8 = redis::cmd("EXISTS").arg("evicted_hash").query(&mut con)?;
+            if exists == 0 {
+                break;
+            }
+            let _: RedisResult<()> = redis::cmd("SET").arg(format!("filler{}", i)).arg(&filler).query(&mut con);
+        }
+
+        let exists: u8 = redis::cmd("EXISTS").arg("evicted_hash").query(&mut con)?;
+        assert!(exists == 0, "The key should have been evicted");
+        let dump: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("evicted_hash").query(&mut con)?;
+        assert_eq!(dump, untracked, "The expirations of an evicted key should be forgotten");
+        Ok(())
+    }
+
+    #[test]
+    fn test_orphaned_expirations_are_collected() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let untracked: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("orphan_hash").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("orphan_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("orphan_hash").arg("field1").arg(60).query(&mut con)?;
+        // Overwriting the hash with a string sends no notification the module follows.
+        let _: () = redis::cmd("SET").arg("orphan_hash").arg("value").query(&mut con)?;
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.gc-effort").arg(1_000_000).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.gc-effort").arg(100).query(&mut con)?;
+
+        let dump: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("orphan_hash").query(&mut con)?;
+        assert_eq!(dump, untracked, "The orphaned expiration should be forgotten");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_check() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let untracked: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("check_hash").query(&mut con)?;
+        // Keeps the orphan around for the check.
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.gc-effort").arg(0).query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("check_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("check_hash").arg("field1").arg(60).query(&mut con)?;
+        let _: () = redis::cmd("SET").arg("check_hash").arg("value").query(&mut con)?;
+
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").query(&mut con)?;
+        assert!(report["tracked"].unwrap() >= 1);
+        assert!(report["missing_keys"].unwrap() >= 1, "The orphan should be reported");
+        assert_eq!(report["repaired"], Some(0));
+
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").arg("REPAIR").query(&mut con)?;
+        assert!(report["repaired"].unwrap() >= 1, "The orphan should be repaired");
+        let dump: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("check_hash").query(&mut con)?;
+        assert_eq!(dump, untracked, "The orphaned expiration should be forgotten");
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.gc-effort").arg(100).query(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_swapdb_swaps_expirations() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("swapped_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("swapped_hash").arg("field1").arg(500).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("SWAPDB").arg(0).arg(1).query(&mut con)?;
+
+        // Database 0 now holds what was database 1.
+        let _: () = redis::cmd("HSET").arg("swapped_hash").arg("field1").arg("value2").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1000));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("swapped_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "Expirations should not apply to the keys swapped in");
+        let _: () = redis::cmd("SELECT").arg(1).query(&mut con)?;
+        let exists: u8 = redis::cmd("HEXISTS").arg("swapped_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 0, "Expirations should follow the keys swapped out");
+        Ok(())
+    }
+
+    #[test]
+    fn test_colliding_key_member_pairs() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        // ("collide_ab", "c") and ("collide_a", "bc") concatenate to the same string.
+        let _: () = redis::cmd("HSET").arg("collide_ab").arg("c").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("collide_a").arg("bc").arg("value2").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("collide_ab").arg("c").arg(500).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("collide_a").arg("bc").arg(60).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1000));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("collide_ab").arg("c").query(&mut con)?;
+        assert!(exists == 0, "The first member should expire");
+        let exists: u8 = redis::cmd("HEXISTS").arg("collide_a").arg("bc").query(&mut con)?;
+        assert!(exists == 1, "The second member should not expire yet");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiration_in_other_database() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+        let mut db2 = redis::Client::open("redis://127.0.0.1:34123/2")?.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("db_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("db_hash").arg("field1").arg("value1").query(&mut db2)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("db_hash").arg("field1").arg(500).arg("ms").query(&mut db2)?;
+        std::thread::sleep(Duration::from_millis(1000));
+
+        let exists: u8 = redis::cmd("HEXISTS").arg("db_hash").arg("field1").query(&mut db2)?;
+        assert!(exists == 0, "The member should expire in the database it was scheduled in");
+        let exists: u8 = redis::cmd("HEXISTS").arg("db_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "The same member of database 0 should be left alone");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_type() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("SET").arg("wrongtype_string").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("XADD").arg("wrongtype_stream").arg("*").arg("field").arg("value").query(&mut con)?;
+
+        for key in ["wrongtype_string", "wrongtype_stream"] {
+            let result: RedisResult<i64> = redis::cmd("EXPIREMEMBER").arg(key).arg("member").arg(60).query(&mut con);
+            let err = result.expect_err("EXPIREMEMBER should reject the key type");
+            assert_eq!(err.code(), Some("WRONGTYPE"));
+            let result: RedisResult<i64> = redis::cmd("EXPIREMEMBERAT").arg(key).arg("member").arg(4102444800u64).query(&mut con);
+            assert_eq!(result.expect_err("EXPIREMEMBERAT should reject the key type").code(), Some("WRONGTYPE"));
+        }
+
+        let dump: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("wrongtype_string").query(&mut con)?;
+        let empty: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("wrongtype_untracked").query(&mut con)?;
+        assert_eq!(dump, empty, "Nothing should be tracked for a key of the wrong type");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_in_transaction_and_script() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("multi_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("MULTI").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("multi_hash").arg("field1").arg(1000).arg("ms").query(&mut con)?;
+        // The time spent queued does not count.
+        std::thread::sleep(Duration::from_millis(700));
+        let _: () = redis::cmd("EXEC").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(600));
+        let exists: u8 = redis::cmd("HEXISTS").arg("multi_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 1, "The expiration should count from EXEC");
+        std::thread::sleep(Duration::from_millis(1000));
+        let exists: u8 = redis::cmd("HEXISTS").arg("multi_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 0, "The member should expire after EXEC");
+
+        let _: () = redis::cmd("HSET").arg("script_hash").arg("field1").arg("value1").query(&mut con)?;
+        let scheduled: i64 = redis::cmd("EVAL")
+            .arg("return redis.call('EXPIREMEMBER', KEYS[1], ARGV[1], 500, 'ms')")
+            .arg(1).arg("script_hash").arg("field1")
+            .query(&mut con)?;
+        assert_eq!(scheduled, 1);
+        std::thread::sleep(Duration::from_millis(1000));
+        let exists: u8 = redis::cmd("HEXISTS").arg("script_hash").arg("field1").query(&mut con)?;
+        assert!(exists == 0, "The member should expire when scheduled from a script");
+
+        let result: RedisResult<()> = redis::cmd("EVAL")
+            .arg("return redis.call('EXPIREMEMBER.EXPORT', '/tmp/expiremember-script.snapshot')")
+            .arg(0)
+            .query(&mut con);
+        assert!(result.is_err(), "EXPIREMEMBER.EXPORT should not be allowed in scripts");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_acl_categories() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let write: Vec<String> = redis::cmd("ACL").arg("CAT").arg("write").query(&mut con)?;
+        assert!(write.iter().any(|command| command == "expiremember"), "EXPIREMEMBER should be in @write");
+        assert!(write.iter().any(|command| command == "expirememberat"), "EXPIREMEMBERAT should be in @write");
+
+        // Module categories need Redis 7.4.
+        let categories: Vec<String> = redis::cmd("ACL").arg("CAT").query(&mut con)?;
+        if categories.iter().any(|category| category == "expiremember") {
+            let commands: Vec<String> = redis::cmd("ACL").arg("CAT").arg("expiremember").query(&mut con)?;
+            for command in ["expiremember", "expirememberat", "expiremember.sync", "expiremember.check"] {
+                assert!(commands.iter().any(|listed| listed == command), "{} should be in @expiremember", command);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_docs() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        // Command metadata needs Redis 7.0.
+        let info: redis::Value = redis::cmd("COMMAND").arg("INFO").arg("expiremember").query(&mut con)?;
+        let redis::Value::Bulk(info) = info else { panic!("unexpected COMMAND INFO reply {:?}", info) };
+        let Some(redis::Value::Bulk(info)) = info.first() else { panic!("EXPIREMEMBER should be listed") };
+        if info.len() < 10 {
+            return Ok(());
+        }
+        assert_eq!(info.get(1), Some(&redis::Value::Int(-4)), "EXPIREMEMBER should take at least 3 arguments");
+
+        let docs: redis::Value = redis::cmd("COMMAND").arg("DOCS").arg("expiremember").query(&mut con)?;
+        let docs = format!("{:?}", docs);
+        for word in ["summary", "since", "arguments", "key", "member", "ttl", "unit", "ms"] {
+            assert!(docs.contains(&format!("\"{}\"", word)), "COMMAND DOCS should mention {}: {}", word, docs);
+        }
+
+        let error = redis::cmd("EXPIREMEMBER").arg("key").arg("member").query::<i64>(&mut con).unwrap_err();
+        assert!(error.to_string().contains("wrong number of arguments"), "unexpected error {}", error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule_burst_beyond_queue() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        // Far more schedules in one go than the worker drains the queue at.
+        let script = r#"
+            for i = 1, 30000 do
+                redis.call('HSET', KEYS[1], 'field' .. i, 'value')
+                redis.call('EXPIREMEMBER', KEYS[1], 'field' .. i, 500, 'ms')
+            end
+        "#;
+        let _: () = redis::cmd("EVAL").arg(script).arg(1).arg("burst_hash").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1500));
+
+        let remaining: i64 = redis::cmd("HLEN").arg("burst_hash").query(&mut con)?;
+        assert_eq!(remaining, 0, "Every member of the burst should expire");
+
+        Ok(())
+    }
+
+    /// Starts a server in KeyDB compatibility mode on a port of its own.
+    fn start_keydb_compat_server() -> RedisResult<(Server, redis::Connection)> {
+        start_server(&["--expiremember.keydb-compat", "yes"], |_| true)
+    }
+
+    #[test]
+    fn test_keydb_compat_missing_key_and_member() -> RedisResult<()> {
+        let (_server, mut con) = start_keydb_compat_server()?;
+
+        let missing_key: i64 = redis::cmd("EXPIREMEMBER").arg("compat_hash").arg("field1").arg(10).query(&mut con)?;
+        assert_eq!(missing_key, 0, "A missing key should reply 0");
+
+        let _: () = redis::cmd("HSET").arg("compat_hash").arg("field1").arg("value1").query(&mut con)?;
+        let missing_member: i64 = redis::cmd("EXPIREMEMBER").arg("compat_hash").arg("field2").arg(10).query(&mut con)?;
+        assert_eq!(missing_member, 0, "A missing member should reply 0");
+        let missing_member: i64 = redis::cmd("EXPIREMEMBERAT").arg("compat_hash").arg("field2").arg(4_000_000_000u64).query(&mut con)?;
+        assert_eq!(missing_member, 0, "A missing member should reply 0");
+        let dump: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("compat_hash").query(&mut con)?;
+        let empty: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("compat_other_hash").query(&mut con)?;
+        assert_eq!(dump, empty, "Nothing should be tracked for missing members");
+
+        let scheduled: i64 = redis::cmd("EXPIREMEMBER").arg("compat_hash").arg("field1").arg(500).arg("ms").query(&mut con)?;
+        assert_eq!(scheduled, 1);
+        std::thread::sleep(Duration::from_millis(1000));
+        let exists: u8 = redis::cmd("HEXISTS").arg("compat_hash").arg("field1").query(&mut con)?;
+        assert_eq!(exists, 0, "The member should expire");
+        Ok(())
+    }
+
+    #[test]
+    fn test_keydb_compat_units_and_past_times() -> RedisResult<()> {
+        let (_server, mut con) = start_keydb_compat_server()?;
+
+        let _: () = redis::cmd("HSET").arg("compat_hash").arg("field1").arg("v").arg("field2").arg("v").arg("field3").arg("v").arg("field4").arg("v").query(&mut con)?;
+
+        // Only the first letter of the unit counts.
+        let _: i64 = redis::cmd("EXPIREMEMBER").arg("compat_hash").arg("field1").arg(300).arg("millis").query(&mut con)?;
+        let _: i64 = redis::cmd("EXPIREMEMBER").arg("compat_hash").arg("field2").arg(60).arg("seconds").query(&mut con)?;
+
+        let deleted: i64 = redis::cmd("EXPIREMEMBER").arg("compat_hash").arg("field3").arg(0).query(&mut con)?;
+        assert_eq!(deleted, 1);
+        let now_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+        let deleted: i64 = redis::cmd("PEXPIREMEMBERAT").arg("compat_hash").arg("field4").arg(now_ms - 1000).query(&mut con)?;
+        assert_eq!(deleted, 1);
+
+        std::thread::sleep(Duration::from_millis(800));
+        let fields: Vec<String> = redis::cmd("HKEYS").arg("compat_hash").query(&mut con)?;
+        assert_eq!(fields, vec!["field2".to_string()], "Only the member expiring in a minute should be left");
+        Ok(())
+    }
+
+    #[test]
+    fn test_keydb_compat_errors() -> RedisResult<()> {
+        let (_server, mut con) = start_keydb_compat_server()?;
+
+        let _: () = redis::cmd("HSET").arg("compat_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("SET").arg("compat_string").arg("value").query(&mut con)?;
+
+        let errors = [
+            (redis::cmd("EXPIREMEMBER").arg("compat_string").arg("field1").arg(10).clone(), "object type is unsupported"),
+            (redis::cmd("EXPIREMEMBER").arg("compat_hash").arg("field1").arg(10).arg("hours").clone(), "Invalid unit arg"),
+            (redis::cmd("EXPIREMEMBER").arg("compat_hash").arg("field1").arg(10).arg("s").arg("extra").clone(), "Invalid number of arguments"),
+            (redis::cmd("EXPIREMEMBER").arg("compat_hash").arg("field1").arg("soon").clone(), "value is not an integer or out of range"),
+            (redis::cmd("EXPIREMEMBERAT").arg("compat_hash").arg("field1").arg("soon").clone(), "value is not an integer or out of range"),
+            (redis::cmd("EXPIREMEMBERAT").arg("compat_hash").arg("field1").arg(i64::MAX).clone(), "invalid expire time"),
+        ];
+        for (command, message) in errors {
+            let error = command.query::<i64>(&mut con).unwrap_err();
+            assert!(error.to_string().contains(message), "expected {}, got {}", message, error);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_ttl_wakes_worker() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("short_ttl_hash").arg("field1").arg("value1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("short_ttl_hash").arg("field1").arg(20).arg("ms").query(&mut con)?;
+
+        // Well before a full 100ms cycle past the deadline.
+        std::thread::sleep(Duration::from_millis(70));
+        let exists: u8 = redis::cmd("HEXISTS").arg("short_ttl_hash").arg("field1").query(&mut con)?;
+        assert_eq!(exists, 0, "A short expiration should not wait for the next cycle");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_sleeps_until_next_deadline() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.max-sleep", "60000"], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("sleep_hash").arg("field1").arg("value1").arg("field2").arg("value2").query(&mut con)?;
+        // The worker goes to sleep with only a far deadline in its heap.
+        let _: () = redis::cmd("EXPIREMEMBER").arg("sleep_hash").arg("field1").arg(3600).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(200));
+
+        let _: () = redis::cmd("EXPIREMEMBER").arg("sleep_hash").arg("field2").arg(300).arg("ms").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(600));
+        let fields: Vec<String> = redis::cmd("HKEYS").arg("sleep_hash").query(&mut con)?;
+        assert_eq!(fields, vec!["field1".to_string()], "The nearer deadline should wake the worker");
+        Ok(())
+    }
+
+    #[test]
+    fn test_timer_wheel_scheduler() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.scheduler", "wheel"], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("wheel_hash").arg("field1").arg("v").arg("field2").arg("v").arg("field3").arg("v").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("wheel_hash").arg("field1").arg(200).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("wheel_hash").arg("field2").arg(5000).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("wheel_hash").arg("field3").arg(3600).query(&mut con)?;
+        // Rescheduled earlier, the old entry is skipped.
+        let _: () = redis::cmd("EXPIREMEMBER").arg("wheel_hash").arg("field2").arg(400).arg("ms").query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(100));
+        let fields: u8 = redis::cmd("HLEN").arg("wheel_hash").query(&mut con)?;
+        assert_eq!(fields, 3, "No member should expire before its deadline");
+
+        std::thread::sleep(Duration::from_millis(700));
+        let fields: Vec<String> = redis::cmd("HKEYS").arg("wheel_hash").query(&mut con)?;
+        assert_eq!(fields, vec!["field3".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_clients_across_shards() -> RedisResult<()> {
+        let (server, mut con) = start_server(&[], |_| true)?;
+
+        let (clients, keys) = (8, 100);
+        let url = server.url();
+        let handles: Vec<_> = (0..clients).map(|client| {
+            let url = url.clone();
+            std::thread::spawn(move || -> RedisResult<()> {
+                let mut con = redis::Client::open(url)?.get_connection()?;
+                for key in 0..keys {
+                    let key = format!("shard_hash_{}_{}", client, key);
+                    let _: () = redis::cmd("HSET").arg(&key).arg("short").arg("v").arg("long").arg("v").query(&mut con)?;
+                    let _: () = redis::cmd("EXPIREMEMBER").arg(&key).arg("short").arg(300).arg("ms").query(&mut con)?;
+                    let _: () = redis::cmd("EXPIREMEMBER").arg(&key).arg("long").arg(3600).query(&mut con)?;
+                }
+                Ok(())
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").query(&mut con)?;
+        assert_eq!(report["tracked"], Some(2 * clients * keys));
+        assert_eq!(report["index_errors"], Some(0));
+        assert_eq!(report["unscheduled"], Some(0));
+
+        std::thread::sleep(Duration::from_millis(800));
+        for client in 0..clients {
+            for key in 0..keys {
+                let fields: Vec<String> = redis::cmd("HKEYS").arg(format!("shard_hash_{}_{}", client, key)).query(&mut con)?;
+                assert_eq!(fields, vec!["long".to_string()]);
+            }
+        }
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").query(&mut con)?;
+        assert_eq!(report["tracked"], Some(clients * keys));
+        Ok(())
+    }
+
+    #[test]
+    fn test_burst_skips_rescheduled_members() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let fields: Vec<String> = (0..1000).map(|field| format!("field{}", field)).collect();
+        let mut hset = redis::cmd("HSET");
+        hset.arg("stale_burst_hash");
+        for field in &fields {
+            hset.arg(field).arg("v");
+        }
+        let _: () = hset.query(&mut con)?;
+        let mut pipe = redis::pipe();
+        for field in &fields {
+            pipe.cmd("EXPIREMEMBER").arg("stale_burst_hash").arg(field).arg(300).arg("ms").ignore();
+        }
+        // Every other one is pushed back, leaving a stale entry due with the others.
+        for field in fields.iter().step_by(2) {
+            pipe.cmd("EXPIREMEMBER").arg("stale_burst_hash").arg(field).arg(3600).ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(800));
+        let remaining: usize = redis::cmd("HLEN").arg("stale_burst_hash").query(&mut con)?;
+        assert_eq!(remaining, 500, "Only the members still due should expire");
+        let exists: bool = redis::cmd("HEXISTS").arg("stale_burst_hash").arg("field0").query(&mut con)?;
+        assert!(exists);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipeline_beyond_drain_threshold() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.queue-drain-threshold", "100"], |_| true)?;
+
+        let fields: Vec<String> = (0..100_000).map(|field| format!("field{}", field)).collect();
+        let mut pipe = redis::pipe();
+        for chunk in fields.chunks(1000) {
+            let hset = pipe.cmd("HSET").arg("pipeline_hash");
+            for field in chunk {
+                hset.arg(field).arg("v");
+            }
+            hset.ignore();
+        }
+        for field in &fields {
+            pipe.cmd("EXPIREMEMBER").arg("pipeline_hash").arg(field).arg(1000).arg("ms").ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").query(&mut con)?;
+        assert_eq!(report["tracked"], Some(100_000));
+        assert_eq!(report["unscheduled"], Some(0), "No schedule of the pipeline should be lost");
+
+        std::thread::sleep(Duration::from_millis(2500));
+        let remaining: i64 = redis::cmd("HLEN").arg("pipeline_hash").query(&mut con)?;
+        assert_eq!(remaining, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule_compacts_refreshed_members() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let fields: Vec<String> = (0..2000).map(|field| format!("field{}", field)).collect();
+        let mut hset = redis::cmd("HSET");
+        hset.arg("heartbeat_hash");
+        for field in &fields {
+            hset.arg(field).arg("v");
+        }
+        let _: () = hset.query(&mut con)?;
+        // Heartbeats refreshing every member's TTL, each leaving a stale entry behind.
+        for _ in 0..5 {
+            let mut pipe = redis::pipe();
+            for field in &fields {
+                pipe.cmd("EXPIREMEMBER").arg("heartbeat_hash").arg(field).arg(30).ignore();
+            }
+            let _: () = pipe.query(&mut con)?;
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").query(&mut con)?;
+        assert_eq!(report["tracked"], Some(2000));
+        assert!(report["heap_size"].unwrap() <= 4000, "Stale entries should be compacted, heap size {:?}", report["heap_size"]);
+        assert_eq!(report["unscheduled"], Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_override_with_same_deadline() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let now_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+        let _: () = redis::cmd("HSET").arg("override_hash").arg("field1").arg("value1").query(&mut con)?;
+        for _ in 0..2 {
+            let _: () = redis::cmd("EXPIREMEMBERAT").arg("override_hash").arg("field1").arg(now_ms + 3_600_000).arg("ms").query(&mut con)?;
+        }
+
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").query(&mut con)?;
+        assert_eq!(report["heap_duplicates"], Some(0), "The overridden schedule is not a duplicate");
+        assert_eq!(report["heap_stale"], Some(1), "The overridden schedule should be stale");
+        assert_eq!(report["unscheduled"], Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_expire_in_batches() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.expire-batch", "10"], |_| true)?;
+
+        let fields: Vec<String> = (0..1000).map(|field| format!("field{}", field)).collect();
+        let mut pipe = redis::pipe();
+        for (i, field) in fields.iter().enumerate() {
+            // Spread over a few keys, some with more members than a batch.
+            let key = format!("batch_hash{}", i % 3);
+            pipe.cmd("HSET").arg(&key).arg(field).arg("v").ignore();
+            pipe.cmd("EXPIREMEMBER").arg(&key).arg(field).arg(300).arg("ms").ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(1000));
+        for key in ["batch_hash0", "batch_hash1", "batch_hash2"] {
+            let remaining: i64 = redis::cmd("HLEN").arg(key).query(&mut con)?;
+            assert_eq!(remaining, 0, "Every batch should be expired");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_expire_threads() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.expire-threads", "4", "--expiremember.expire-batch", "50"], |_| true)?;
+
+        let mut pipe = redis::pipe();
+        for i in 0..2000 {
+            let key = format!("threads_hash{}", i % 20);
+            pipe.cmd("HSET").arg(&key).arg(format!("field{}", i)).arg("v").ignore();
+            pipe.cmd("EXPIREMEMBER").arg(&key).arg(format!("field{}", i)).arg(300).arg("ms").ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(1000));
+        for key in 0..20 {
+            let remaining: i64 = redis::cmd("HLEN").arg(format!("threads_hash{}", key)).query(&mut con)?;
+            assert_eq!(remaining, 0, "Every thread's share should be expired");
+        }
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").query(&mut con)?;
+        assert_eq!(report["tracked"], Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_timer_driver() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.driver", "timer", "--expiremember.expire-batch", "100"], |_| true)?;
+
+        let mut pipe = redis::pipe();
+        for i in 0..1000 {
+            pipe.cmd("HSET").arg("timer_hash").arg(format!("field{}", i)).arg("v").ignore();
+            pipe.cmd("EXPIREMEMBER").arg("timer_hash").arg(format!("field{}", i)).arg(300).arg("ms").ignore();
+        }
+        let _: () = redis::cmd("HSET").arg("timer_hash").arg("kept").arg("v").query(&mut con)?;
+        let _: () = pipe.query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("timer_hash").arg("kept").arg(3600).query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(100));
+        let fields: i64 = redis::cmd("HLEN").arg("timer_hash").query(&mut con)?;
+        assert_eq!(fields, 1001, "No member should expire before its deadline");
+
+        // Expired over several slices of 100 members.
+        std::thread::sleep(Duration::from_millis(700));
+        let fields: Vec<String> = redis::cmd("HKEYS").arg("timer_hash").query(&mut con)?;
+        assert_eq!(fields, vec!["kept".to_string()]);
+
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").query(&mut con)?;
+        assert_eq!(report["tracked"], Some(1));
+        assert_eq!(report["unscheduled"], Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_members_of_a_key_removed_in_one_call() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let expire_at = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64 + 300;
+        let mut pipe = redis::pipe();
+        for i in 0..100 {
+            pipe.cmd("SADD").arg("variadic_set").arg(format!("member{}", i)).ignore();
+            pipe.cmd("EXPIREMEMBERAT").arg("variadic_set").arg(format!("member{}", i)).arg(expire_at).arg("ms").ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+        let _: () = redis::cmd("CONFIG").arg("RESETSTAT").query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(800));
+        let members: i64 = redis::cmd("SCARD").arg("variadic_set").query(&mut con)?;
+        assert_eq!(members, 0);
+
+        let info: String = redis::cmd("INFO").arg("commandstats").query(&mut con)?;
+        let calls: u64 = info.lines()
+            .find_map(|line| line.strip_prefix("cmdstat_srem:calls="))
+            .and_then(|stats| stats.split(',').next())
+            .and_then(|calls| calls.parse().ok())
+            .unwrap_or(0);
+        assert!((1..10).contains(&calls), "Members due together should be removed together, got {} SREM calls", calls);
+        Ok(())
+    }
+
+    #[test]
+    fn test_zset_members_removed_through_zset_api() -> RedisResult<()> {
+        let (server, mut con) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("notify-keyspace-events").arg("Kgz").query(&mut con)?;
+        let mut sub_con = redis::Client::open(server.url())?.get_connection()?;
+        let mut pubsub = sub_con.as_pubsub();
+        pubsub.subscribe("__keyspace@0__:api_zset")?;
+        pubsub.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let _: () = redis::cmd("ZADD").arg("api_zset").arg(1).arg("member1").arg(2).arg("member2").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("api_zset").arg("member1").arg(200).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("api_zset").arg("member2").arg(200).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("CONFIG").arg("RESETSTAT").query(&mut con)?;
+
+        let mut events = Vec::new();
+        while events.last().map(String::as_str) != Some("del") {
+            let event: String = pubsub.get_message()?.get_payload()?;
+            if event != "zadd" {
+                events.push(event);
+            }
+        }
+        assert!(events.iter().all(|event| event == "zrem" || event == "del"), "Unexpected events {:?}", events);
+        assert!(events.contains(&"zrem".to_string()), "Removals should be notified as zrem");
+
+        let exists: bool = redis::cmd("EXISTS").arg("api_zset").query(&mut con)?;
+        assert!(!exists, "The emptied zset should be deleted");
+        let info: String = redis::cmd("INFO").arg("commandstats").query(&mut con)?;
+        assert!(!info.contains("cmdstat_zrem:"), "No ZREM command should be called");
+        Ok(())
+    }
+
+    #[test]
+    fn test_keys_sharing_member_names() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let mut pipe = redis::pipe();
+        for key in ["shared_names_a", "shared_names_b"] {
+            for i in 0..50 {
+                pipe.cmd("HSET").arg(key).arg(format!("field{}", i)).arg("value").ignore();
+            }
+        }
+        for i in 0..50 {
+            pipe.cmd("EXPIREMEMBER").arg("shared_names_a").arg(format!("field{}", i)).arg(200).arg("ms").ignore();
+            pipe.cmd("EXPIREMEMBER").arg("shared_names_b").arg(format!("field{}", i)).arg(60).ignore();
+        }
+        pipe.cmd("EXPIREMEMBER").arg("shared_names_b").arg("field0").arg(200).arg("ms").ignore();
+        pipe.cmd("RENAME").arg("shared_names_a").arg("shared_names_c").ignore();
+        let _: () = pipe.query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(800));
+        let exists: bool = redis::cmd("EXISTS").arg("shared_names_c").query(&mut con)?;
+        assert!(!exists, "Every member of the renamed key should have expired");
+        let fields: i64 = redis::cmd("HLEN").arg("shared_names_b").query(&mut con)?;
+        assert_eq!(fields, 49, "Only the member of the other key with the same name and a short TTL should have expired");
+        let field0: bool = redis::cmd("HEXISTS").arg("shared_names_b").arg("field0").query(&mut con)?;
+        assert!(!field0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ttl_granularity() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.ttl-granularity", "1000"], |_| true)?;
+
+        let granularity: Vec<String> = redis::cmd("CONFIG").arg("GET").arg("expiremember.ttl-granularity").query(&mut con)?;
+        assert_eq!(granularity[1], "1000");
+
+        let _: () = redis::cmd("SADD").arg("coarse_set").arg("member1").arg("member2").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("coarse_set").arg("member1").arg(300).arg("ms").query(&mut con)?;
+        let scheduled = Instant::now();
+        while redis::cmd("SISMEMBER").arg("coarse_set").arg("member1").query::<bool>(&mut con)? {
+            assert!(scheduled.elapsed() < Duration::from_millis(2000), "The member should expire within a granularity of its deadline");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(scheduled.elapsed() >= Duration::from_millis(300), "The member should not expire before its deadline");
+
+        // Back to exact deadlines at runtime.
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.ttl-granularity").arg(0).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("coarse_set").arg("member2").arg(100).arg("ms").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+        let exists: bool = redis::cmd("EXISTS").arg("coarse_set").query(&mut con)?;
+        assert!(!exists);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "notifications")]
+    fn test_parked_worker_wakes_up() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.max-sleep", "60000", "--expiremember.event-log-size", "100"], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("parked_hash").arg("field1").arg("value").arg("field2").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("parked_hash").arg("field1").arg(100).arg("ms").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+        let fields: i64 = redis::cmd("HLEN").arg("parked_hash").query(&mut con)?;
+        assert_eq!(fields, 1);
+
+        // Nothing is scheduled any more, the worker is parked: a subscriber timeout and a new
+        // schedule each wake it up well before `max-sleep`.
+        let start = Instant::now();
+        let reply: Option<SubscribeReply> = redis::cmd("EXPIREMEMBER.SUBSCRIBE").arg("$").arg("BLOCK").arg(200).query(&mut con)?;
+        assert!(reply.is_none());
+        assert!(start.elapsed() < Duration::from_secs(2), "The subscriber should time out while the worker is parked");
+
+        let _: () = redis::cmd("EXPIREMEMBER").arg("parked_hash").arg("field2").arg(100).arg("ms").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+        let exists: bool = redis::cmd("EXISTS").arg("parked_hash").query(&mut con)?;
+        assert!(!exists, "A schedule should wake the parked worker");
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_entries() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.max-entries", "10"], |_| true)?;
+
+        let tracked = |con: &mut redis::Connection| -> RedisResult<u64> {
+            let payload: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("capped_set").query(con)?;
+            Ok(u64::from_le_bytes(payload[1..9].try_into().unwrap()))
+        };
+
+        for i in 0..12 {
+            let _: () = redis::cmd("SADD").arg("capped_set").arg(format!("member{}", i)).query(&mut con)?;
+        }
+        for i in 0..10 {
+            let _: () = redis::cmd("EXPIREMEMBER").arg("capped_set").arg(format!("member{}", i)).arg(100 + i).query(&mut con)?;
+        }
+
+        let rejected: RedisResult<i64> = redis::cmd("EXPIREMEMBER").arg("capped_set").arg("member10").arg(100).query(&mut con);
+        assert!(rejected.is_err(), "A new schedule beyond max-entries should be rejected");
+        let _: () = redis::cmd("EXPIREMEMBER").arg("capped_set").arg("member0").arg(50).query(&mut con)?;
+        assert_eq!(tracked(&mut con)?, 10);
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.max-entries-policy").arg("evict").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("capped_set").arg("member10").arg(200).arg("ms").query(&mut con)?;
+        assert_eq!(tracked(&mut con)?, 10, "The farthest expiration should have been evicted");
+
+        std::thread::sleep(Duration::from_millis(600));
+        let members: i64 = redis::cmd("SCARD").arg("capped_set").query(&mut con)?;
+        assert_eq!(members, 11, "Evicted expirations should leave their members in place");
+        let evicted_kept: bool = redis::cmd("SISMEMBER").arg("capped_set").arg("member9").query(&mut con)?;
+        assert!(evicted_kept);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_members_per_key() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.max-members-per-key", "2"], |_| true)?;
+
+        let _: () = redis::cmd("SADD").arg("per_key_capped").arg("member1").arg("member2").arg("member3").query(&mut con)?;
+        let _: () = redis::cmd("SADD").arg("per_key_other").arg("member1").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("per_key_capped").arg("member1").arg(60).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("per_key_capped").arg("member2").arg(60).query(&mut con)?;
+        let rejected: RedisResult<i64> = redis::cmd("EXPIREMEMBER").arg("per_key_capped").arg("member3").arg(60).query(&mut con);
+        let err = rejected.expect_err("A schedule beyond max-members-per-key should be rejected");
+        assert!(err.to_string().contains("max-members-per-key"), "Unexpected error {}", err);
+
+        // Rescheduling and other keys are not affected.
+        let _: () = redis::cmd("EXPIREMEMBER").arg("per_key_capped").arg("member2").arg(30).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("per_key_other").arg("member1").arg(60).query(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_deletions_per_second() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.max-deletions-per-second", "500"], |_| true)?;
+
+        let mut pipe = redis::pipe();
+        for i in 0..1000 {
+            pipe.cmd("HSET").arg("rate_limited_hash").arg(format!("field{}", i)).arg("value").ignore();
+        }
+        for i in 0..1000 {
+            pipe.cmd("EXPIREMEMBER").arg("rate_limited_hash").arg(format!("field{}", i)).arg(100).arg("ms").ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(600));
+        let fields: i64 = redis::cmd("HLEN").arg("rate_limited_hash").query(&mut con)?;
+        assert!(fields > 400, "At most about 500 members should be deleted in the first second, {} are left", fields);
+        assert!(fields < 1000, "Deletions should have started, {} are left", fields);
+
+        let start = Instant::now();
+        while redis::cmd("EXISTS").arg("rate_limited_hash").query::<bool>(&mut con)? {
+            assert!(start.elapsed() < Duration::from_secs(5), "Every member should eventually expire");
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_lock_percent() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.max-lock-percent", "1", "--expiremember.expire-batch", "100"], |_| true)?;
+
+        let mut pipe = redis::pipe();
+        for i in 0..20000 {
+            pipe.cmd("HSET").arg("lock_budget_hash").arg(format!("field{}", i)).arg("value").ignore();
+        }
+        for i in 0..20000 {
+            pipe.cmd("EXPIREMEMBER").arg("lock_budget_hash").arg(format!("field{}", i)).arg(100).arg("ms").ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(400));
+        let fields: i64 = redis::cmd("HLEN").arg("lock_budget_hash").query(&mut con)?;
+        assert!(fields > 0, "Deletions should be spread out under max-lock-percent");
+
+        let start = Instant::now();
+        while redis::cmd("EXISTS").arg("lock_budget_hash").query::<bool>(&mut con)? {
+            assert!(start.elapsed() < Duration::from_secs(60), "Every member should eventually expire");
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_backlog_threshold() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.backlog-threshold", "100", "--expiremember.max-deletions-per-second", "10"], |_| true)?;
+
+        let mut pipe = redis::pipe();
+        for i in 0..1000 {
+            pipe.cmd("SADD").arg("backlog_set").arg(format!("member{}", i)).ignore();
+        }
+        for i in 0..1000 {
+            pipe.cmd("EXPIREMEMBER").arg("backlog_set").arg(format!("member{}", i)).arg(50).arg("ms").ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+        let _: () = redis::cmd("SADD").arg("backlog_other").arg("member").query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(500));
+        let rejected: RedisResult<i64> = redis::cmd("EXPIREMEMBER").arg("backlog_other").arg("member").arg(60).query(&mut con);
+        let err = rejected.expect_err("New schedules should be rejected while the backlog exceeds the threshold");
+        assert_eq!(err.code(), Some("BACKLOG"));
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.max-deletions-per-second").arg(0).query(&mut con)?;
+        let start = Instant::now();
+        while redis::cmd("EXPIREMEMBER").arg("backlog_other").arg("member").arg(60).query::<i64>(&mut con).is_err() {
+            assert!(start.elapsed() < Duration::from_secs(5), "Schedules should be accepted again once the backlog is gone");
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_priority_classes() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.max-deletions-per-second", "500"], |_| true)?;
+
+        let invalid: RedisResult<()> = redis::cmd("EXPIREMEMBER").arg("priority_cache").arg("field").arg(100).arg("PRIORITY").arg("urgent").query(&mut con);
+        assert!(invalid.is_err(), "An unknown priority should be rejected");
+
+        let mut pipe = redis::pipe();
+        for i in 0..1000 {
+            pipe.cmd("HSET").arg("priority_cache").arg(format!("field{}", i)).arg("value").ignore();
+            pipe.cmd("EXPIREMEMBER").arg("priority_cache").arg(format!("field{}", i)).arg(100).arg("ms").arg("PRIORITY").arg("low").ignore();
+        }
+        for i in 0..100 {
+            pipe.cmd("HSET").arg("priority_sessions").arg(format!("field{}", i)).arg("value").ignore();
+            pipe.cmd("EXPIREMEMBER").arg("priority_sessions").arg(format!("field{}", i)).arg(100).arg("ms").arg("PRIORITY").arg("high").ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+
+        let start = Instant::now();
+        while redis::cmd("EXISTS").arg("priority_sessions").query::<bool>(&mut con)? {
+            assert!(start.elapsed() < Duration::from_secs(2), "High priority members should be deleted first");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let fields: i64 = redis::cmd("HLEN").arg("priority_cache").query(&mut con)?;
+        assert!(fields > 800, "Low priority members should wait for the high priority ones, {} are left", fields);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fair_batches_across_keys() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.max-lock-percent", "1", "--expiremember.expire-batch", "100"], |_| true)?;
+
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+        let expire_at = now + 300;
+        let mut pipe = redis::pipe();
+        for i in 0..20000 {
+            pipe.cmd("HSET").arg("fair_big_hash").arg(format!("field{}", i)).arg("value").ignore();
+            pipe.cmd("EXPIREMEMBERAT").arg("fair_big_hash").arg(format!("field{}", i)).arg(expire_at).arg("ms").ignore();
+        }
+        for key in 0..10 {
+            for i in 0..5 {
+                pipe.cmd("HSET").arg(format!("fair_small_hash{}", key)).arg(format!("field{}", i)).arg("value").ignore();
+                pipe.cmd("EXPIREMEMBERAT").arg(format!("fair_small_hash{}", key)).arg(format!("field{}", i)).arg(expire_at).arg("ms").ignore();
+            }
+        }
+        let _: () = pipe.query(&mut con)?;
+
+        let start = Instant::now();
+        loop {
+            let small: i64 = (0..10).map(|key| redis::cmd("EXISTS").arg(format!("fair_small_hash{}", key)).query::<i64>(&mut con)).sum::<RedisResult<i64>>()?;
+            if small == 0 {
+                break;
+            }
+            assert!(start.elapsed() < Duration::from_secs(3), "Small keys should not wait for the big one, {} are left", small);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let fields: i64 = redis::cmd("HLEN").arg("fair_big_hash").query(&mut con)?;
+        assert!(fields > 10000, "The big key should still be expiring, {} fields are left", fields);
+        Ok(())
+    }
+
+    #[test]
+    fn test_oom_refuses_new_expirations() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("oom_hash").arg("field1").arg("value").arg("field2").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("oom_hash").arg("field1").arg(200).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("maxmemory").arg("1").query(&mut con)?;
+
+        let err = redis::cmd("EXPIREMEMBER").arg("oom_hash").arg("field2").arg(200).arg("ms").query::<()>(&mut con).unwrap_err();
+        assert_eq!(err.code(), Some("OOM"), "New expirations should be refused over maxmemory");
+
+        std::thread::sleep(Duration::from_millis(400));
+        let fields: Vec<String> = redis::cmd("HKEYS").arg("oom_hash").query(&mut con)?;
+        assert_eq!(fields, vec!["field2"], "Due members should still be deleted over maxmemory");
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("maxmemory").arg("0").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("oom_hash").arg("field2").arg(200).arg("ms").query(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_info_section() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("info_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("info_hash").arg("field1").arg(60).query(&mut con)?;
+
+        let info: String = redis::cmd("INFO").arg("expiremember").query(&mut con)?;
+        for field in ["# expiremember_stats", "# expiremember_worker", "expiremember_tracked:", "expiremember_expired_per_sec:",
+                      "expiremember_overdue:", "expiremember_gil_wait_usec:", "expiremember_last_cycle_ms_ago:", "thread0:expired="] {
+            assert!(info.contains(field), "INFO expiremember should report {}: {}", field, info);
+        }
+        let tracked: i64 = info.lines()
+            .find_map(|line| line.strip_prefix("expiremember_tracked:"))
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0);
+        assert!(tracked >= 1, "The scheduled member should be tracked");
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_command() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+        let stats = |con: &mut redis::Connection| -> RedisResult<std::collections::HashMap<String, i64>> {
+            redis::cmd("EXPIREMEMBER.STATS").query(con)
+        };
+
+        let before = stats(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("stats_hash").arg("field1").arg("value").arg("field2").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("stats_hash").arg("field1").arg(100).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("stats_hash").arg("field2").arg(60).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("stats_hash").arg("field2").arg(120).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("stats_hash").arg("field2").arg(-1).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(400));
+        let after = stats(&mut con)?;
+
+        for (counter, at_least) in [("schedules_set", 2), ("schedules_overridden", 1), ("schedules_cancelled", 1), ("members_expired", 1)] {
+            assert!(after[counter] - before[counter] >= at_least, "{} should have grown by {} at least", counter, at_least);
+            assert!(after[&format!("{}_last_minute", counter)] >= at_least, "{} should count the last minute", counter);
+        }
+        assert!(after.contains_key("deletions_failed") && after.contains_key("queue_overflows"));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_info_command() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("info_command_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("info_command_hash").arg("field1").arg(60).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(100));
+
+        let info: std::collections::HashMap<String, redis::Value> = redis::cmd("EXPIREMEMBER.INFO").query(&mut con)?;
+        let config: std::collections::HashMap<String, String> = redis::from_redis_value(&info["config"])?;
+        assert_eq!(config.get("expiremember.expire-batch").map(String::as_str), Some("1000"));
+        let constants: std::collections::HashMap<String, i64> = redis::from_redis_value(&info["constants"])?;
+        assert_eq!(constants.get("shards"), Some(&64));
+        let runtime: std::collections::HashMap<String, redis::Value> = redis::from_redis_value(&info["runtime"])?;
+        assert_eq!(redis::from_redis_value::<i64>(&runtime["worker_started"])?, 1);
+        assert!(redis::from_redis_value::<i64>(&runtime["tracked"])? >= 1);
+        assert!(redis::from_redis_value::<i64>(&runtime["next_deadline"])? > 0, "A deadline should be scheduled");
+        Ok(())
+    }
+
+    #[test]
+    fn test_prometheus_metrics() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("HSET").arg("metrics_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("metrics_hash").arg("field1").arg(60).query(&mut con)?;
+
+        let metrics: String = redis::cmd("EXPIREMEMBER.METRICS").query(&mut con)?;
+        assert!(metrics.contains("# TYPE expiremember_tracked gauge"), "{}", metrics);
+        assert!(metrics.contains("# TYPE expiremember_schedules_set_total counter"), "{}", metrics);
+        assert!(metrics.contains("expiremember_expired_members_total{thread=\"0\"}"), "{}", metrics);
+        for line in metrics.lines().filter(|line| !line.starts_with('#')) {
+            let (name, value) = line.rsplit_once(' ').expect("Samples should be a name and a value");
+            assert!(name.starts_with("expiremember_"), "Unexpected metric {}", name);
+            assert!(value.parse::<f64>().is_ok(), "Unexpected value in {}", line);
+        }
+        let tracked: f64 = metrics.lines()
+            .find_map(|line| line.strip_prefix("expiremember_tracked "))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0.0);
+        assert!(tracked >= 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_statsd_emitter() -> RedisResult<()> {
+        let statsd_address = format!("127.0.0.1:{}", free_port());
+        let receiver = std::net::UdpSocket::bind(&statsd_address)?;
+        receiver.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let (_server, mut con) = start_server(&[
+            "--expiremember.statsd-address", &statsd_address,
+            "--expiremember.statsd-interval", "100",
+            "--expiremember.statsd-prefix", "test",
+        ], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("statsd_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("statsd_hash").arg("field1").arg(60).query(&mut con)?;
+
+        let mut packet = [0u8; 1500];
+        let start = Instant::now();
+        loop {
+            let (len, _) = receiver.recv_from(&mut packet)?;
+            let metrics = String::from_utf8_lossy(&packet[..len]).to_string();
+            for name in ["test.tracked:", "test.overdue:", "test.expired_per_sec:", "test.cycle_duration:"] {
+                assert!(metrics.contains(name), "StatsD packets should carry {}: {}", name, metrics);
+            }
+            if metrics.contains("test.tracked:1|g") {
+                break;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "The tracked member should be reported: {}", metrics);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_latency_monitor_events() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--latency-monitor-threshold", "1", "--expiremember.expire-batch", "1000000"], |_| true)?;
+
+        let mut pipe = redis::pipe();
+        for i in 0..200000 {
+            pipe.cmd("HSET").arg("latency_hash").arg(format!("field{}", i)).arg("value").ignore();
+            pipe.cmd("EXPIREMEMBER").arg("latency_hash").arg(format!("field{}", i)).arg(100).arg("ms").ignore();
+        }
+        let _: () = pipe.query(&mut con)?;
+
+        let start = Instant::now();
+        while redis::cmd("EXISTS").arg("latency_hash").query::<bool>(&mut con)? {
+            assert!(start.elapsed() < Duration::from_secs(30), "Every member should eventually expire");
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        let history: Vec<(i64, i64)> = redis::cmd("LATENCY").arg("HISTORY").arg("expiremember-cycle").query(&mut con)?;
+        assert!(!history.is_empty(), "Deleting 200000 members in one batch should be reported to the latency monitor");
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiry_lag_percentiles() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        for i in 0..100 {
+            let _: () = redis::cmd("HSET").arg("lag_hash").arg(format!("field{}", i)).arg("value").query(&mut con)?;
+            let _: () = redis::cmd("EXPIREMEMBER").arg("lag_hash").arg(format!("field{}", i)).arg(100).arg("ms").query(&mut con)?;
+        }
+        let start = Instant::now();
+        while redis::cmd("EXISTS").arg("lag_hash").query::<bool>(&mut con)? {
+            assert!(start.elapsed() < Duration::from_secs(10), "Every member should eventually expire");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        let info: String = redis::cmd("INFO").arg("expiremember").query(&mut con)?;
+        let field = |name: &str| -> i64 {
+            info.lines()
+                .find_map(|line| line.strip_prefix(&format!("expiremember_{}:", name)))
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or_else(|| panic!("INFO expiremember should report {}: {}", name, info))
+        };
+        let (p50, p95, p99, max) = (field("lag_p50_usec"), field("lag_p95_usec"), field("lag_p99_usec"), field("lag_max_usec"));
+        assert!(p50 <= p95 && p95 <= p99 && p99 <= max, "Percentiles should be ordered: {} {} {} {}", p50, p95, p99, max);
+        assert!(max < 5_000_000, "Members should be deleted within seconds of their deadline, not {} usec", max);
+
+        let metrics: String = redis::cmd("EXPIREMEMBER.METRICS").query(&mut con)?;
+        assert!(metrics.contains("expiremember_expiry_lag_seconds{quantile=\"0.99\"}"), "The lag should be a summary: {}", metrics);
+        assert!(metrics.contains("expiremember_expiry_lag_seconds_count 100\n"), "Every expired member should be counted: {}", metrics);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_keys() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.stats-keys", "2"], |_| true)?;
+
+        for (key, members) in [("hot_keys_a", 5), ("hot_keys_b", 3), ("hot_keys_c", 1)] {
+            for i in 0..members {
+                let _: () = redis::cmd("SADD").arg(key).arg(format!("member{}", i)).query(&mut con)?;
+                let _: () = redis::cmd("EXPIREMEMBER").arg(key).arg(format!("member{}", i)).arg(50).arg("ms").query(&mut con)?;
+            }
+            let start = Instant::now();
+            while redis::cmd("EXISTS").arg(key).query::<bool>(&mut con)? {
+                assert!(start.elapsed() < Duration::from_secs(10), "Every member should eventually expire");
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+
+        // hot_keys_a went longest without an expiration, so it is the one forgotten.
+        let keys: Vec<(String, i64, i64)> = redis::cmd("EXPIREMEMBER.STATS").arg("KEYS").query(&mut con)?;
+        assert_eq!(keys, vec![("hot_keys_b".to_string(), 0, 3), ("hot_keys_c".to_string(), 0, 1)]);
+        let keys: Vec<(String, i64, i64)> = redis::cmd("EXPIREMEMBER.STATS").arg("KEYS").arg(1).query(&mut con)?;
+        assert_eq!(keys, vec![("hot_keys_b".to_string(), 0, 3)]);
+
+        let result: RedisResult<Vec<(String, i64, i64)>> = redis::cmd("EXPIREMEMBER.STATS").arg("KEYS").arg(0).query(&mut con);
+        assert!(result.is_err(), "A count of 0 should be rejected");
+        Ok(())
+    }
+
+    #[test]
+    fn test_failures_counted_and_logged() -> RedisResult<()> {
+        let logfile = std::env::temp_dir().join("expiremember_failures.log");
+        let _ = std::fs::remove_file(&logfile);
+        let (_server, mut con) = start_server(&["--logfile", logfile.to_str().unwrap(), "--expiremember.expire-function", "no_such_function"], |_| true)?;
+
+        for i in 0..5 {
+            let _: () = redis::cmd("HSET").arg("failing_hash").arg(format!("field{}", i)).arg("value").query(&mut con)?;
+            let _: () = redis::cmd("EXPIREMEMBER").arg("failing_hash").arg(format!("field{}", i)).arg(50).arg("ms").query(&mut con)?;
+        }
+        let start = Instant::now();
+        while redis::cmd("EXISTS").arg("failing_hash").query::<bool>(&mut con)? {
+            assert!(start.elapsed() < Duration::from_secs(10), "Every member should eventually expire");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        let stats: std::collections::HashMap<String, i64> = redis::cmd("EXPIREMEMBER.STATS").query(&mut con)?;
+        assert_eq!(stats["notifications_failed"], 5, "Every failed call of the expiry function should be counted");
+        let log = std::fs::read_to_string(&logfile).unwrap_or_default();
+        assert_eq!(log.matches("Expiry function 'no_such_function' failed").count(), 1, "The failures should be logged once: {}", log);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace_expirations() -> RedisResult<()> {
+        let tracefile = std::env::temp_dir().join("expiremember_trace.log");
+        let _ = std::fs::remove_file(&tracefile);
+        let (_server, mut con) = start_server(&["--expiremember.trace-file", tracefile.to_str().unwrap()], |_| true)?;
+
+        let expire = |con: &mut redis::Connection, key: &str| -> RedisResult<()> {
+            let _: () = redis::cmd("HSET").arg(key).arg("field1").arg("value").arg("field2").arg("value").query(con)?;
+            let _: () = redis::cmd("EXPIREMEMBER").arg(key).arg("field1").arg(50).arg("ms").query(con)?;
+            let _: () = redis::cmd("EXPIREMEMBER").arg(key).arg("field2").arg(50).arg("ms").query(con)?;
+            let start = Instant::now();
+            while redis::cmd("EXISTS").arg(key).query::<bool>(con)? {
+                assert!(start.elapsed() < Duration::from_secs(10), "Every member should eventually expire");
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Ok(())
+        };
+
+        expire(&mut con, "untraced_hash")?;
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.trace").arg("yes").query(&mut con)?;
+        expire(&mut con, "traced_hash")?;
+
+        let trace = std::fs::read_to_string(&tracefile).unwrap_or_default();
+        assert!(!trace.contains("untraced_hash"), "Expirations before tracing was enabled should not be traced: {}", trace);
+        for member in ["field1", "field2"] {
+            assert!(trace.contains(&format!("expired db=0 key=\"traced_hash\" member=\"{}\" deadline=", member)),
+                "{} should be traced: {}", member, trace);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_watchdog_reports_stalls() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--enable-debug-command", "yes", "--expiremember.watchdog-timeout", "500", "--expiremember.max-sleep", "100"], |_| true)?;
+
+        let worker = |con: &mut redis::Connection| -> RedisResult<std::collections::HashMap<String, String>> {
+            let info: String = redis::cmd("INFO").arg("expiremember").query(con)?;
+            Ok(info.lines()
+                .filter_map(|line| line.strip_prefix("expiremember_")?.split_once(':'))
+                .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+                .collect())
+        };
+
+        let _: () = redis::cmd("HSET").arg("watchdog_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("watchdog_hash").arg("field1").arg(60).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(300));
+        let before = worker(&mut con)?;
+        assert_eq!(before["health"], "ok");
+        assert!(before["cycles"].parse::<u64>().unwrap() > 0, "Cycles should be counted");
+
+        // The worker cannot take the server lock while the server sleeps.
+        let _: () = redis::cmd("DEBUG").arg("SLEEP").arg(3).query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(1500));
+        let after = worker(&mut con)?;
+        assert_eq!(after["health"], "ok", "The cycles should be found resumed");
+        assert_eq!(after["stalls"], "1", "The stall should have been detected");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_no_worker_panics() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let stats: std::collections::HashMap<String, i64> = redis::cmd("EXPIREMEMBER.STATS").query(&mut con)?;
+        assert_eq!(stats["worker_panics"], 0, "The worker should never panic");
+        let info: std::collections::HashMap<String, redis::Value> = redis::cmd("EXPIREMEMBER.INFO").query(&mut con)?;
+        let runtime: std::collections::HashMap<String, redis::Value> = redis::from_redis_value(&info["runtime"])?;
+        assert_eq!(runtime["last_panic"], redis::Value::Nil, "No panic should be reported");
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_command() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        for (key, members) in [("memory_small", 2), ("memory_large", 50)] {
+            for i in 0..members {
+                let _: () = redis::cmd("HSET").arg(key).arg(format!("field{}", i)).arg("value").query(&mut con)?;
+                let _: () = redis::cmd("EXPIREMEMBER").arg(key).arg(format!("field{}", i)).arg(60).query(&mut con)?;
+            }
+        }
+
+        let memory: std::collections::HashMap<String, i64> = redis::cmd("EXPIREMEMBER.MEMORY").query(&mut con)?;
+        assert_eq!(memory["tracked"], 52);
+        assert_eq!(memory["keys"], 2);
+        let parts: i64 = ["tracking_map_bytes", "key_index_bytes", "names_bytes", "shards_bytes", "schedule_bytes", "queue_bytes", "event_log_bytes"]
+            .iter().map(|part| memory[*part]).sum();
+        assert_eq!(memory["total_bytes"], parts, "The total should add up the parts");
+        assert!(memory["tracking_map_bytes"] > 0 && memory["names_bytes"] > 0);
+
+        let keys: Vec<(String, i64, i64, i64)> = redis::cmd("EXPIREMEMBER.MEMORY").arg("KEYS").query(&mut con)?;
+        assert_eq!(keys.iter().map(|(key, _, members, _)| (key.as_str(), *members)).collect::<Vec<_>>(), vec![("memory_large", 50), ("memory_small", 2)]);
+        assert!(keys[0].3 > keys[1].3, "The larger key should take more bytes");
+        let keys: Vec<(String, i64, i64, i64)> = redis::cmd("EXPIREMEMBER.MEMORY").arg("KEYS").arg(1).query(&mut con)?;
+        assert_eq!(keys.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shadow_key_memory_usage() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.backend", "datatype"], |_| true)?;
+
+        let schedule = |con: &mut redis::Connection, members: std::ops::Range<i32>| -> RedisResult<()> {
+            for i in members {
+                let _: () = redis::cmd("HSET").arg("usage_hash").arg(format!("field{}", i)).arg("value").query(con)?;
+                let _: () = redis::cmd("EXPIREMEMBER").arg("usage_hash").arg(format!("field{}", i)).arg(60).query(con)?;
+            }
+            Ok(())
+        };
+
+        schedule(&mut con, 0..10)?;
+        let small: i64 = redis::cmd("MEMORY").arg("USAGE").arg("expiremember:{usage_hash}").query(&mut con)?;
+        schedule(&mut con, 10..1000)?;
+        let large: i64 = redis::cmd("MEMORY").arg("USAGE").arg("expiremember:{usage_hash}").query(&mut con)?;
+        assert!(large > small + 990 * 8, "The shadow key should count its members: {} then {}", small, large);
+        Ok(())
+    }
+
+    #[test]
+    fn test_module_argument_aliases() -> RedisResult<()> {
+        let module_args = ["interval-ms", "10", "queue-size", "1000000", "workers", "4", "max-sleep", "500"];
+        let (server, mut con) = start_server_with_module_args(&[], &module_args, |_| true)?;
+
+        for (name, expected) in [("worker-interval", "10"), ("queue-drain-threshold", "1000000"), ("expire-threads", "4"), ("max-sleep", "500")] {
+            let config: Vec<String> = redis::cmd("CONFIG").arg("GET").arg(format!("expiremember.{}", name)).query(&mut con)?;
+            assert_eq!(config[1], expected, "The module argument should set expiremember.{}", name);
+        }
+
+        drop(server);
+
+        // An unknown argument or a value out of range fails the load, and the server with it.
+        let redis_server_bin = env::var("REDIS_SERVER_BIN").unwrap_or_else(|_| "redis-server".to_string());
+        for module_args in [["interval-ms", "0"], ["no-such-setting", "1"]] {
+            let port = free_port().to_string();
+            let status = Command::new(&redis_server_bin)
+                .arg("--port")
+                .arg(&port)
+                .arg("--loadmodule")
+                .arg("target/debug/libredis_expiremember_module.so")
+                .args(module_args)
+                .status()
+                .expect("Failed to start another Redis server with the module");
+            assert!(!status.success(), "Loading the module with {:?} should fail", module_args);
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_runtime_tuning() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let runtime = |con: &mut redis::Connection| -> RedisResult<std::collections::HashMap<String, redis::Value>> {
+            let info: std::collections::HashMap<String, redis::Value> = redis::cmd("EXPIREMEMBER.INFO").query(con)?;
+            redis::from_redis_value(&info["runtime"])
+        };
+        let expire_wave = |con: &mut redis::Connection, wave: i32| -> RedisResult<()> {
+            for i in 0..100 {
+                let key = format!("tuning_hash{}_{}", wave, i % 10);
+                let _: () = redis::cmd("HSET").arg(&key).arg(format!("field{}", i)).arg("value").query(con)?;
+                let _: () = redis::cmd("EXPIREMEMBER").arg(&key).arg(format!("field{}", i)).arg(50).arg("ms").query(con)?;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+            let _: () = redis::cmd("EXPIREMEMBER.SYNC").query(con)?;
+            for i in 0..10 {
+                let len: i64 = redis::cmd("HLEN").arg(format!("tuning_hash{}_{}", wave, i)).query(con)?;
+                assert_eq!(len, 0, "Every member of wave {} should be expired", wave);
+            }
+            Ok(())
+        };
+
+        expire_wave(&mut con, 0)?;
+        for (name, value) in [("expire-threads", "4"), ("scheduler", "wheel"), ("worker-interval", "20"), ("expire-batch", "10")] {
+            let _: () = redis::cmd("CONFIG").arg("SET").arg(format!("expiremember.{}", name)).arg(value).query(&mut con)?;
+        }
+        expire_wave(&mut con, 1)?;
+        let threads: i64 = redis::from_redis_value(&runtime(&mut con)?["deletion_threads"])?;
+        assert_eq!(threads, 4, "The wave after CONFIG SET should be split among 4 threads");
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.expire-threads").arg("1").query(&mut con)?;
+        expire_wave(&mut con, 2)?;
+        let threads: i64 = redis::from_redis_value(&runtime(&mut con)?["deletion_threads"])?;
+        assert_eq!(threads, 1, "Lowering expire-threads should stop the extra threads");
+
+        let err = redis::cmd("CONFIG").arg("SET").arg("expiremember.backend").arg("zset").query::<()>(&mut con);
+        assert!(err.is_err(), "The backend should only be chosen at load time");
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_rewrite_and_checks() -> RedisResult<()> {
+        let redis_server_bin = env::var("REDIS_SERVER_BIN").unwrap_or_else(|_| "redis-server".to_string());
+        let port = free_port();
+        let path = env::temp_dir().join("expiremember_rewrite_test.conf");
+        let module = std::fs::canonicalize("target/debug/libredis_expiremember_module.so").expect("The module should be built");
+        std::fs::write(&path, format!("port {}\nloadmodule {} interval-ms 50\n", port, module.display())).expect("Failed to write the config file");
+        let start = || {
+            let server = Command::new(&redis_server_bin).arg(&path).spawn().expect("Failed to start another Redis server with the module");
+            wait_for_server(server, port, |_| true)
+        };
+        let config_get = |con: &mut redis::Connection, name: &str| -> RedisResult<String> {
+            let config: Vec<String> = redis::cmd("CONFIG").arg("GET").arg(format!("expiremember.{}", name)).query(con)?;
+            Ok(config[1].clone())
+        };
+
+        let (server, mut con) = start()?;
+        assert_eq!(config_get(&mut con, "worker-interval")?, "50");
+        for (name, value) in [("worker-interval", "20"), ("expire-batch", "250"), ("max-entries", "1000"), ("statsd-address", "localhost:8125")] {
+            let _: () = redis::cmd("CONFIG").arg("SET").arg(format!("expiremember.{}", name)).arg(value).query(&mut con)?;
+        }
+
+        for (name, value) in [("max-members-per-key", "5000"), ("statsd-address", "localhost"), ("expire-script-sha", "not-a-sha")] {
+            let err = redis::cmd("CONFIG").arg("SET").arg(format!("expiremember.{}", name)).arg(value).query::<()>(&mut con);
+            assert!(err.is_err(), "expiremember.{} {} should be refused", name, value);
+        }
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.max-members-per-key").arg("500").query(&mut con)?;
+        let _: () = redis::cmd("CONFIG").arg("REWRITE").query(&mut con)?;
+        drop(server);
+
+        // The rewritten settings win over the module argument on restart.
+        let (_server, mut con) = start()?;
+        for (name, expected) in [("worker-interval", "20"), ("expire-batch", "250"), ("max-entries", "1000"), ("max-members-per-key", "500"), ("statsd-address", "localhost:8125")] {
+            assert_eq!(config_get(&mut con, name)?, expected, "expiremember.{} should survive the restart", name);
+        }
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_configuration_profiles() -> RedisResult<()> {
+        let (_server, mut con) = start_server_with_module_args(&[], &["profile", "low_latency", "expire-threads", "4"], |_| true)?;
+
+        let config_get = |con: &mut redis::Connection, name: &str| -> RedisResult<String> {
+            let config: Vec<String> = redis::cmd("CONFIG").arg("GET").arg(format!("expiremember.{}", name)).query(con)?;
+            Ok(config[1].clone())
+        };
+
+        assert_eq!(config_get(&mut con, "profile")?, "low_latency");
+        assert_eq!(config_get(&mut con, "worker-interval")?, "10");
+        assert_eq!(config_get(&mut con, "expire-batch")?, "200");
+        assert_eq!(config_get(&mut con, "expire-threads")?, "4", "A setting given at load should win over the profile");
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.profile").arg("low_cpu").query(&mut con)?;
+        for (name, expected) in [("worker-interval", "500"), ("max-sleep", "5000"), ("expire-threads", "1"), ("max-lock-percent", "25"), ("ttl-granularity", "1000")] {
+            assert_eq!(config_get(&mut con, name)?, expected, "The low_cpu profile should set expiremember.{}", name);
+        }
+
+        // Members still expire, no earlier than their deadline.
+        let _: () = redis::cmd("HSET").arg("profile_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("profile_hash").arg("field1").arg(100).arg("ms").query(&mut con)?;
+        let exists: i64 = redis::cmd("HEXISTS").arg("profile_hash").arg("field1").query(&mut con)?;
+        assert_eq!(exists, 1);
+        std::thread::sleep(Duration::from_millis(2500));
+        let exists: i64 = redis::cmd("HEXISTS").arg("profile_hash").arg("field1").query(&mut con)?;
+        assert_eq!(exists, 0, "The member should expire under the low_cpu profile");
+
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.profile").arg("balanced").query(&mut con)?;
+        assert_eq!(config_get(&mut con, "worker-interval")?, "100");
+        assert_eq!(config_get(&mut con, "ttl-granularity")?, "0");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_command_prefix() -> RedisResult<()> {
+        let (_server, mut con) = start_server_with_module_args(&[], &["command-prefix", "em."], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("prefix_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EM.EXPIREMEMBER").arg("prefix_hash").arg("field1").arg(100).arg("ms").query(&mut con)?;
+        let err = redis::cmd("EXPIREMEMBER").arg("prefix_hash").arg("field1").arg(100).arg("ms").query::<()>(&mut con);
+        assert!(err.is_err(), "The unprefixed name should not be registered");
+
+        let info: std::collections::HashMap<String, redis::Value> = redis::cmd("EM.EXPIREMEMBER.INFO").query(&mut con)?;
+        assert!(info.contains_key("runtime"));
+        let docs: Vec<redis::Value> = redis::cmd("COMMAND").arg("DOCS").arg("em.expiremember").query(&mut con)?;
+        assert_eq!(docs.len(), 2, "The prefixed command should be documented");
+
+        std::thread::sleep(Duration::from_millis(300));
+        let exists: i64 = redis::cmd("HEXISTS").arg("prefix_hash").arg("field1").query(&mut con)?;
+        assert_eq!(exists, 0, "The member should expire");
+
+        let err = redis::cmd("CONFIG").arg("SET").arg("expiremember.command-prefix").arg("x.").query::<()>(&mut con);
+        assert!(err.is_err(), "The prefix should only be set at load time");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_track_types() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&["--expiremember.track-zsets", "no"], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("types_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("SADD").arg("types_set").arg("member1").query(&mut con)?;
+        let _: () = redis::cmd("ZADD").arg("types_zset").arg(1).arg("member1").query(&mut con)?;
+
+        let err = redis::cmd("EXPIREMEMBER").arg("types_zset").arg("member1").arg(60).query::<()>(&mut con);
+        assert!(err.is_err_and(|err| err.to_string().contains("track-zsets")), "Sorted set members should not be scheduled");
+        let err = redis::cmd("EXPIREMEMBER").arg("types_zset").arg("member1").arg(0).query::<()>(&mut con);
+        assert!(err.is_err(), "Sorted set members should not be deleted either");
+        let _: () = redis::cmd("EXPIREMEMBER").arg("types_hash").arg("field1").arg(200).arg("ms").query(&mut con)?;
+
+        // Set members scheduled before their type is disabled stay tracked, not removed.
+        let _: () = redis::cmd("EXPIREMEMBER").arg("types_set").arg("member1").arg(200).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.track-sets").arg("no").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(500));
+
+        let hash: i64 = redis::cmd("HEXISTS").arg("types_hash").arg("field1").query(&mut con)?;
+        assert_eq!(hash, 0, "Hash fields should still expire");
+        let set: i64 = redis::cmd("SISMEMBER").arg("types_set").arg("member1").query(&mut con)?;
+        assert_eq!(set, 1, "The set member should be left in place");
+        let zset: i64 = redis::cmd("ZCARD").arg("types_zset").query(&mut con)?;
+        assert_eq!(zset, 1);
+        let info: std::collections::HashMap<String, redis::Value> = redis::cmd("EXPIREMEMBER.INFO").query(&mut con)?;
+        let runtime: std::collections::HashMap<String, redis::Value> = redis::from_redis_value(&info["runtime"])?;
+        assert_eq!(redis::from_redis_value::<i64>(&runtime["tracked"])?, 1, "The set member's expiration should be kept");
+        let stats: std::collections::HashMap<String, i64> = redis::cmd("EXPIREMEMBER.STATS").query(&mut con)?;
+        assert_eq!(stats["members_expired"], 1, "Only the hash field should count as expired");
+
+        let _: () = redis::cmd("EXPIREMEMBER.DEBUG").arg("CYCLE").query(&mut con)?;
+        let set: i64 = redis::cmd("SISMEMBER").arg("types_set").arg("member1").query(&mut con)?;
+        assert_eq!(set, 1, "A cycle should leave the set member in place");
+
+        // The orphan check leaves it alone as well.
+        let report: std::collections::HashMap<String, Option<i64>> = redis::cmd("EXPIREMEMBER.CHECK").arg("REPAIR").query(&mut con)?;
+        assert_eq!(report["repaired"], Some(0), "The set member's expiration is not orphaned");
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.track-sets").arg("yes").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(300));
+        let set: i64 = redis::cmd("SISMEMBER").arg("types_set").arg("member1").query(&mut con)?;
+        assert_eq!(set, 0, "The set member should expire once its type is tracked again");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "notifications")]
+    fn test_stream_and_keyspace_signals() -> RedisResult<()> {
+        let (server, mut con) = start_server(&["--notify-keyspace-events", "Kh"], |_| true)?;
+
+        for (name, value) in [("events-stream", "expiry_stream"), ("events-include-value", "yes"), ("keyspace-event-class", "typed")] {
+            let _: () = redis::cmd("CONFIG").arg("SET").arg(format!("expiremember.{}", name)).arg(value).query(&mut con)?;
+        }
+        let mut sub_con = redis::Client::open(server.url())?.get_connection()?;
+        let mut pubsub = sub_con.as_pubsub();
+        pubsub.subscribe("__keyspace@0__:signal_hash")?;
+        pubsub.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let _: () = redis::cmd("HSET").arg("signal_hash").arg("field1").arg("value1").arg("field2").arg("value2").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("signal_hash").arg("field1").arg(100).arg("ms").query(&mut con)?;
+
+        let mut events = Vec::new();
+        while events.last().map(String::as_str) != Some("hdel") {
+            let event: String = pubsub.get_message()?.get_payload()?;
+            if event != "hset" {
+                events.push(event);
+            }
+        }
+        assert_eq!(events, ["expiremember", "hdel"], "The expiremember event should come before the removal's");
+
+        let entries: Vec<(String, std::collections::HashMap<String, String>)> = redis::cmd("XRANGE").arg("expiry_stream").arg("-").arg("+").query(&mut con)?;
+        assert_eq!(entries.len(), 1, "One entry should be added to the stream");
+        let fields = &entries[0].1;
+        assert_eq!(fields.get("key").map(String::as_str), Some("signal_hash"));
+        assert_eq!(fields.get("member").map(String::as_str), Some("field1"));
+        assert_eq!(fields.get("value").map(String::as_str), Some("value1"));
+        assert!(fields.contains_key("expired_at"));
+
+        // Turned off, no more entries.
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("expiremember.events-stream").arg("").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("signal_hash").arg("field2").arg(100).arg("ms").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(300));
+        let _: () = redis::cmd("EXPIREMEMBER.SYNC").query(&mut con)?;
+        let length: i64 = redis::cmd("XLEN").arg("expiry_stream").query(&mut con)?;
+        assert_eq!(length, 1);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "rules")]
+    fn test_rules_pathological_pattern() -> RedisResult<()> {
+        let path = env::temp_dir().join("expiremember_rules_pattern_test.conf");
+        std::fs::write(&path, "exclude *a*a*a*a*a*a*a*a*a*a*a*a*b\n").expect("Failed to write the rules file");
+        let path_arg = path.display().to_string();
+        let (_server, mut con) = start_server(&["--expiremember.config-file", &path_arg], |_| true)?;
+
+        // Backtracking into every split of the key would not end in the lifetime of the test.
+        let key = "a".repeat(200);
+        let _: () = redis::cmd("HSET").arg(&key).arg("field1").arg("value").query(&mut con)?;
+        let start = Instant::now();
+        let _: () = redis::cmd("EXPIREMEMBER").arg(&key).arg("field1").arg(60).query(&mut con)?;
+        assert!(start.elapsed() < Duration::from_secs(1), "Matching the key took {:?}", start.elapsed());
+
+        let excluded = format!("{}b", key);
+        let _: () = redis::cmd("HSET").arg(&excluded).arg("field1").arg("value").query(&mut con)?;
+        let err = redis::cmd("EXPIREMEMBER").arg(&excluded).arg("field1").arg(60).query::<()>(&mut con);
+        assert!(err.is_err_and(|err| err.to_string().contains("excluded")), "The key matching the pattern should be excluded");
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "rules")]
+    fn test_rules_file() -> RedisResult<()> {
+        let path = env::temp_dir().join("expiremember_rules_test.conf");
+        std::fs::write(&path, "# Managed in git\nexclude frozen:*\npolicy session:* clear\nset max-sleep 500\n").expect("Failed to write the rules file");
+        let path_arg = path.display().to_string();
+        let (_server, mut con) = start_server(&["--expiremember.config-file", &path_arg], |_| true)?;
+
+        let config: Vec<String> = redis::cmd("CONFIG").arg("GET").arg("expiremember.max-sleep").query(&mut con)?;
+        assert_eq!(config[1], "500");
+        let _: () = redis::cmd("HSET").arg("frozen:1").arg("field1").arg("value").query(&mut con)?;
+        let err = redis::cmd("EXPIREMEMBER").arg("frozen:1").arg("field1").arg(60).query::<()>(&mut con);
+        assert!(err.is_err_and(|err| err.to_string().contains("excluded")), "Excluded keys should refuse expirations");
+        let policy: Vec<String> = redis::cmd("EXPIREMEMBER.POLICY").arg("session:1").query(&mut con)?;
+        assert_eq!(policy, vec!["clear"]);
+
+        // A file that does not parse keeps the rules loaded.
+        std::fs::write(&path, "exclude\n").expect("Failed to write the rules file");
+        let err = redis::cmd("EXPIREMEMBER.RELOADCONF").query::<()>(&mut con);
+        assert!(err.is_err_and(|err| err.to_string().contains("line 1")));
+        let policy: Vec<String> = redis::cmd("EXPIREMEMBER.POLICY").arg("session:1").query(&mut con)?;
+        assert_eq!(policy, vec!["clear"]);
+
+        std::fs::write(&path, "policy session:* reset 10 s\n").expect("Failed to write the rules file");
+        let counts: std::collections::HashMap<String, i64> = redis::cmd("EXPIREMEMBER.RELOADCONF").query(&mut con)?;
+        assert_eq!((counts["excludes"], counts["policies"], counts["settings"]), (0, 1, 0));
+        let _: () = redis::cmd("EXPIREMEMBER").arg("frozen:1").arg("field1").arg(60).query(&mut con)?;
+
+        // Changes are picked up without the command as well.
+        std::thread::sleep(Duration::from_millis(1100));
+        std::fs::write(&path, "exclude session:*\n").expect("Failed to write the rules file");
+        std::thread::sleep(Duration::from_millis(2500));
+        let policy: Vec<String> = redis::cmd("EXPIREMEMBER.POLICY").arg("session:1").query(&mut con)?;
+        assert_eq!(policy, vec!["keep"]);
+        let _: () = redis::cmd("HSET").arg("session:1").arg("field1").arg("value").query(&mut con)?;
+        let err = redis::cmd("EXPIREMEMBER").arg("session:1").arg("field1").arg(60).query::<()>(&mut con);
+        assert!(err.is_err(), "The reloaded exclusion should apply");
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_server_detection() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let server_info: String = redis::cmd("INFO").arg("server").query(&mut con)?;
+        let valkey = server_info.lines().any(|line| line.trim() == "server_name:valkey");
+        let info: std::collections::HashMap<String, redis::Value> = redis::cmd("EXPIREMEMBER.INFO").query(&mut con)?;
+        let runtime: std::collections::HashMap<String, redis::Value> = redis::from_redis_value(&info["runtime"])?;
+        assert_eq!(redis::from_redis_value::<String>(&runtime["server"])?, if valkey { "valkey" } else { "redis" });
+        let version = redis::from_redis_value::<String>(&runtime["server_version"])?;
+        assert!(server_info.contains(&format!("_version:{}", version)), "The version should be the server's own");
+
+        // The ACL category is found under either server's name for the API.
+        let categories: Vec<String> = redis::cmd("ACL").arg("CAT").query(&mut con)?;
+        if categories.iter().any(|category| category == "expiremember") {
+            let commands: Vec<String> = redis::cmd("ACL").arg("CAT").arg("expiremember").query(&mut con)?;
+            assert!(commands.iter().any(|command| command == "expiremember"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_module_unload_is_refused() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("unload_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("unload_hash").arg("field1").arg(300).arg("ms").query(&mut con)?;
+
+        // The server keeps a module exporting data types loaded, the worker going on.
+        let unloaded = redis::cmd("MODULE").arg("UNLOAD").arg("expiremember").query::<()>(&mut con);
+        assert!(unloaded.is_err(), "The server should refuse to unload the module");
+        std::thread::sleep(Duration::from_millis(600));
+        let exists: i64 = redis::cmd("HEXISTS").arg("unload_hash").arg("field1").query(&mut con)?;
+        assert_eq!(exists, 0, "Expirations should go on after a refused unload");
+        Ok(())
+    }
+
+    #[test]
+    fn test_restored_expirations_expire_without_commands() -> RedisResult<()> {
+        let dir = env::temp_dir().join("expiremember_restore_test");
+        std::fs::create_dir_all(&dir).expect("Failed to create the data directory");
+        let dir_arg = dir.display().to_string();
+        let port = free_port();
+        let args = ["--dir", &dir_arg, "--dbfilename", "restore.rdb"];
+
+        let (server, mut con) = start_server_on(port, &args, &[], |_| true)?;
+        let _: () = redis::cmd("HSET").arg("restored_hash").arg("field1").arg("value").arg("field2").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("restored_hash").arg("field1").arg(1500).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("SAVE").query(&mut con)?;
+        drop(server);
+
+        // Nothing but plain commands is sent after the restart.
+        let (_server, mut con) = start_server_on(port, &args, &[], |_| true)?;
+        std::thread::sleep(Duration::from_millis(2000));
+        let fields: Vec<String> = redis::cmd("HKEYS").arg("restored_hash").query(&mut con)?;
+        assert_eq!(fields, vec!["field2"], "The restored expiration should have run");
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_debug_pause_resume() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("paused_hash").arg("field1").arg("value").arg("field2").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER.DEBUG").arg("PAUSE").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("paused_hash").arg("field1").arg(200).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("paused_hash").arg("field2").arg(200).arg("ms").query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(500));
+        let exists: i64 = redis::cmd("HEXISTS").arg("paused_hash").arg("field1").query(&mut con)?;
+        assert_eq!(exists, 1, "Members should not expire while paused");
+
+        // SYNC expires due members regardless.
+        let expired: i64 = redis::cmd("EXPIREMEMBER.SYNC").arg("paused_hash").query(&mut con)?;
+        assert_eq!(expired, 2);
+
+        let _: () = redis::cmd("HSET").arg("paused_hash").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("paused_hash").arg("field1").arg(200).arg("ms").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(400));
+        let _: () = redis::cmd("EXPIREMEMBER.DEBUG").arg("RESUME").query(&mut con)?;
+        std::thread::sleep(Duration::from_millis(300));
+        let exists: i64 = redis::cmd("HEXISTS").arg("paused_hash").arg("field1").query(&mut con)?;
+        assert_eq!(exists, 0, "Members due while paused should expire once resumed");
+
+        let unknown = redis::cmd("EXPIREMEMBER.DEBUG").arg("FREEZE").query::<()>(&mut con);
+        assert!(unknown.is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_debug_jumptime() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("jumped_hash").arg("field1").arg("value").arg("field2").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("jumped_hash").arg("field1").arg(60).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("jumped_hash").arg("field2").arg(3600).query(&mut con)?;
+
+        let ahead: i64 = redis::cmd("EXPIREMEMBER.DEBUG").arg("JUMPTIME").arg(61000).query(&mut con)?;
+        assert_eq!(ahead, 61000);
+        let expired: i64 = redis::cmd("EXPIREMEMBER.SYNC").arg("jumped_hash").query(&mut con)?;
+        let exists: i64 = redis::cmd("HEXISTS").arg("jumped_hash").arg("field1").query(&mut con)?;
+        assert!(expired <= 1 && exists == 0, "Members jumped over should be due at once");
+        let exists: i64 = redis::cmd("HEXISTS").arg("jumped_hash").arg("field2").query(&mut con)?;
+        assert_eq!(exists, 1, "Members not jumped over should stay");
+
+        let ahead: i64 = redis::cmd("EXPIREMEMBER.DEBUG").arg("JUMPTIME").arg(3600000).query(&mut con)?;
+        assert_eq!(ahead, 3661000);
+        std::thread::sleep(Duration::from_millis(200));
+        let exists: i64 = redis::cmd("HEXISTS").arg("jumped_hash").arg("field2").query(&mut con)?;
+        assert_eq!(exists, 0, "The worker should expire members jumped over without SYNC");
+
+        let negative = redis::cmd("EXPIREMEMBER.DEBUG").arg("JUMPTIME").arg(-1).query::<()>(&mut con);
+        assert!(negative.is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_debug_cycle() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("EXPIREMEMBER.DEBUG").arg("PAUSE").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("cycle_hash").arg("field1").arg("value").arg("field2").arg("value").arg("field3").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("cycle_hash").arg("field1").arg(10).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("cycle_hash").arg("field2").arg(10).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("cycle_hash").arg("field3").arg(3600).query(&mut con)?;
+
+        let expired: i64 = redis::cmd("EXPIREMEMBER.DEBUG").arg("CYCLE").query(&mut con)?;
+        assert_eq!(expired, 0, "Nothing should be due yet");
+
+        let _: () = redis::cmd("EXPIREMEMBER.DEBUG").arg("JUMPTIME").arg(11000).query(&mut con)?;
+        let expired: i64 = redis::cmd("EXPIREMEMBER.DEBUG").arg("CYCLE").query(&mut con)?;
+        assert_eq!(expired, 2, "A cycle should expire the due members even while paused");
+        let remaining: i64 = redis::cmd("HLEN").arg("cycle_hash").query(&mut con)?;
+        assert_eq!(remaining, 1);
+
+        let expired: i64 = redis::cmd("EXPIREMEMBER.DEBUG").arg("CYCLE").query(&mut con)?;
+        assert_eq!(expired, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_flushall() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("flush:a").arg("field1").arg("value").arg("field2").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("other:b").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("flush:a").arg("field1").arg(300).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("flush:a").arg("field2").arg(300).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("other:b").arg("field1").arg(300).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("SELECT").arg(1).query(&mut con)?;
+        let _: () = redis::cmd("HSET").arg("flush:c").arg("field1").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("flush:c").arg("field1").arg(300).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("SELECT").arg(0).query(&mut con)?;
+
+        let forgotten: i64 = redis::cmd("EXPIREMEMBER.FLUSHALL").arg("DB").arg(0).arg("MATCH").arg("flush:*").query(&mut con)?;
+        assert_eq!(forgotten, 2);
+
+        std::thread::sleep(Duration::from_millis(600));
+        let remaining: i64 = redis::cmd("HLEN").arg("flush:a").query(&mut con)?;
+        assert_eq!(remaining, 2, "Members of flushed expirations should stay");
+        let exists: i64 = redis::cmd("EXISTS").arg("other:b").query(&mut con)?;
+        assert_eq!(exists, 0, "Expirations of keys not matching should be kept");
+        let _: () = redis::cmd("SELECT").arg(1).query(&mut con)?;
+        let exists: i64 = redis::cmd("EXISTS").arg("flush:c").query(&mut con)?;
+        assert_eq!(exists, 0, "Expirations of other databases should be kept");
+
+        let _: () = redis::cmd("SELECT").arg(0).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("flush:a").arg("field1").arg(60).query(&mut con)?;
+        let forgotten: i64 = redis::cmd("EXPIREMEMBER.FLUSHALL").query(&mut con)?;
+        assert_eq!(forgotten, 1);
+
+        let invalid = redis::cmd("EXPIREMEMBER.FLUSHALL").arg("DB").arg(-1).query::<()>(&mut con);
+        assert!(invalid.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_list_functionality() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        let _: () = redis::cmd("RPUSH").arg("mylist").arg("job:1").arg("job:2").arg("job:1").arg("job:3").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("mylist").arg("job:1").arg(300).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("mylist").arg("job:3").arg(300).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("mylist").arg("job:2").arg(60).query(&mut con)?;
+
+        // Removing an element forgets its expiration, so pushing it again starts without one.
+        let _: () = redis::cmd("LREM").arg("mylist").arg(0).arg("job:3").query(&mut con)?;
+        let _: () = redis::cmd("RPUSH").arg("mylist").arg("job:3").query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(800));
+        let elements: Vec<String> = redis::cmd("LRANGE").arg("mylist").arg(0).arg(-1).query(&mut con)?;
+        assert_eq!(elements, vec!["job:2", "job:1", "job:3"], "Only the first occurrence of an expired element should be removed");
+
+        let _: () = redis::cmd("EXPIREMEMBER").arg("mylist").arg("job:2").arg(0).query(&mut con)?;
+        let elements: Vec<String> = redis::cmd("LRANGE").arg("mylist").arg(0).arg(-1).query(&mut con)?;
+        assert_eq!(elements, vec!["job:1", "job:3"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiremember_json_path() -> RedisResult<()> {
+        let client = redis::Client::open("redis://127.0.0.1:34123/")?;
+        let mut con = client.get_connection()?;
+
+        // Needs RedisJSON, as in Redis Stack or Redis 8.
+        let document = r#"{"sessions":{"abc":{"ip":"10.0.0.1"},"def":{"ip":"10.0.0.2"}}}"#;
+        if redis::cmd("JSON.SET").arg("json_doc").arg("$").arg(document).query::<()>(&mut con).is_err() {
+            return Ok(());
+        }
+        let _: () = redis::cmd("EXPIREMEMBER").arg("json_doc").arg("$.sessions.abc").arg(300).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("json_doc").arg("$.sessions.def").arg(300).arg("ms").query(&mut con)?;
+
+        // Overwriting the parent forgets the expirations of the paths below it.
+        let _: () = redis::cmd("JSON.SET").arg("json_doc").arg("$.sessions").arg(r#"{"abc":{"ip":"10.0.0.3"}}"#).query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("json_doc").arg("$.sessions.abc").arg(300).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("JSON.SET").arg("json_doc").arg("$.sessions.def").arg(r#"{"ip":"10.0.0.4"}"#).query(&mut con)?;
+
+        std::thread::sleep(Duration::from_millis(800));
+        let sessions: String = redis::cmd("JSON.GET").arg("json_doc").arg("$.sessions").query(&mut con)?;
+        assert_eq!(sessions, r#"[{"def":{"ip":"10.0.0.4"}}]"#, "Only the expired path should be deleted");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_emptied_key_counted_as_expired_only() -> RedisResult<()> {
+        let (_server, mut con) = start_server(&[], |_| true)?;
+
+        for (container, add) in [("counted_hash", "HSET"), ("counted_zset", "ZADD")] {
+            let args: &[&str] = if add == "HSET" { &["field1", "value"] } else { &["1", "field1"] };
+            let _: () = redis::cmd(add).arg(container).arg(args).query(&mut con)?;
+            let _: () = redis::cmd("EXPIREMEMBER").arg(container).arg("field1").arg(100).arg("ms").query(&mut con)?;
+        }
+        std::thread::sleep(Duration::from_millis(400));
+
+        let stats: std::collections::HashMap<String, i64> = redis::cmd("EXPIREMEMBER.STATS").query(&mut con)?;
+        assert_eq!(stats["members_expired"], 2);
+        assert_eq!(stats["schedules_cancelled"], 0, "Emptying a key by expiry should not count as cancelling");
+        Ok(())
+    }
+
+    #[test]
+    fn test_aof_rewrite_without_preamble() -> RedisResult<()> {
+        let dir = std::env::temp_dir().join("expiremember_aof_rewrite");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let args = ["--dir", dir.to_str().unwrap(), "--appendonly", "yes", "--aof-use-rdb-preamble", "no", "--enable-debug-command", "yes"];
+        let (_server, mut con) = start_server(&args, |_| true)?;
+
+        let _: () = redis::cmd("HSET").arg("aof_hash").arg("field1").arg("value").arg("field2").arg("value").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("aof_hash").arg("field1").arg(1000).arg("ms").query(&mut con)?;
+        let _: () = redis::cmd("EXPIREMEMBER").arg("aof_hash").arg("field2").arg(60).query(&mut con)?;
+
+        let _: () = redis::cmd("BGREWRITEAOF").query(&mut con)?;
+        let start = Instant::now();
+        loop {
+            let info: String = redis::cmd("INFO").arg("persistence").query(&mut con)?;
+            if info.contains("aof_rewrite_in_progress:0") && info.contains("aof_rewrite_scheduled:0") {
+                break;
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "The AOF rewrite should end");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        // Replays the rewritten AOF, which has no RDB preamble carrying the expirations.
+        let _: () = redis::cmd("DEBUG").arg("LOADAOF").query(&mut con)?;
+        let dump: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("aof_hash").query(&mut con)?;
+        let untracked: Vec<u8> = redis::cmd("EXPIREMEMBER.DUMP").arg("aof_missing").query(&mut con)?;
+        assert_ne!(dump, untracked, "The expirations should be restored from the AOF");
+
+        std::thread::sleep(Duration::from_millis(1500));
+        let expired: i64 = redis::cmd("HEXISTS").arg("aof_hash").arg("field1").query(&mut con)?;
+        assert_eq!(expired, 0, "The field should expire after the reload");
+        let kept: i64 = redis::cmd("HEXISTS").arg("aof_hash").arg("field2").query(&mut con)?;
+        assert_eq!(kept, 1);
+
+        // The carrier key the expirations were rewritten through goes with the setting.
+        let carrier: String = redis::cmd("TYPE").arg("expiremember:aof").query(&mut con)?;
+        assert_eq!(carrier, "expmember");
+        let _: () = redis::cmd("CONFIG").arg("SET").arg("aof-use-rdb-preamble").arg("yes").query(&mut con)?;
+        let exists: i64 = redis::cmd("EXISTS").arg("expiremember:aof").query(&mut con)?;
+        assert_eq!(exists, 0, "The carrier key should be deleted along with the setting");
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
 }